@@ -0,0 +1,144 @@
+use anyhow::{bail, Context, Result};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+
+/// Disguises exported data: prices are scaled by a factor and symbols
+/// replaced by pseudonyms, both derived from a secret key so the same key
+/// gives the same dataset. Returns, ratios and oscillators are unchanged by
+/// the scaling, so the data stays useful for research and demos.
+#[derive(Debug, Clone)]
+pub struct Anonymizer {
+    factor: f64,
+    /// Hex digest of the key, standing in for it in queries so the key
+    /// itself never reaches the server's logs.
+    salt: String,
+    /// Aliases by upper-cased symbol, from --symbol-map.
+    aliases: HashMap<String, String>,
+}
+
+impl Anonymizer {
+    pub fn new(key: &str, symbol_map: Option<&str>) -> Result<Self> {
+        if key.is_empty() {
+            bail!("--anonymize-key must not be empty");
+        }
+        let digest = Sha256::digest(key.as_bytes());
+        // A factor in [0.5, 2), far enough from 1 to hide the price level.
+        let bits = u64::from_be_bytes(digest[..8].try_into().unwrap());
+        let factor = 0.5 + 1.5 * (bits as f64 / u64::MAX as f64).min(0.999_999);
+        let salt = digest[8..].iter().map(|b| format!("{b:02x}")).collect();
+
+        let mut aliases = HashMap::new();
+        if let Some(path) = symbol_map {
+            let mut rdr = csv::ReaderBuilder::new()
+                .has_headers(false)
+                .from_path(path)
+                .with_context(|| format!("opening symbol map: {path}"))?;
+            for (line, record) in rdr.records().enumerate() {
+                let record = record.with_context(|| format!("reading symbol map: {path}"))?;
+                let (Some(symbol), Some(alias)) = (record.get(0), record.get(1)) else {
+                    bail!(
+                        "malformed symbol map entry on line {}: {record:?}",
+                        line + 1
+                    );
+                };
+                aliases.insert(symbol.trim().to_uppercase(), alias.trim().to_string());
+            }
+        }
+        Ok(Anonymizer {
+            factor,
+            salt,
+            aliases,
+        })
+    }
+
+    /// Alias of a symbol or per-symbol table: its --symbol-map entry, or a
+    /// pseudonym like `SYM_1a2b3c4d`.
+    pub fn alias(&self, symbol: &str) -> String {
+        let symbol = symbol.to_uppercase();
+        if let Some(alias) = self.aliases.get(&symbol) {
+            return alias.clone();
+        }
+        let digest = Sha256::digest(format!("{}{symbol}", self.salt).as_bytes());
+        let hex: String = digest[..4].iter().map(|b| format!("{b:02x}")).collect();
+        format!("SYM_{hex}")
+    }
+
+    /// SQL expression scaling a price column.
+    pub fn scale(&self, column: &str) -> String {
+        format!("{column} * {}", self.factor)
+    }
+
+    /// SQL expression giving the alias of each value of a `symbol` column,
+    /// computed the same way as [`Anonymizer::alias`].
+    pub fn symbol(&self, column: &str) -> String {
+        let pseudonym = format!(
+            "'SYM_' || left(encode(sha256(convert_to('{}' || upper({column}), 'UTF8')), 'hex'), 8)",
+            self.salt
+        );
+        if self.aliases.is_empty() {
+            return pseudonym;
+        }
+        let mut aliases: Vec<_> = self.aliases.iter().collect();
+        aliases.sort();
+        let cases: Vec<String> = aliases
+            .into_iter()
+            .map(|(symbol, alias)| {
+                format!(
+                    "when '{}' then '{}'",
+                    symbol.replace('\'', "''"),
+                    alias.replace('\'', "''")
+                )
+            })
+            .collect();
+        format!(
+            "case upper({column}) {} else {pseudonym} end",
+            cases.join(" ")
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    #[test]
+    fn alias_is_stable_and_case_insensitive() {
+        let anonymizer = Anonymizer::new("secret", None).unwrap();
+        let alias = anonymizer.alias("INFY");
+        assert!(alias.starts_with("SYM_") && alias.len() == 12, "{alias}");
+        // Per-symbol tables are lower case; their rows must get the alias
+        // the symbol gets in a shared table.
+        assert_eq!(anonymizer.alias("infy"), alias);
+        assert_eq!(
+            Anonymizer::new("secret", None).unwrap().alias("INFY"),
+            alias
+        );
+        assert_ne!(anonymizer.alias("TCS"), alias);
+    }
+
+    #[test]
+    fn alias_depends_on_the_key() {
+        let a = Anonymizer::new("secret", None).unwrap();
+        let b = Anonymizer::new("other", None).unwrap();
+        assert_ne!(a.alias("INFY"), b.alias("INFY"));
+        assert_ne!(a.factor, b.factor);
+        assert!((0.5..2.0).contains(&a.factor));
+    }
+
+    #[test]
+    fn alias_prefers_the_symbol_map() {
+        let mut map = tempfile::NamedTempFile::new().unwrap();
+        writeln!(map, "infy, Alpha").unwrap();
+        let path = map.path().to_str().unwrap();
+        let anonymizer = Anonymizer::new("secret", Some(path)).unwrap();
+        assert_eq!(anonymizer.alias("INFY"), "Alpha");
+        assert_eq!(anonymizer.alias("infy"), "Alpha");
+        assert!(anonymizer.alias("TCS").starts_with("SYM_"));
+    }
+
+    #[test]
+    fn empty_key_is_rejected() {
+        assert!(Anonymizer::new("", None).is_err());
+    }
+}
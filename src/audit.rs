@@ -0,0 +1,40 @@
+use anyhow::{Context, Result};
+use tokio_postgres::Client;
+
+use pg_nifty_dump::pipeline::Script;
+
+static AUDIT_SQL: &str = include_str!("static/audit.sql");
+
+/// Creates the `pg_nifty_dump_audit` table and trigger function, and marks
+/// this session as the loader's so its own deletes are not logged.
+pub async fn prepare(c: &Client, script: Option<&Script>) -> Result<()> {
+    let marker = "set pg_nifty_dump.loading = 'on'";
+    c.batch_execute(AUDIT_SQL)
+        .await
+        .context("creating audit table")?;
+    c.batch_execute(marker)
+        .await
+        .context("marking loader session")?;
+    if let Some(script) = script {
+        script.statement(AUDIT_SQL)?;
+        script.statement(marker)?;
+    }
+    Ok(())
+}
+
+/// Logs every later update or delete of the table's rows, with the old and
+/// new row, into `pg_nifty_dump_audit`.
+pub async fn install(c: &Client, table_name: &str, script: Option<&Script>) -> Result<()> {
+    let query = format!(
+        "drop trigger if exists pg_nifty_dump_audit on {table_name};
+         create trigger pg_nifty_dump_audit after update or delete on {table_name}
+         for each row execute function pg_nifty_dump_audit()"
+    );
+    c.batch_execute(&query)
+        .await
+        .with_context(|| format!("installing audit trigger => {table_name}"))?;
+    if let Some(script) = script {
+        script.statement(&query)?;
+    }
+    Ok(())
+}
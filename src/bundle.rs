@@ -0,0 +1,210 @@
+use anyhow::{bail, Context, Result};
+use std::fs;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::process::Stdio;
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+use tokio_postgres::types::Type;
+use tokio_postgres::Client;
+
+use crate::export::{self, Selection};
+use crate::schema;
+
+/// Single-file database a bundle is written as, chosen by its extension.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Engine {
+    DuckDb,
+    Sqlite,
+}
+
+impl Engine {
+    fn for_path(path: &Path) -> Result<Engine> {
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("duckdb" | "ddb") => Ok(Engine::DuckDb),
+            Some("sqlite" | "sqlite3" | "db") => Ok(Engine::Sqlite),
+            _ => bail!("bundle must end in .duckdb or .sqlite: {}", path.display()),
+        }
+    }
+
+    fn program(self) -> &'static str {
+        match self {
+            Engine::DuckDb => "duckdb",
+            Engine::Sqlite => "sqlite3",
+        }
+    }
+
+    /// Column type holding values of a Postgres type. SQLite keeps dates
+    /// and timestamps as ISO text.
+    fn column_type(self, ty: &Type) -> &'static str {
+        match (self, ty.name()) {
+            (Engine::DuckDb, "timestamptz") => "timestamptz",
+            (Engine::DuckDb, "timestamp") => "timestamp",
+            (Engine::DuckDb, "date") => "date",
+            (Engine::DuckDb, "int2" | "int4" | "int8") => "bigint",
+            (Engine::DuckDb, "float4" | "float8" | "numeric") => "double",
+            (Engine::DuckDb, "bool") => "boolean",
+            (Engine::DuckDb, _) => "varchar",
+            (Engine::Sqlite, "int2" | "int4" | "int8" | "bool") => "integer",
+            (Engine::Sqlite, "float4" | "float8" | "numeric") => "real",
+            (Engine::Sqlite, _) => "text",
+        }
+    }
+
+    /// Statements creating `table` and loading it from a CSV with a header.
+    fn load(self, table: &str, columns: &[(String, &str)], csv: &Path) -> String {
+        let definition: Vec<String> = columns
+            .iter()
+            .map(|(name, ty)| format!("\"{}\" {ty}", name.replace('"', "\"\"")))
+            .collect();
+        let create = format!("create table \"{table}\" ({});\n", definition.join(", "));
+        let csv = csv.display().to_string();
+        match self {
+            Engine::DuckDb => format!(
+                "{create}copy \"{table}\" from '{}' (header true);\n",
+                csv.replace('\'', "''")
+            ),
+            Engine::Sqlite => format!("{create}.import --csv --skip 1 \"{csv}\" \"{table}\"\n"),
+        }
+    }
+}
+
+/// Writes the tables into a DuckDB or SQLite file, with `symbols` and
+/// `calendar` dimension tables, for analysis without Postgres access.
+///
+/// Every table is spooled to a temporary CSV and loaded by the `duckdb` or
+/// `sqlite3` CLI, so no database library is linked in. An existing bundle
+/// is replaced.
+pub async fn write(
+    client: &Client,
+    tables: &[String],
+    selection: &Selection,
+    path: &Path,
+) -> Result<()> {
+    let engine = Engine::for_path(path)?;
+    if tables.is_empty() {
+        bail!("no managed tables to bundle");
+    }
+    let spool = tempfile::tempdir().context("creating spool directory")?;
+    let mut script = String::new();
+
+    for table in tables {
+        println!("Exporting {table} to {}...", path.display());
+        let selection = selection.for_table(client, table, true).await?;
+        let name = schema::unquoted(table);
+        let csv = spool.path().join(format!("{name}.csv"));
+        let columns = spool_query(client, &selection.select("", table), &csv)
+            .await
+            .with_context(|| format!("error exporting table: {table}"))?;
+        let columns: Vec<(String, &str)> = columns
+            .into_iter()
+            .map(|(name, ty)| (name, engine.column_type(&ty)))
+            .collect();
+        script.push_str(&engine.load(&name, &columns, &csv));
+    }
+
+    for (name, query) in [
+        ("symbols", symbols_query(client, tables, selection).await?),
+        ("calendar", calendar_query(tables)),
+    ] {
+        let csv = spool.path().join(format!("{name}.csv"));
+        let columns = spool_query(client, &query, &csv)
+            .await
+            .with_context(|| format!("error building dimension: {name}"))?;
+        let columns: Vec<(String, &str)> = columns
+            .into_iter()
+            .map(|(name, ty)| (name, engine.column_type(&ty)))
+            .collect();
+        script.push_str(&engine.load(name, &columns, &csv));
+    }
+
+    if path.exists() {
+        fs::remove_file(path).with_context(|| format!("replacing bundle: {}", path.display()))?;
+    }
+    run_script(engine, path, &script).await?;
+    println!(
+        "Wrote {} tables and the symbols and calendar dimensions to {}",
+        tables.len(),
+        path.display()
+    );
+    Ok(())
+}
+
+/// Writes the rows of `query` into `csv` with a header, returning the names
+/// and types of its columns.
+async fn spool_query(c: &Client, query: &str, csv: &Path) -> Result<Vec<(String, Type)>> {
+    let statement = c.prepare(query).await?;
+    let columns = statement
+        .columns()
+        .iter()
+        .map(|c| (c.name().to_string(), c.type_().clone()))
+        .collect();
+
+    let file = fs::File::create(csv).with_context(|| format!("creating file: {:?}", csv))?;
+    let mut writer = BufWriter::new(file);
+    let copy = format!("copy ({query}) to stdout with (format csv, header true)");
+    export::copy_out(c, &copy, &mut writer).await?;
+    writer.flush()?;
+    Ok(columns)
+}
+
+/// Each symbol with the table holding it and its date range. Shared tables
+/// contribute their distinct symbols, per-symbol tables the one their name
+/// is made of.
+async fn symbols_query(c: &Client, tables: &[String], selection: &Selection) -> Result<String> {
+    let mut parts = Vec::with_capacity(tables.len());
+    for table in tables {
+        let shared = export::is_shared(c, table).await?;
+        let literal = format!("'{}'", schema::unquoted(table).replace('\'', "''"));
+        let own = format!("'{}'", selection.symbol_of(table).replace('\'', "''"));
+        let (symbol, group) = if shared {
+            ("symbol::text", " group by symbol")
+        } else {
+            (own.as_str(), "")
+        };
+        parts.push(format!(
+            "select {symbol} as symbol, {literal}::text as table_name, min(date)::date as first_date, \
+             max(date)::date as last_date, count(*) as rows from {table}{group}"
+        ));
+    }
+    Ok(format!("{} order by 1, 2", parts.join(" union all ")))
+}
+
+/// Every trading day found in any table, with its weekday and position.
+fn calendar_query(tables: &[String]) -> String {
+    let days: Vec<String> = tables
+        .iter()
+        .map(|t| format!("select date::date as date from {t}"))
+        .collect();
+    format!(
+        "select date, extract(year from date)::int as year, extract(month from date)::int as month, \
+         extract(isodow from date)::int as weekday, row_number() over (order by date) as trading_day \
+         from ({}) days order by date",
+        days.join(" union ")
+    )
+}
+
+async fn run_script(engine: Engine, path: &Path, script: &str) -> Result<()> {
+    let program = engine.program();
+    let mut child = Command::new(program)
+        .arg(path)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .with_context(|| format!("running {program}; is it installed?"))?;
+    let mut stdin = child.stdin.take().unwrap();
+    stdin.write_all(script.as_bytes()).await?;
+    drop(stdin);
+    let output = child
+        .wait_with_output()
+        .await
+        .with_context(|| format!("running {program}"))?;
+    if !output.status.success() {
+        bail!(
+            "{program} failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,163 @@
+use anyhow::{bail, Context, Result};
+use std::fs;
+use std::io::{BufReader, Read};
+use std::path::Path;
+
+/// Archive formats recognised by their leading bytes, whatever the file is
+/// named.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    Gzip,
+    Zstd,
+    Xz,
+    Bzip2,
+    Zip,
+}
+
+impl Compression {
+    /// Extensions the format is usually stored under.
+    const EXTENSIONS: [&'static str; 5] = ["gz", "zst", "xz", "bz2", "zip"];
+
+    fn from_magic(magic: &[u8]) -> Option<Compression> {
+        if magic.starts_with(&[0x1f, 0x8b]) {
+            Some(Compression::Gzip)
+        } else if magic.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+            Some(Compression::Zstd)
+        } else if magic.starts_with(&[0xfd, b'7', b'z', b'X', b'Z', 0x00]) {
+            Some(Compression::Xz)
+        } else if magic.starts_with(b"BZh") {
+            Some(Compression::Bzip2)
+        } else if magic.starts_with(b"PK\x03\x04") {
+            Some(Compression::Zip)
+        } else {
+            None
+        }
+    }
+}
+
+/// Compression of the file, judged from its first bytes.
+pub fn sniff(path: &Path) -> Result<Option<Compression>> {
+    let mut file =
+        fs::File::open(path).with_context(|| format!("opening file: {}", path.display()))?;
+    let mut magic = [0; 6];
+    let mut len = 0;
+    while len < magic.len() {
+        match file.read(&mut magic[len..])? {
+            0 => break,
+            n => len += n,
+        }
+    }
+    Ok(Compression::from_magic(&magic[..len]))
+}
+
+/// Reader over the decompressed contents of the file. Zip archives must
+/// hold a single file, which is read into memory.
+pub fn open(path: &Path) -> Result<Box<dyn Read + Send>> {
+    let file = fs::File::open(path).with_context(|| format!("opening file: {}", path.display()))?;
+    let reader: Box<dyn Read + Send> = match sniff(path)? {
+        None => Box::new(file),
+        Some(Compression::Gzip) => {
+            Box::new(flate2::read::MultiGzDecoder::new(BufReader::new(file)))
+        }
+        #[cfg(feature = "compression")]
+        Some(Compression::Zstd) => Box::new(zstd::stream::read::Decoder::new(file)?),
+        #[cfg(feature = "compression")]
+        Some(Compression::Xz) => Box::new(xz2::read::XzDecoder::new_multi_decoder(BufReader::new(
+            file,
+        ))),
+        #[cfg(feature = "compression")]
+        Some(Compression::Bzip2) => {
+            Box::new(bzip2::read::MultiBzDecoder::new(BufReader::new(file)))
+        }
+        #[cfg(feature = "compression")]
+        Some(Compression::Zip) => {
+            let mut archive = zip::ZipArchive::new(file)
+                .with_context(|| format!("reading zip archive: {}", path.display()))?;
+            if archive.len() != 1 {
+                bail!(
+                    "zip archive must hold exactly one file, found {}: {}",
+                    archive.len(),
+                    path.display()
+                );
+            }
+            let mut entry = archive.by_index(0)?;
+            let mut contents = Vec::with_capacity(entry.size() as usize);
+            entry.read_to_end(&mut contents)?;
+            Box::new(std::io::Cursor::new(contents))
+        }
+        #[cfg(not(feature = "compression"))]
+        Some(compression) => bail!(
+            "{compression:?} input needs a build with the compression feature: {}",
+            path.display()
+        ),
+    };
+    Ok(reader)
+}
+
+/// File name without its compression suffix, e.g. `INFY.csv` for
+/// `INFY.csv.gz`.
+pub fn strip_extension(path: &Path) -> &Path {
+    let compressed = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| Compression::EXTENSIONS.contains(&e.to_lowercase().as_str()));
+    match path.file_stem() {
+        Some(stem) if compressed => Path::new(stem),
+        _ => path.file_name().map(Path::new).unwrap_or(path),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sniffed(contents: &[u8]) -> Option<Compression> {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("INFY.csv");
+        fs::write(&path, contents).unwrap();
+        sniff(&path).unwrap()
+    }
+
+    #[test]
+    fn sniff_knows_gzip() {
+        assert_eq!(sniffed(&[0x1f, 0x8b, 0x08, 0x00]), Some(Compression::Gzip));
+    }
+
+    #[test]
+    fn sniff_knows_zstd() {
+        assert_eq!(
+            sniffed(&[0x28, 0xb5, 0x2f, 0xfd, 0x24]),
+            Some(Compression::Zstd)
+        );
+    }
+
+    #[test]
+    fn sniff_knows_xz() {
+        assert_eq!(sniffed(b"\xfd7zXZ\x00\x00\x04"), Some(Compression::Xz));
+    }
+
+    #[test]
+    fn sniff_knows_bzip2() {
+        assert_eq!(sniffed(b"BZh91AY&SY"), Some(Compression::Bzip2));
+    }
+
+    #[test]
+    fn sniff_knows_zip() {
+        assert_eq!(sniffed(b"PK\x03\x04\x14\x00"), Some(Compression::Zip));
+    }
+
+    #[test]
+    fn sniff_takes_lookalike_csv_as_plain() {
+        // Each starts the way a codec does, but not all the way.
+        for contents in [
+            &b"PK,date,close\n"[..],
+            b"BZ,date,close\n",
+            b"(date),close\n",
+            b"\xfd7z,close\n",
+            b"BZ",
+            b"",
+        ] {
+            assert_eq!(sniffed(contents), None, "{contents:?}");
+        }
+    }
+}
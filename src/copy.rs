@@ -0,0 +1,182 @@
+use anyhow::{Context, Result};
+use bytes::Bytes;
+use futures::{pin_mut, SinkExt};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+use tokio_postgres::Client;
+
+/// Records buffered before they are flushed into the COPY stream.
+const CHUNK_RECORDS: usize = 10_000;
+
+/// Row data sent by this process through COPY and INSERT.
+static BYTES_SENT: AtomicU64 = AtomicU64::new(0);
+
+/// Bytes of row data sent to the database so far, as CSV or as the text
+/// of `INSERT` statements. Protocol framing and other statements are
+/// not counted.
+pub fn bytes_sent() -> u64 {
+    BYTES_SENT.load(Ordering::Relaxed)
+}
+
+async fn send_chunk<S>(sink: &mut S, chunk: Vec<u8>) -> Result<()>
+where
+    S: futures::Sink<Bytes, Error = tokio_postgres::Error> + Unpin,
+{
+    BYTES_SENT.fetch_add(chunk.len() as u64, Ordering::Relaxed);
+    sink.send(Bytes::from(chunk)).await?;
+    Ok(())
+}
+
+/// How often a client-side COPY commits. Each commit ends one COPY and
+/// starts the next, so a failure keeps the rows committed before it at the
+/// cost of more round trips and WAL flushes. Unset, a batch is one COPY.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CommitEvery {
+    pub rows: Option<u64>,
+    pub seconds: Option<u64>,
+}
+
+impl CommitEvery {
+    pub fn is_set(&self) -> bool {
+        self.rows.is_some() || self.seconds.is_some()
+    }
+}
+
+/// Client-side COPY: streams `records` as CSV through `COPY ... FROM STDIN`,
+/// for rows that have been reshaped in Rust and so cannot be read by the
+/// server from the original file.
+pub async fn copy_records<I>(
+    c: &Client,
+    table_name: &str,
+    columns: &[String],
+    records: I,
+) -> Result<u64>
+where
+    I: IntoIterator<Item = csv::ByteRecord>,
+{
+    let committed = AtomicU64::new(0);
+    copy_records_committing(
+        c,
+        table_name,
+        columns,
+        records,
+        CommitEvery::default(),
+        &committed,
+    )
+    .await
+}
+
+/// [`copy_records`] committing at the given interval. `committed` holds
+/// the rows committed so far, which survive if a later COPY fails.
+pub async fn copy_records_committing<I>(
+    c: &Client,
+    table_name: &str,
+    columns: &[String],
+    records: I,
+    every: CommitEvery,
+    committed: &AtomicU64,
+) -> Result<u64>
+where
+    I: IntoIterator<Item = csv::ByteRecord>,
+{
+    let query = format!(
+        "copy {table_name} ({}) from stdin with (format csv)",
+        columns.join(",")
+    );
+    let mut records = records.into_iter().peekable();
+    let mut total = 0;
+    while records.peek().is_some() {
+        let deadline = every
+            .seconds
+            .map(|s| Instant::now() + Duration::from_secs(s));
+        let sink = c
+            .copy_in(query.as_str())
+            .await
+            .with_context(|| format!("starting copy => {table_name}"))?;
+        pin_mut!(sink);
+
+        let mut wtr = csv::Writer::from_writer(Vec::new());
+        let mut buffered = 0;
+        for (i, record) in records.by_ref().enumerate() {
+            wtr.write_byte_record(&record)?;
+            buffered += 1;
+            if buffered == CHUNK_RECORDS {
+                let chunk = std::mem::replace(&mut wtr, csv::Writer::from_writer(Vec::new()));
+                send_chunk(&mut sink, chunk.into_inner()?).await?;
+                buffered = 0;
+            }
+            if every.rows.is_some_and(|n| i as u64 + 1 >= n)
+                || deadline.is_some_and(|d| Instant::now() >= d)
+            {
+                break;
+            }
+        }
+        if buffered > 0 {
+            send_chunk(&mut sink, wtr.into_inner()?).await?;
+        }
+
+        total += sink
+            .finish()
+            .await
+            .with_context(|| format!("finishing copy => {table_name}"))?;
+        committed.store(total, Ordering::Relaxed);
+    }
+    Ok(total)
+}
+
+/// Rows per `INSERT` statement of [`insert_records`].
+const INSERT_ROWS: usize = 1_000;
+
+/// Loads `records` with multi-row `INSERT`s of quoted literals, for targets
+/// such as foreign tables whose wrapper does not accept COPY. Empty fields
+/// become null, as with CSV COPY.
+pub async fn insert_records<I>(
+    c: &Client,
+    table_name: &str,
+    columns: &[String],
+    records: I,
+) -> Result<u64>
+where
+    I: IntoIterator<Item = csv::ByteRecord>,
+{
+    insert_records_committing(c, table_name, columns, records, &AtomicU64::new(0)).await
+}
+
+/// [`insert_records`] publishing in `committed` the rows inserted so far,
+/// each statement committing on its own.
+pub async fn insert_records_committing<I>(
+    c: &Client,
+    table_name: &str,
+    columns: &[String],
+    records: I,
+    committed: &AtomicU64,
+) -> Result<u64>
+where
+    I: IntoIterator<Item = csv::ByteRecord>,
+{
+    let prefix = format!("insert into {table_name} ({}) values ", columns.join(","));
+    let mut rows = 0;
+    let mut values: Vec<String> = Vec::with_capacity(INSERT_ROWS);
+    let mut records = records.into_iter().peekable();
+    while let Some(record) = records.next() {
+        let fields: Vec<String> = record
+            .iter()
+            .map(|f| match String::from_utf8_lossy(f).as_ref() {
+                "" => "null".to_string(),
+                f => format!("'{}'", f.replace('\'', "''")),
+            })
+            .collect();
+        values.push(format!("({})", fields.join(",")));
+        if values.len() == INSERT_ROWS || records.peek().is_none() {
+            let statement = format!("{prefix}{}", values.join(","));
+            BYTES_SENT.fetch_add(statement.len() as u64, Ordering::Relaxed);
+            rows += c
+                .execute(&statement, &[])
+                .await
+                .with_context(|| format!("inserting rows => {table_name}"))?;
+            committed.store(rows, Ordering::Relaxed);
+            values.clear();
+        }
+    }
+    Ok(rows)
+}
@@ -0,0 +1,157 @@
+use anyhow::{bail, Context, Result};
+use clap::ValueEnum;
+use std::fs;
+use std::path::Path;
+
+use crate::transform::PercentRule;
+use pg_nifty_dump::compression;
+
+/// How one data file is loaded, from a `<stem>.schema.sql` or
+/// `<stem>.schema.toml` file next to it.
+#[derive(Debug, Default)]
+pub struct Definition {
+    /// Column names and types replacing the built-in table definition;
+    /// empty to keep it.
+    pub columns: Vec<(String, String)>,
+    /// Percentage normalization of each listed column.
+    pub percent: Vec<(String, PercentRule)>,
+}
+
+/// Definition of the data file, if one sits next to it.
+///
+/// The SQL file holds a parenthesised column list like
+/// `static/table_definition.sql`, optionally as a whole `create table`
+/// statement. The TOML file maps names to types in a `[columns]` table,
+/// and columns to a [`PercentRule`] in a `[percent]` table:
+///
+/// ```toml
+/// [columns]
+/// date = "date not null"
+/// close = "numeric(12, 2)"
+///
+/// [percent]
+/// close = "percent"
+/// ```
+pub fn discover(path: &Path) -> Result<Option<Definition>> {
+    let stem = compression::strip_extension(path)
+        .file_stem()
+        .unwrap()
+        .to_string_lossy()
+        .into_owned();
+    let dir = path.parent().unwrap_or(Path::new("."));
+
+    let sql = dir.join(format!("{stem}.schema.sql"));
+    if sql.is_file() {
+        let contents = fs::read_to_string(&sql)
+            .with_context(|| format!("reading definition: {}", sql.display()))?;
+        let columns = parse_sql(&contents)
+            .with_context(|| format!("invalid definition: {}", sql.display()))?;
+        return Ok(Some(Definition {
+            columns,
+            percent: Vec::new(),
+        }));
+    }
+    let toml = dir.join(format!("{stem}.schema.toml"));
+    if toml.is_file() {
+        let contents = fs::read_to_string(&toml)
+            .with_context(|| format!("reading definition: {}", toml.display()))?;
+        return parse_toml(&contents)
+            .with_context(|| format!("invalid definition: {}", toml.display()))
+            .map(Some);
+    }
+    Ok(None)
+}
+
+fn parse_sql(contents: &str) -> Result<Vec<(String, String)>> {
+    let contents: String = contents
+        .lines()
+        .map(|l| l.split_once("--").map_or(l, |(code, _)| code))
+        .collect::<Vec<_>>()
+        .join("\n");
+    let (Some(open), Some(close)) = (contents.find('('), contents.rfind(')')) else {
+        bail!("expected a parenthesised column list");
+    };
+
+    // Split on the commas between columns, not those of `numeric(12, 2)`.
+    let mut elements = Vec::new();
+    let (mut depth, mut start) = (0, open + 1);
+    for (i, ch) in contents[..close].char_indices().filter(|(i, _)| *i > open) {
+        match ch {
+            '(' => depth += 1,
+            ')' => depth -= 1,
+            ',' if depth == 0 => {
+                elements.push(&contents[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    elements.push(&contents[start..close]);
+
+    let mut columns = Vec::new();
+    for element in elements.iter().map(|e| e.trim()).filter(|e| !e.is_empty()) {
+        let (name, data_type) = element
+            .split_once(char::is_whitespace)
+            .with_context(|| format!("column without a type: {element}"))?;
+        let keyword = name.to_lowercase();
+        if [
+            "constraint",
+            "primary",
+            "unique",
+            "check",
+            "foreign",
+            "exclude",
+            "like",
+        ]
+        .contains(&keyword.as_str())
+        {
+            bail!("table constraints are not supported: {element}");
+        }
+        columns.push((
+            name.to_string(),
+            data_type.split_whitespace().collect::<Vec<_>>().join(" "),
+        ));
+    }
+    if columns.is_empty() {
+        bail!("no columns defined");
+    }
+    Ok(columns)
+}
+
+fn parse_toml(contents: &str) -> Result<Definition> {
+    let mut definition = Definition::default();
+    let mut table = "";
+    for line in contents.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+            table = name.trim();
+            continue;
+        }
+        let (key, value) = line
+            .split_once('=')
+            .with_context(|| format!("expected `name = \"value\"`: {line}"))?;
+        let key = key.trim().trim_matches('"').to_string();
+        let Some(value) = value
+            .trim()
+            .strip_prefix('"')
+            .and_then(|v| v.strip_suffix('"'))
+        else {
+            bail!("value must be a quoted string: {line}");
+        };
+        match table {
+            "columns" => definition.columns.push((key, value.to_string())),
+            "percent" => {
+                let rule = PercentRule::from_str(value, true)
+                    .map_err(|_| anyhow::anyhow!("unknown percent rule: {line}"))?;
+                definition.percent.push((key, rule));
+            }
+            _ => {}
+        }
+    }
+    if definition.columns.is_empty() && definition.percent.is_empty() {
+        bail!("no [columns] or [percent] entries");
+    }
+    Ok(definition)
+}
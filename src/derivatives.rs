@@ -0,0 +1,102 @@
+use anyhow::{bail, Result};
+use std::collections::BTreeMap;
+
+use crate::schema::IdentifierPolicy;
+use pg_nifty_dump::pipeline::{Batch, Layout};
+
+/// NSE F&O bhavcopy headers and the derivatives table columns they load
+/// into, in table order.
+static BHAVCOPY_COLUMNS: &[(&str, &str)] = &[
+    ("TIMESTAMP", "date"),
+    ("INSTRUMENT", "instrument"),
+    ("EXPIRY_DT", "expiry"),
+    ("STRIKE_PR", "strike"),
+    ("OPTION_TYP", "option_type"),
+    ("OPEN", "open"),
+    ("HIGH", "high"),
+    ("LOW", "low"),
+    ("CLOSE", "close"),
+    ("SETTLE_PR", "settle"),
+    ("CONTRACTS", "contracts"),
+    ("VAL_INLAKH", "value_lakh"),
+    ("OPEN_INT", "oi"),
+    ("CHG_IN_OI", "oi_change"),
+];
+
+/// Splits the rows of a bhavcopy into one batch per underlying (table
+/// `<symbol>_fo`), or a single batch for `single_table` keyed by symbol.
+/// Futures carry `XX` as option type, which is stored as null.
+pub fn batches(
+    headers: &csv::StringRecord,
+    records: Vec<csv::ByteRecord>,
+    identifiers: &IdentifierPolicy,
+    single_table: Option<&str>,
+) -> Result<Vec<Batch>> {
+    let position = |name: &str| {
+        headers
+            .iter()
+            .position(|h| h.trim().eq_ignore_ascii_case(name))
+    };
+    let Some(symbol) = position("SYMBOL") else {
+        bail!("missing bhavcopy column \"SYMBOL\"");
+    };
+    let mut fields = Vec::with_capacity(BHAVCOPY_COLUMNS.len());
+    for (header, _) in BHAVCOPY_COLUMNS {
+        match position(header) {
+            Some(i) => fields.push(i),
+            None => bail!("missing bhavcopy column {header:?}"),
+        }
+    }
+    let option_type = fields[4];
+
+    let mut by_underlying: BTreeMap<String, Vec<csv::ByteRecord>> = BTreeMap::new();
+    for record in records {
+        let mut row: csv::ByteRecord = fields
+            .iter()
+            .map(|&i| {
+                let field = record.get(i).unwrap_or_default().trim_ascii();
+                if i == option_type && field == b"XX" {
+                    &b""[..]
+                } else {
+                    field
+                }
+            })
+            .collect();
+        let underlying =
+            String::from_utf8_lossy(record.get(symbol).unwrap_or_default().trim_ascii())
+                .into_owned();
+        if single_table.is_some() {
+            row.push_field(underlying.as_bytes());
+        }
+        by_underlying.entry(underlying).or_default().push(row);
+    }
+
+    let mut columns: Vec<String> = BHAVCOPY_COLUMNS
+        .iter()
+        .map(|(_, c)| c.to_string())
+        .collect();
+    if let Some(table_name) = single_table {
+        columns.push("symbol".to_string());
+        return Ok(vec![Batch {
+            table_name: table_name.to_string(),
+            columns,
+            records: by_underlying.into_values().flatten().collect(),
+            extra_columns: Vec::new(),
+            shared: true,
+            source: String::new(),
+            layout: Layout::Derivatives,
+        }]);
+    }
+    Ok(by_underlying
+        .into_iter()
+        .map(|(underlying, records)| Batch {
+            table_name: identifiers.table(&format!("{underlying}_fo")),
+            columns: columns.clone(),
+            records,
+            extra_columns: Vec::new(),
+            shared: false,
+            source: String::new(),
+            layout: Layout::Derivatives,
+        })
+        .collect())
+}
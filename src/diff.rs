@@ -0,0 +1,371 @@
+use anyhow::{bail, Context, Result};
+use clap::Args;
+use futures::{pin_mut, StreamExt};
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::PathBuf;
+use tokio_postgres::{Client, Row};
+
+use crate::tolerance::{Tolerance, Tolerances};
+use crate::{export, ConnectionArgs};
+
+/// Column types whose values are compared within a tolerance; columns of
+/// other types identify the rows.
+static NUMERIC_TYPES: &[&str] = &["double precision", "real", "bigint", "integer", "smallint"];
+
+#[derive(Debug, Args)]
+pub struct DiffSchemaArgs {
+    /// Connection string of the reference database, e.g. staging.
+    #[clap(long = "from")]
+    from: String,
+
+    /// Connection string of the database compared against it.
+    #[clap(long = "to")]
+    to: String,
+
+    /// Skip the row counts, which scan every table.
+    #[clap(long = "no-counts")]
+    no_counts: bool,
+
+    /// Also compare the numeric values of rows matched on their other
+    /// columns, such as the date and symbol.
+    #[clap(long = "values")]
+    values: bool,
+
+    /// CSV of per-column tolerances for --values (`column,absolute,relative`),
+    /// e.g. `ema*,,1e-6` or `volume,,` for an exact match. Columns without an
+    /// entry must be equal.
+    #[clap(long = "tolerances", requires = "values")]
+    tolerances: Option<PathBuf>,
+}
+
+/// What is compared of one managed table.
+#[derive(Debug)]
+struct TableInfo {
+    /// Column types by name.
+    columns: BTreeMap<String, String>,
+    rows: Option<i64>,
+    /// Latest date in UTC, comparable whatever each server's time zone.
+    max_date: Option<String>,
+}
+
+/// Compares the managed tables of two databases: tables missing on either
+/// side, column and type differences, and row count and latest date deltas.
+/// Fails when anything differs, so it can gate a deployment.
+pub async fn run(args: &DiffSchemaArgs) -> Result<()> {
+    let from = connect(&args.from).await.context("connecting to --from")?;
+    let to = connect(&args.to).await.context("connecting to --to")?;
+    let from_tables = tables(&from, !args.no_counts).await?;
+    let to_tables = tables(&to, !args.no_counts).await?;
+    let tolerances = Tolerances::load(args.tolerances.as_deref())?;
+
+    let mut differences = 0;
+    let names: BTreeSet<&String> = from_tables.keys().chain(to_tables.keys()).collect();
+    for name in names {
+        let (a, b) = match (from_tables.get(name), to_tables.get(name)) {
+            (Some(a), Some(b)) => (a, b),
+            (Some(_), None) => {
+                println!("{name}: missing in --to");
+                differences += 1;
+                continue;
+            }
+            (None, _) => {
+                println!("{name}: missing in --from");
+                differences += 1;
+                continue;
+            }
+        };
+
+        let columns: BTreeSet<&String> = a.columns.keys().chain(b.columns.keys()).collect();
+        for column in columns {
+            match (a.columns.get(column), b.columns.get(column)) {
+                (Some(x), Some(y)) if x == y => continue,
+                (Some(x), Some(y)) => println!("{name}.{column}: type {x} vs {y}"),
+                (Some(_), None) => println!("{name}.{column}: missing in --to"),
+                (None, _) => println!("{name}.{column}: missing in --from"),
+            }
+            differences += 1;
+        }
+        if a.rows != b.rows {
+            let (x, y) = (a.rows.unwrap_or(0), b.rows.unwrap_or(0));
+            println!("{name}: {x} vs {y} rows ({:+})", y - x);
+            differences += 1;
+        }
+        if a.max_date != b.max_date {
+            println!(
+                "{name}: latest date {} vs {}",
+                a.max_date.as_deref().unwrap_or("none"),
+                b.max_date.as_deref().unwrap_or("none")
+            );
+            differences += 1;
+        }
+        if args.values {
+            differences += compare_values(&from, &to, name, a, b, &tolerances).await?;
+        }
+    }
+
+    if differences > 0 {
+        bail!("{differences} differences between --from and --to");
+    }
+    println!("No differences across {} tables", from_tables.len());
+    Ok(())
+}
+
+/// Connects with `uri` alone: the service, secret and tunnel of the global
+/// options belong to the default database, not the two compared.
+async fn connect(uri: &str) -> Result<Client> {
+    let args = ConnectionArgs {
+        uri: uri.to_string(),
+        service: None,
+        secret_ref: None,
+        #[cfg(feature = "ssh")]
+        ssh_tunnel: None,
+        #[cfg(feature = "ssh")]
+        ssh_port: None,
+        #[cfg(feature = "ssh")]
+        ssh_identity: None,
+    };
+    crate::connect(&args).await
+}
+
+async fn tables(c: &Client, counts: bool) -> Result<BTreeMap<String, TableInfo>> {
+    let mut tables = BTreeMap::new();
+    for table in export::managed_tables(c).await? {
+        let rows = c
+            .query(
+                "select attname::text, format_type(atttypid, atttypmod) from pg_attribute
+                 where attrelid = $1::text::regclass and attnum > 0 and not attisdropped",
+                &[&table],
+            )
+            .await
+            .with_context(|| format!("reading columns => {table}"))?;
+        let columns = rows.iter().map(|r| (r.get(0), r.get(1))).collect();
+
+        let count = if counts { "count(*)" } else { "null::bigint" };
+        let row = c
+            .query_one(
+                &format!("select {count}, (max(date) at time zone 'UTC')::text from {table}"),
+                &[],
+            )
+            .await
+            .with_context(|| format!("summarizing table => {table}"))?;
+        tables.insert(
+            table,
+            TableInfo {
+                columns,
+                rows: row.get(0),
+                max_date: row.get(1),
+            },
+        );
+    }
+    Ok(tables)
+}
+
+/// Values of one column that differ beyond its tolerance.
+#[derive(Debug, Default)]
+struct Mismatches {
+    count: u64,
+    /// Largest difference where both sides have a value.
+    largest: f64,
+    /// Key of the first mismatching row.
+    example: Option<String>,
+}
+
+impl Mismatches {
+    /// Compares the values of a row present on both sides, counting them
+    /// when they differ beyond `tolerance` or only one side has a value.
+    fn compare(
+        &mut self,
+        tolerance: Tolerance,
+        p: Option<f64>,
+        q: Option<f64>,
+        key: impl FnOnce() -> String,
+    ) {
+        let accepted = match (p, q) {
+            (Some(p), Some(q)) => {
+                let accepted = tolerance.accepts(p, q);
+                if !accepted {
+                    self.largest = self.largest.max((p - q).abs());
+                }
+                accepted
+            }
+            (None, None) => true,
+            _ => false,
+        };
+        if !accepted {
+            self.count += 1;
+            self.example.get_or_insert_with(key);
+        }
+    }
+}
+
+/// Walks the rows of the table on both sides in key order, printing the
+/// columns with values differing beyond their tolerance and the rows found
+/// on one side only. Returns the number of differences.
+async fn compare_values(
+    from: &Client,
+    to: &Client,
+    name: &str,
+    a: &TableInfo,
+    b: &TableInfo,
+    tolerances: &Tolerances,
+) -> Result<usize> {
+    let (values, keys): (Vec<_>, Vec<_>) = a
+        .columns
+        .iter()
+        .filter(|(column, data_type)| b.columns.get(*column) == Some(data_type))
+        .partition(|(_, data_type)| {
+            NUMERIC_TYPES.contains(&data_type.as_str()) || data_type.starts_with("numeric")
+        });
+    if keys.is_empty() || values.is_empty() {
+        return Ok(0);
+    }
+    let quote = |column: &str| format!("\"{}\"", column.replace('"', "\"\""));
+    let key_exprs: Vec<String> = keys
+        .iter()
+        .map(|(column, data_type)| match data_type.as_str() {
+            "timestamp with time zone" => {
+                format!("coalesce(({} at time zone 'UTC')::text, '')", quote(column))
+            }
+            _ => format!("coalesce({}::text, '')", quote(column)),
+        })
+        .collect();
+    let value_exprs: Vec<String> = values
+        .iter()
+        .map(|(column, _)| format!("{}::float8", quote(column)))
+        .collect();
+    // Byte order, as the keys are compared here.
+    let order: Vec<String> = key_exprs
+        .iter()
+        .map(|k| format!("{k} collate \"C\""))
+        .collect();
+    let query = format!(
+        "select {}, {} from {name} order by {}",
+        key_exprs.join(", "),
+        value_exprs.join(", "),
+        order.join(", ")
+    );
+
+    let left = from
+        .query_raw(query.as_str(), std::iter::empty::<&str>())
+        .await
+        .with_context(|| format!("reading rows of --from => {name}"))?;
+    let right = to
+        .query_raw(query.as_str(), std::iter::empty::<&str>())
+        .await
+        .with_context(|| format!("reading rows of --to => {name}"))?;
+    pin_mut!(left, right);
+    let key = |row: &Row| -> Vec<String> { (0..keys.len()).map(|i| row.get(i)).collect() };
+
+    let mut mismatches: Vec<Mismatches> = values.iter().map(|_| Mismatches::default()).collect();
+    let column_tolerances: Vec<_> = values
+        .iter()
+        .map(|(column, _)| tolerances.for_column(column))
+        .collect();
+    let (mut only_from, mut only_to) = (0u64, 0u64);
+    let mut l = left.next().await.transpose()?;
+    let mut r = right.next().await.transpose()?;
+    loop {
+        let ordering = match (&l, &r) {
+            (None, None) => break,
+            (Some(_), None) => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            (Some(x), Some(y)) => key(x).cmp(&key(y)),
+        };
+        match ordering {
+            Ordering::Less => {
+                only_from += 1;
+                l = left.next().await.transpose()?;
+            }
+            Ordering::Greater => {
+                only_to += 1;
+                r = right.next().await.transpose()?;
+            }
+            Ordering::Equal => {
+                let (x, y) = (l.as_ref().unwrap(), r.as_ref().unwrap());
+                for (i, found) in mismatches.iter_mut().enumerate() {
+                    let (p, q) = (x.get(keys.len() + i), y.get(keys.len() + i));
+                    found.compare(column_tolerances[i], p, q, || key(x).join(", "));
+                }
+                l = left.next().await.transpose()?;
+                r = right.next().await.transpose()?;
+            }
+        }
+    }
+
+    let mut differences = 0;
+    for ((column, _), found) in values.iter().zip(&mismatches) {
+        if found.count == 0 {
+            continue;
+        }
+        println!(
+            "{name}.{column}: {} values differ beyond tolerance, by up to {} (first at {})",
+            found.count,
+            found.largest,
+            found.example.as_deref().unwrap_or_default()
+        );
+        differences += 1;
+    }
+    if only_from > 0 {
+        println!("{name}: {only_from} rows only in --from");
+        differences += 1;
+    }
+    if only_to > 0 {
+        println!("{name}: {only_to} rows only in --to");
+        differences += 1;
+    }
+    Ok(differences)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TOLERANCE: Tolerance = Tolerance {
+        absolute: 0.01,
+        relative: 0.0,
+    };
+
+    #[test]
+    fn values_within_tolerance_match() {
+        let mut found = Mismatches::default();
+        found.compare(TOLERANCE, Some(100.0), Some(100.005), || "a".into());
+        found.compare(TOLERANCE, None, None, || "b".into());
+        found.compare(TOLERANCE, Some(f64::NAN), Some(f64::NAN), || "c".into());
+        assert_eq!(found.count, 0);
+        assert_eq!(found.example, None);
+    }
+
+    #[test]
+    fn mismatches_keep_the_largest_and_first() {
+        let mut found = Mismatches::default();
+        found.compare(TOLERANCE, Some(100.0), Some(100.5), || "2024-01-01".into());
+        found.compare(TOLERANCE, Some(100.0), Some(102.0), || "2024-01-02".into());
+        found.compare(TOLERANCE, Some(100.0), Some(100.1), || "2024-01-03".into());
+        assert_eq!(found.count, 3);
+        assert_eq!(found.largest, 2.0);
+        assert_eq!(found.example.as_deref(), Some("2024-01-01"));
+    }
+
+    #[test]
+    fn missing_value_on_one_side_mismatches() {
+        let mut found = Mismatches::default();
+        found.compare(TOLERANCE, Some(1.0), None, || "a".into());
+        found.compare(TOLERANCE, None, Some(1.0), || "b".into());
+        assert_eq!(found.count, 2);
+        assert_eq!(found.largest, 0.0);
+    }
+
+    #[test]
+    fn relative_tolerance_scales_with_the_values() {
+        let tolerance = Tolerance {
+            absolute: 0.0,
+            relative: 0.001,
+        };
+        let mut found = Mismatches::default();
+        found.compare(tolerance, Some(10_000.0), Some(10_005.0), || "a".into());
+        found.compare(tolerance, Some(10.0), Some(10.05), || "b".into());
+        assert_eq!(found.count, 1);
+        assert_eq!(found.example.as_deref(), Some("b"));
+    }
+}
@@ -0,0 +1,202 @@
+use anyhow::{Context, Result};
+use clap::{Args, ValueEnum};
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::fs;
+use tokio_postgres::Client;
+
+use pg_nifty_dump::pipeline::Layout;
+use pg_nifty_dump::postgres;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Format {
+    Markdown,
+    Html,
+}
+
+#[derive(Debug, Args)]
+pub struct DocsArgs {
+    #[clap(long = "format", value_enum, default_value_t = Format::Markdown)]
+    format: Format,
+
+    /// File the data dictionary is written to. Defaults to stdout.
+    #[clap(short, long = "output")]
+    output: Option<String>,
+
+    /// Loaded table whose column comments override the built-in
+    /// descriptions.
+    #[clap(long = "table")]
+    table: Option<String>,
+}
+
+/// Descriptions of the columns without a window in their name.
+static DESCRIPTIONS: &[(&str, &str)] = &[
+    ("date", "Trading day (or bar timestamp for intraday data)"),
+    ("close", "Closing price"),
+    ("high", "Highest traded price"),
+    ("low", "Lowest traded price"),
+    ("open", "Opening price"),
+    ("volume", "Traded quantity"),
+    ("upperband", "Upper Bollinger band"),
+    ("middleband", "Middle Bollinger band"),
+    ("lowerband", "Lower Bollinger band"),
+    ("ht_trendline", "Hilbert transform instantaneous trendline"),
+    ("sar", "Parabolic SAR"),
+    ("apo", "Absolute price oscillator"),
+    ("mfi", "Money flow index"),
+    ("ppo", "Percentage price oscillator"),
+    ("slowk", "Slow stochastic %K"),
+    ("slowd", "Slow stochastic %D"),
+    ("fastk", "Fast stochastic %K"),
+    ("fastd", "Fast stochastic %D"),
+    ("fastksr", "Stochastic RSI %K"),
+    ("fastdsr", "Stochastic RSI %D"),
+    ("ultosc", "Ultimate oscillator"),
+    ("willr", "Williams %R"),
+    ("atr", "Average true range"),
+    ("trange", "True range"),
+    ("typprice", "Typical price, (high + low + close) / 3"),
+    ("ht_dcperiod", "Hilbert transform dominant cycle period"),
+    ("beta", "Beta against the index"),
+    ("instrument", "Contract type, e.g. FUTIDX or OPTSTK"),
+    ("expiry", "Expiry date of the contract"),
+    ("strike", "Strike price, 0 for futures"),
+    ("option_type", "CE or PE, null for futures"),
+    ("settle", "Settlement price"),
+    ("contracts", "Number of contracts traded"),
+    ("value_lakh", "Traded value in lakh rupees"),
+    ("oi", "Open interest"),
+    ("oi_change", "Change in open interest"),
+];
+
+/// Indicators whose name ends in their window length, e.g. `sma20`.
+static WINDOWED: &[(&str, &str)] = &[
+    ("sma", "Simple moving average of close"),
+    ("ema", "Exponential moving average of close"),
+    ("kama", "Kaufman adaptive moving average"),
+    ("trima", "Triangular moving average"),
+    ("adx", "Average directional index"),
+    ("cci", "Commodity channel index"),
+    ("mom", "Momentum, close minus close n days earlier"),
+    ("roc", "Rate of change in percent"),
+    ("rsi", "Relative strength index"),
+    ("macd", "MACD line, fast minus slow EMA"),
+];
+
+/// Writes a data dictionary of the table layouts: each column with its
+/// type, description and window length.
+pub async fn run(client: &Client, args: &DocsArgs) -> Result<()> {
+    let comments = match &args.table {
+        Some(table) => column_comments(client, table)
+            .await
+            .with_context(|| format!("reading column comments: {table}"))?,
+        None => HashMap::new(),
+    };
+
+    let sections = [
+        ("Indicator tables", Layout::Indicators),
+        ("Derivatives tables", Layout::Derivatives),
+    ];
+    let mut out = String::new();
+    if args.format == Format::Html {
+        out.push_str("<!DOCTYPE html>\n<html>\n<head><meta charset=\"utf-8\"><title>pg_nifty_dump data dictionary</title></head>\n<body>\n<h1>pg_nifty_dump data dictionary</h1>\n");
+    } else {
+        out.push_str("# pg_nifty_dump data dictionary\n");
+    }
+    for (title, layout) in sections {
+        let rows: Vec<[String; 4]> = postgres::table_columns(layout)
+            .into_iter()
+            .map(|(name, data_type)| {
+                let (description, window) = describe(name);
+                let description = comments
+                    .get(&name.to_lowercase())
+                    .cloned()
+                    .unwrap_or(description);
+                [name.to_string(), data_type.to_string(), description, window]
+            })
+            .collect();
+        match args.format {
+            Format::Markdown => {
+                writeln!(out, "\n## {title}\n")?;
+                writeln!(out, "| Column | Type | Description | Window |")?;
+                writeln!(out, "|---|---|---|---|")?;
+                for row in &rows {
+                    let cells: Vec<String> = row.iter().map(|c| c.replace('|', "\\|")).collect();
+                    writeln!(out, "| {} |", cells.join(" | "))?;
+                }
+            }
+            Format::Html => {
+                writeln!(out, "<h2>{}</h2>\n<table>", escape(title))?;
+                writeln!(
+                    out,
+                    "<tr><th>Column</th><th>Type</th><th>Description</th><th>Window</th></tr>"
+                )?;
+                for row in &rows {
+                    let cells: Vec<String> = row
+                        .iter()
+                        .map(|c| format!("<td>{}</td>", escape(c)))
+                        .collect();
+                    writeln!(out, "<tr>{}</tr>", cells.concat())?;
+                }
+                writeln!(out, "</table>")?;
+            }
+        }
+    }
+    if args.format == Format::Html {
+        out.push_str("</body>\n</html>\n");
+    }
+
+    match &args.output {
+        Some(path) => {
+            fs::write(path, out).with_context(|| format!("writing file: {path}"))?;
+            println!("Wrote data dictionary to {path}");
+        }
+        None => print!("{out}"),
+    }
+    Ok(())
+}
+
+/// Description and window length of a column; windows are in days, with
+/// MACD's fast and slow windows run together in the name (`macd1226`).
+fn describe(column: &str) -> (String, String) {
+    let lower = column.to_lowercase();
+    if let Some((_, description)) = DESCRIPTIONS.iter().find(|(c, _)| *c == lower) {
+        return (description.to_string(), String::new());
+    }
+    for (prefix, description) in WINDOWED {
+        let Some(digits) = lower.strip_prefix(prefix) else {
+            continue;
+        };
+        if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+            continue;
+        }
+        let window = match (*prefix, digits.len()) {
+            ("macd", 3) => format!("{}/{}", &digits[..1], &digits[1..]),
+            ("macd", 4) => format!("{}/{}", &digits[..2], &digits[2..]),
+            _ => digits.to_string(),
+        };
+        return (description.to_string(), window);
+    }
+    (String::new(), String::new())
+}
+
+async fn column_comments(client: &Client, table: &str) -> Result<HashMap<String, String>> {
+    let rows = client
+        .query(
+            "select a.attname::text, col_description(a.attrelid, a.attnum)
+             from pg_attribute a
+             where a.attrelid = $1::text::regclass and a.attnum > 0 and not a.attisdropped",
+            &[&table],
+        )
+        .await?;
+    Ok(rows
+        .iter()
+        .filter_map(|r| Some((r.get::<_, String>(0), r.get::<_, Option<String>>(1)?)))
+        .collect())
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
@@ -0,0 +1,98 @@
+use anyhow::{bail, Context, Result};
+use std::collections::HashMap;
+
+use crate::fx;
+use crate::schema::IdentifierPolicy;
+use pg_nifty_dump::pipeline::Batch;
+
+/// What happened to a symbol on one day.
+#[derive(Debug, Clone, Copy, Default)]
+struct Event {
+    halt: bool,
+    /// `upper` or `lower` when the price hit a circuit limit.
+    circuit: Option<&'static str>,
+}
+
+/// Trading halts and circuit-limit days, from a CSV of `date,symbol,event`
+/// rows where the event is `halt`, `upper_circuit` or `lower_circuit`. A
+/// symbol of `*` (or none) marks a market-wide event.
+pub struct Events {
+    /// Events by upper-cased symbol and `YYYY-MM-DD` day.
+    days: HashMap<(String, String), Event>,
+    /// Symbol of each per-symbol table, for batches without a symbol column.
+    tables: HashMap<String, String>,
+}
+
+impl Events {
+    pub fn load(path: &str, identifiers: &IdentifierPolicy) -> Result<Self> {
+        let mut rdr =
+            csv::Reader::from_path(path).with_context(|| format!("opening events: {path}"))?;
+        let mut days: HashMap<(String, String), Event> = HashMap::new();
+        let mut tables = HashMap::new();
+        for (line, record) in rdr.records().enumerate() {
+            let record = record.with_context(|| format!("reading events: {path}"))?;
+            let (Some(date), Some(symbol), Some(kind)) =
+                (record.get(0), record.get(1), record.get(2))
+            else {
+                bail!("malformed event on line {}: {record:?}", line + 2);
+            };
+            let symbol = match symbol.trim() {
+                "" => "*".to_string(),
+                symbol => {
+                    tables.insert(identifiers.table(symbol), symbol.to_uppercase());
+                    symbol.to_uppercase()
+                }
+            };
+            let event = days.entry((symbol, fx::day(date).to_string())).or_default();
+            match kind.trim().to_lowercase().as_str() {
+                "halt" => event.halt = true,
+                "upper_circuit" => event.circuit = Some("upper"),
+                "lower_circuit" => event.circuit = Some("lower"),
+                kind => bail!(
+                    "unknown event on line {}: {kind} (expected halt, upper_circuit or lower_circuit)",
+                    line + 2
+                ),
+            }
+        }
+        Ok(Events { days, tables })
+    }
+
+    /// Adds an `is_trading_halt` column and a `circuit_limit` column
+    /// (`upper`, `lower` or null) to the batch, returning the rows with an
+    /// event.
+    pub fn flag(&self, batch: &mut Batch) -> Result<usize> {
+        let date = batch.column("date").context("missing date column")?;
+        let symbol_column = batch.column("symbol");
+        let table_symbol = self.tables.get(&batch.table_name);
+
+        let mut flagged = 0;
+        for record in &mut batch.records {
+            let day = fx::day(&String::from_utf8_lossy(&record[date])).to_string();
+            let symbol = match symbol_column {
+                Some(i) => String::from_utf8_lossy(&record[i]).trim().to_uppercase(),
+                None => table_symbol.cloned().unwrap_or_default(),
+            };
+            let mut event = Event::default();
+            for found in [symbol, "*".to_string()]
+                .into_iter()
+                .filter_map(|s| self.days.get(&(s, day.clone())))
+            {
+                event.halt |= found.halt;
+                event.circuit = event.circuit.or(found.circuit);
+            }
+            if event.halt || event.circuit.is_some() {
+                flagged += 1;
+            }
+            record.push_field(if event.halt { b"true" } else { b"false" });
+            record.push_field(event.circuit.unwrap_or_default().as_bytes());
+        }
+
+        for (column, data_type) in [("is_trading_halt", "boolean"), ("circuit_limit", "text")] {
+            batch.columns.push(column.to_string());
+            batch
+                .extra_columns
+                .push((column.to_string(), data_type.to_string()));
+        }
+        Ok(flagged)
+    }
+}
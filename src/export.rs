@@ -0,0 +1,1051 @@
+use anyhow::{bail, Context, Result};
+use clap::{Args, ValueEnum};
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use futures::{pin_mut, StreamExt};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, BufWriter, Seek, SeekFrom, Write};
+use std::path::Path;
+use std::sync::Mutex;
+use tokio_postgres::Client;
+
+use crate::anonymize::Anonymizer;
+#[cfg(feature = "bundle")]
+use crate::bundle;
+use crate::schema::{self, IdentifierPolicy};
+use crate::ConnectionArgs;
+use pg_nifty_dump::compression;
+
+/// Functions that may be called inside a `--select` projection.
+static ALLOWED_FUNCTIONS: &[&str] = &[
+    "abs", "ceil", "coalesce", "exp", "floor", "greatest", "least", "ln", "log", "nullif", "power",
+    "round", "sign", "sqrt",
+];
+
+#[derive(Debug, Args)]
+pub struct ExportArgs {
+    /// Directory the CSV files are written to.
+    #[clap(short, long = "dir", required_unless_present_any = ["combined", "bundle"])]
+    dir: Option<String>,
+
+    /// Write every table into one CSV with a leading `symbol` column instead,
+    /// or to stdout for `-`. A `.gz` or `.zst` extension compresses the
+    /// output with gzip or zstd.
+    #[clap(long = "combined", conflicts_with = "dir")]
+    combined: Option<String>,
+
+    /// Compress the exported files as they are streamed out.
+    #[clap(long = "compress", value_enum, conflicts_with = "bundle")]
+    compress: Option<Codec>,
+
+    /// Write the tables, plus `symbols` and `calendar` dimensions, into a
+    /// single DuckDB (`.duckdb`) or SQLite (`.sqlite`) file instead, using
+    /// the `duckdb` or `sqlite3` CLI.
+    #[clap(long = "bundle", conflicts_with_all = ["dir", "combined"])]
+    bundle: Option<String>,
+
+    /// Tables to export. Defaults to every managed table.
+    #[clap(short, long = "tables", value_delimiter = ',')]
+    tables: Vec<String>,
+
+    /// Export every managed table.
+    #[clap(long = "all", conflicts_with = "tables")]
+    all: bool,
+
+    /// Table template the tables were loaded with, so per-symbol tables are
+    /// exported under their symbol. Without it the symbol is taken from the
+    /// file named in the table's load comment.
+    #[clap(long = "table-template")]
+    table_template: Option<String>,
+
+    /// The tables were loaded with --keep-case.
+    #[clap(long = "keep-case")]
+    keep_case: bool,
+
+    /// Columns or expressions to export, e.g. "date, close, (close - open)/open as day_return".
+    #[clap(short, long = "select")]
+    select: Option<String>,
+
+    /// Only export rows dated on or after this date.
+    #[clap(long = "from")]
+    from: Option<String>,
+
+    /// Only export rows dated before this date.
+    #[clap(long = "to")]
+    to: Option<String>,
+
+    /// Time zone the timestamps are written in, e.g. `Asia/Kolkata`.
+    /// Defaults to the server's setting.
+    #[clap(long = "tz")]
+    tz: Option<String>,
+
+    /// Scale prices by a factor derived from --anonymize-key and replace
+    /// symbols with pseudonyms, to share data without the licensed values.
+    #[clap(
+        long = "anonymize",
+        requires = "anonymize_key",
+        conflicts_with_all = ["bundle", "select"]
+    )]
+    anonymize: bool,
+
+    /// Secret the price factor and pseudonyms are derived from; the same key
+    /// gives the same dataset.
+    #[clap(
+        long = "anonymize-key",
+        env = "PG_NIFTY_DUMP_ANONYMIZE_KEY",
+        hide_env_values = true
+    )]
+    anonymize_key: Option<String>,
+
+    /// CSV of `symbol,alias` pairs used with --anonymize instead of
+    /// pseudonyms.
+    #[clap(long = "symbol-map", requires = "anonymize")]
+    symbol_map: Option<String>,
+
+    /// Number of connections used to export tables, or the partitions of a
+    /// single table, in parallel.
+    #[clap(short, long = "jobs", default_value_t = 1)]
+    jobs: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Codec {
+    Gzip,
+    Zstd,
+}
+
+impl Codec {
+    fn extension(self) -> &'static str {
+        match self {
+            Codec::Gzip => "gz",
+            Codec::Zstd => "zst",
+        }
+    }
+}
+
+/// Writer compressing what is written through it.
+enum Encoder<W: Write> {
+    Plain(W),
+    Gzip(GzEncoder<W>),
+    #[cfg(feature = "compression")]
+    Zstd(zstd::stream::write::Encoder<'static, W>),
+}
+
+impl<W: Write> Encoder<W> {
+    fn new(inner: W, codec: Option<Codec>) -> Result<Self> {
+        Ok(match codec {
+            None => Encoder::Plain(inner),
+            Some(Codec::Gzip) => Encoder::Gzip(GzEncoder::new(inner, Compression::default())),
+            #[cfg(feature = "compression")]
+            Some(Codec::Zstd) => Encoder::Zstd(zstd::stream::write::Encoder::new(inner, 0)?),
+            #[cfg(not(feature = "compression"))]
+            Some(Codec::Zstd) => bail!("zstd output needs a build with the compression feature"),
+        })
+    }
+
+    /// Ends the compressed stream, returning the writer below.
+    fn finish(self) -> io::Result<W> {
+        match self {
+            Encoder::Plain(inner) => Ok(inner),
+            Encoder::Gzip(encoder) => encoder.finish(),
+            #[cfg(feature = "compression")]
+            Encoder::Zstd(encoder) => encoder.finish(),
+        }
+    }
+}
+
+impl<W: Write> Write for Encoder<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Encoder::Plain(inner) => inner.write(buf),
+            Encoder::Gzip(encoder) => encoder.write(buf),
+            #[cfg(feature = "compression")]
+            Encoder::Zstd(encoder) => encoder.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Encoder::Plain(inner) => inner.flush(),
+            Encoder::Gzip(encoder) => encoder.flush(),
+            #[cfg(feature = "compression")]
+            Encoder::Zstd(encoder) => encoder.flush(),
+        }
+    }
+}
+
+/// What is read from every exported table.
+#[derive(Clone)]
+pub struct Selection {
+    /// Select list, or `None` for every column in canonical order.
+    projection: Option<String>,
+    /// Date range as a `where` clause, empty when unbounded.
+    filter: String,
+    anonymizer: Option<Anonymizer>,
+    /// How symbols were made table names, to tell a table's symbol.
+    identifiers: IdentifierPolicy,
+    /// Symbol of each per-symbol table its load comment names.
+    symbols: HashMap<String, String>,
+}
+
+impl Selection {
+    pub fn query(&self, prefix: &str, relation: &str, header: bool) -> String {
+        format!(
+            "copy ({}) to stdout with (format csv, header {header})",
+            self.select(prefix, relation)
+        )
+    }
+
+    pub fn select(&self, prefix: &str, relation: &str) -> String {
+        format!(
+            "select {prefix}{} from {relation}{} order by date",
+            self.projection.as_deref().unwrap_or("*"),
+            self.filter
+        )
+    }
+
+    /// The selection with its projection fixed for `table`, so files are
+    /// byte-comparable whatever the table's physical column order: the
+    /// `symbol` column of a shared table (unless `with_symbol` is false),
+    /// the canonical columns under their canonical names, then any extra
+    /// columns by name. Anonymized, extra price columns are scaled too and
+    /// extra columns of unknown units left out, as they might be prices.
+    pub async fn for_table(&self, c: &Client, table: &str, with_symbol: bool) -> Result<Selection> {
+        if self.projection.is_some() {
+            return Ok(self.clone());
+        }
+        let rows = c
+            .query(
+                "select attname::text from pg_attribute
+                 where attrelid = $1::text::regclass and attnum > 0 and not attisdropped",
+                &[&table],
+            )
+            .await
+            .with_context(|| format!("reading columns => {table}"))?;
+        let present: Vec<String> = rows.iter().map(|r| r.get(0)).collect();
+        Ok(self.with_columns(present, with_symbol))
+    }
+
+    /// The selection with its projection fixed for a table of these
+    /// columns, as [`Selection::for_table`] makes it.
+    fn with_columns(&self, mut present: Vec<String>, with_symbol: bool) -> Selection {
+        present.sort();
+        let mut items = Vec::with_capacity(present.len());
+        if with_symbol && present.iter().any(|c| c == "symbol") {
+            match &self.anonymizer {
+                Some(anonymizer) => {
+                    items.push(format!("{} as symbol", anonymizer.symbol("symbol")));
+                }
+                None => items.push("symbol".to_string()),
+            }
+        }
+        let prices = schema::price_columns();
+        for column in schema::columns() {
+            let lower = column.to_lowercase();
+            if !present.contains(&lower) {
+                continue;
+            }
+            let value = match &self.anonymizer {
+                Some(anonymizer) if prices.contains(&column) => anonymizer.scale(&lower),
+                _ => lower.clone(),
+            };
+            if value == column {
+                items.push(value);
+            } else {
+                items.push(format!("{value} as \"{column}\""));
+            }
+        }
+        for column in present
+            .into_iter()
+            .filter(|c| c != "symbol" && !schema::is_column(c))
+        {
+            match (&self.anonymizer, schema::extra_column_is_price(&column)) {
+                (None, _) | (Some(_), Some(false)) => items.push(column),
+                (Some(anonymizer), Some(true)) => {
+                    items.push(format!("{} as {column}", anonymizer.scale(&column)))
+                }
+                (Some(_), None) => {}
+            }
+        }
+
+        Selection {
+            projection: Some(items.join(", ")),
+            filter: self.filter.clone(),
+            anonymizer: self.anonymizer.clone(),
+            identifiers: self.identifiers.clone(),
+            symbols: self.symbols.clone(),
+        }
+    }
+
+    /// What goes before the projection of `table` in a combined export, so
+    /// every row leads with its symbol: nothing for a shared table whose
+    /// projection selects its `symbol` column, the column itself for a
+    /// `--select` one, and the table's symbol as a constant otherwise.
+    fn combined_prefix(&self, table: &str, shared: bool) -> String {
+        match (shared, &self.projection) {
+            (true, None) => String::new(),
+            (true, Some(_)) => "symbol, ".to_string(),
+            (false, _) => format!(
+                "'{}' as symbol, ",
+                self.exported_symbol(table).replace('\'', "''")
+            ),
+        }
+    }
+
+    /// Symbol of a per-symbol table, through the table template when one
+    /// is given, else its load comment; the table name itself when neither
+    /// tells.
+    pub fn symbol_of(&self, table: &str) -> String {
+        let name = schema::unquoted(table);
+        let templated = self.identifiers.table_template.is_some();
+        templated
+            .then(|| self.identifiers.symbol_of(&name))
+            .flatten()
+            .or_else(|| self.symbols.get(table).cloned())
+            .unwrap_or(name)
+    }
+
+    /// Name a table's rows are exported under: its symbol, or the symbol's
+    /// alias when anonymized, the same alias its rows get in shared tables.
+    pub fn exported_symbol(&self, table: &str) -> String {
+        let symbol = self.symbol_of(table);
+        match &self.anonymizer {
+            Some(anonymizer) => anonymizer.alias(&symbol),
+            None => symbol,
+        }
+    }
+}
+
+pub async fn run(client: &Client, conn: &ConnectionArgs, args: &ExportArgs) -> Result<()> {
+    if let Some(tz) = &args.tz {
+        crate::set_time_zone(client, tz).await?;
+    }
+    let projection = match &args.select {
+        Some(select) => Some(parse_projection(select).context("invalid --select")?),
+        None => None,
+    };
+    let mut selection = Selection {
+        projection,
+        filter: date_filter(client, args.from.as_deref(), args.to.as_deref()).await?,
+        anonymizer: match (args.anonymize, &args.anonymize_key) {
+            (true, Some(key)) => Some(Anonymizer::new(key, args.symbol_map.as_deref())?),
+            _ => None,
+        },
+        identifiers: IdentifierPolicy {
+            keep_case: args.keep_case,
+            table_template: args.table_template.clone(),
+            ..IdentifierPolicy::default()
+        },
+        symbols: HashMap::new(),
+    };
+    if let Some(template) = &args.table_template {
+        schema::check_template(template)?;
+    }
+
+    let tables = if args.all || args.tables.is_empty() {
+        managed_tables(client).await?
+    } else {
+        resolve_tables(client, &args.tables).await?
+    };
+    if args.table_template.is_none() {
+        selection.symbols = commented_symbols(client, &tables, &selection.identifiers).await?;
+    }
+
+    if let Some(combined) = &args.combined {
+        return export_combined(client, &tables, &selection, combined, args.compress).await;
+    }
+    if let Some(path) = &args.bundle {
+        #[cfg(feature = "bundle")]
+        return bundle::write(client, &tables, &selection, Path::new(path)).await;
+        #[cfg(not(feature = "bundle"))]
+        bail!("--bundle needs a build with the bundle feature: {path}");
+    }
+
+    let dir = args.dir.as_deref().context("--dir is required")?;
+    fs::create_dir_all(dir).with_context(|| format!("creating directory: {dir}"))?;
+    let total = tables.len();
+    let mut exported = Vec::with_capacity(total);
+    let pool = if args.jobs > 1 {
+        Some(Pool::open(conn, args, args.jobs).await?)
+    } else {
+        None
+    };
+    if let (Some(pool), true) = (&pool, total > 1) {
+        // One pooled connection per worker; partitions of each table are
+        // then read serially so the pool bounds the connection count.
+        let results = futures::stream::iter(tables.iter())
+            .map(|table| {
+                let selection = &selection;
+                async move {
+                    let client = pool.take();
+                    let file = export_one(&client, None, table, selection, dir, args).await;
+                    pool.put(client);
+                    file
+                }
+            })
+            .buffer_unordered(args.jobs);
+        pin_mut!(results);
+        while let Some(file) = results.next().await {
+            exported.push(file?);
+            report_exported(exported.len(), total, exported.last().unwrap());
+        }
+    } else {
+        for table in &tables {
+            let file = export_one(client, pool.as_ref(), table, &selection, dir, args).await?;
+            exported.push(file);
+            report_exported(exported.len(), total, exported.last().unwrap());
+        }
+    }
+
+    exported.sort_by(|a, b| a.table.cmp(&b.table));
+    let manifest = Path::new(dir).join("manifest.json");
+    fs::write(&manifest, serde_json::to_vec_pretty(&exported)?)
+        .with_context(|| format!("writing manifest: {}", manifest.display()))?;
+    println!("Wrote {}", manifest.display());
+
+    Ok(())
+}
+
+/// Connections for the export's workers, opened once with the session set
+/// up like the main one. Workers never outnumber the connections.
+struct Pool {
+    clients: Mutex<Vec<Client>>,
+}
+
+impl Pool {
+    async fn open(conn: &ConnectionArgs, args: &ExportArgs, size: usize) -> Result<Pool> {
+        let mut clients = Vec::with_capacity(size);
+        for _ in 0..size {
+            let client = crate::connect(conn).await?;
+            if let Some(tz) = &args.tz {
+                crate::set_time_zone(&client, tz).await?;
+            }
+            clients.push(client);
+        }
+        Ok(Pool {
+            clients: Mutex::new(clients),
+        })
+    }
+
+    fn size(&self) -> usize {
+        self.clients.lock().unwrap().len()
+    }
+
+    fn take(&self) -> Client {
+        self.clients
+            .lock()
+            .unwrap()
+            .pop()
+            .expect("more export workers than connections")
+    }
+
+    fn put(&self, client: Client) {
+        self.clients.lock().unwrap().push(client);
+    }
+}
+
+/// Entry of the export manifest.
+#[derive(Debug, Serialize)]
+struct ExportedFile {
+    table: String,
+    file: String,
+    /// Data lines, excluding the header.
+    rows: u64,
+    /// Size of the file as written, compressed or not.
+    bytes: u64,
+    sha256: String,
+}
+
+fn report_exported(done: usize, total: usize, file: &ExportedFile) {
+    println!(
+        "[{done}/{total}] Exported {} ({} rows) to {}",
+        file.table, file.rows, file.file
+    );
+}
+
+/// Exports one table, reading its partitions over the pool's connections
+/// when there is one.
+async fn export_one(
+    client: &Client,
+    pool: Option<&Pool>,
+    table: &str,
+    selection: &Selection,
+    dir: &str,
+    args: &ExportArgs,
+) -> Result<ExportedFile> {
+    let name = match &selection.anonymizer {
+        Some(_) => selection.exported_symbol(table),
+        None => schema::unquoted(table),
+    };
+    let path = match args.compress {
+        Some(codec) => Path::new(dir).join(format!("{name}.csv.{}", codec.extension())),
+        None => Path::new(dir).join(format!("{name}.csv")),
+    };
+    let has_range = args.from.is_some() || args.to.is_some();
+    let partitions = if has_range {
+        relevant_partitions(client, table, args.from.as_deref(), args.to.as_deref()).await?
+    } else {
+        Vec::new()
+    };
+
+    let selection = &selection.for_table(client, table, true).await?;
+    let file = fs::File::create(&path).with_context(|| format!("creating file: {:?}", path))?;
+    // Rows are counted before compression, bytes and hash after it.
+    let output = Tally::new(BufWriter::new(file));
+    let mut writer = Tally::new(Encoder::new(output, args.compress)?);
+    let result = if partitions.is_empty() {
+        copy_out(client, &selection.query("", table, true), &mut writer).await
+    } else {
+        println!("Reading {} partitions of {table}", partitions.len());
+        export_partitions(client, pool, &partitions, selection, &mut writer).await
+    };
+    result.with_context(|| format!("error exporting table: {table}"))?;
+    let Tally { inner, lines, .. } = writer;
+    let mut output = inner.finish()?;
+    output.flush()?;
+
+    Ok(ExportedFile {
+        table: name,
+        file: path.display().to_string(),
+        rows: lines.saturating_sub(1),
+        bytes: output.bytes,
+        sha256: format!("{:x}", output.hasher.finalize()),
+    })
+}
+
+/// Counts and hashes everything written through it.
+struct Tally<W> {
+    inner: W,
+    hasher: Sha256,
+    bytes: u64,
+    lines: u64,
+}
+
+impl<W: Write> Tally<W> {
+    fn new(inner: W) -> Self {
+        Tally {
+            inner,
+            hasher: Sha256::new(),
+            bytes: 0,
+            lines: 0,
+        }
+    }
+}
+
+impl<W: Write> Write for Tally<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.hasher.update(&buf[..n]);
+        self.bytes += n as u64;
+        self.lines += buf[..n].iter().filter(|b| **b == b'\n').count() as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Validates the date bounds with the server and turns them into a `where`
+/// clause.
+async fn date_filter(c: &Client, from: Option<&str>, to: Option<&str>) -> Result<String> {
+    let mut conditions = Vec::new();
+    for (bound, op) in [(from, ">="), (to, "<")] {
+        if let Some(bound) = bound {
+            let bound: String = c
+                .query_one("select $1::text::timestamptz::text", &[&bound])
+                .await
+                .with_context(|| format!("invalid date: {bound}"))?
+                .get(0);
+            conditions.push(format!("date {op} '{bound}'"));
+        }
+    }
+    if conditions.is_empty() {
+        return Ok(String::new());
+    }
+    Ok(format!(" where {}", conditions.join(" and ")))
+}
+
+/// Partitions of `table` whose range bounds overlap `[from, to)`, ordered by
+/// their lower bound. Empty for tables that are not partitioned.
+async fn relevant_partitions(
+    c: &Client,
+    table: &str,
+    from: Option<&str>,
+    to: Option<&str>,
+) -> Result<Vec<String>> {
+    let rows = c
+        .query(
+            r"
+with bounds as (
+    select c.oid::regclass::text as partition,
+        substring(pg_get_expr(c.relpartbound, c.oid) from $$FROM \('([^']*)'\)$$)::timestamptz as lower,
+        substring(pg_get_expr(c.relpartbound, c.oid) from $$TO \('([^']*)'\)$$)::timestamptz as upper
+    from pg_inherits i
+    join pg_class c on c.oid = i.inhrelid
+    where i.inhparent = $1::text::regclass
+)
+select partition from bounds
+where ($2::text is null or upper is null or upper > $2::text::timestamptz)
+    and ($3::text is null or lower is null or lower < $3::text::timestamptz)
+order by lower nulls first, partition
+",
+            &[&table, &from, &to],
+        )
+        .await
+        .with_context(|| format!("listing partitions => {table}"))?;
+    Ok(rows.iter().map(|r| r.get(0)).collect())
+}
+
+/// Exports the partitions, on every connection of the pool at once when
+/// there is one. Each partition is spooled to a temporary file and appended
+/// in order, so the output stays sorted by date.
+async fn export_partitions(
+    client: &Client,
+    pool: Option<&Pool>,
+    partitions: &[String],
+    selection: &Selection,
+    writer: &mut impl Write,
+) -> Result<()> {
+    let Some(pool) = pool else {
+        for (i, partition) in partitions.iter().enumerate() {
+            copy_out(client, &selection.query("", partition, i == 0), writer)
+                .await
+                .with_context(|| format!("exporting partition => {partition}"))?;
+        }
+        return Ok(());
+    };
+    let spools = futures::stream::iter(partitions.iter().enumerate())
+        .map(|(i, partition)| async move {
+            let client = pool.take();
+            let mut spool = tempfile::tempfile()?;
+            let result = copy_out(&client, &selection.query("", partition, i == 0), &mut spool)
+                .await
+                .with_context(|| format!("exporting partition => {partition}"));
+            pool.put(client);
+            result?;
+            anyhow::Ok(spool)
+        })
+        .buffered(pool.size());
+    pin_mut!(spools);
+    while let Some(spool) = spools.next().await {
+        let mut spool = spool?;
+        spool.seek(SeekFrom::Start(0))?;
+        io::copy(&mut spool, writer)?;
+    }
+    Ok(())
+}
+
+/// Streams the tables into one file, or stdout for `-`, compressed with
+/// `codec` or as the file's extension says.
+async fn export_combined(
+    client: &Client,
+    tables: &[String],
+    selection: &Selection,
+    target: &str,
+    codec: Option<Codec>,
+) -> Result<()> {
+    let path = Path::new(target);
+    let named = match path.extension().and_then(|e| e.to_str()) {
+        Some("gz") => Some(Codec::Gzip),
+        Some("zst") => Some(Codec::Zstd),
+        _ => None,
+    };
+    let codec = match (codec, named) {
+        (Some(codec), _) if target == "-" => Some(codec),
+        (Some(codec), named) if named != Some(codec) => bail!(
+            "--compress does not match the extension of {target}; name it .{}",
+            codec.extension()
+        ),
+        (codec, named) => codec.or(named),
+    };
+    // Not locked, as the lock would be held across awaits.
+    let output: Box<dyn Write + Send> = if target == "-" {
+        Box::new(io::stdout())
+    } else {
+        Box::new(fs::File::create(path).with_context(|| format!("creating file: {:?}", path))?)
+    };
+    let mut writer = Encoder::new(BufWriter::new(output), codec)?;
+    write_combined(client, tables, selection, path, &mut writer).await?;
+    writer.finish()?.flush()?;
+    Ok(())
+}
+
+async fn write_combined(
+    client: &Client,
+    tables: &[String],
+    selection: &Selection,
+    path: &Path,
+    writer: &mut impl Write,
+) -> Result<()> {
+    for (i, table) in tables.iter().enumerate() {
+        // On stderr, as the rows may be going to stdout.
+        eprintln!("Exporting {table} to {}...", path.display());
+        // Only the first table contributes the header line.
+        let shared = is_shared(client, table).await?;
+        let prefix = selection.combined_prefix(table, shared);
+        let query = selection
+            .for_table(client, table, shared)
+            .await?
+            .query(&prefix, table, i == 0);
+        copy_out(client, &query, writer)
+            .await
+            .with_context(|| format!("error exporting table: {table}"))?;
+    }
+    Ok(())
+}
+
+/// Whether the table holds several symbols, keyed by a `symbol` column.
+pub async fn is_shared(c: &Client, table: &str) -> Result<bool> {
+    Ok(c.query_one(
+        "select exists (select from pg_attribute
+             where attrelid = $1::text::regclass and attname = 'symbol' and not attisdropped)",
+        &[&table],
+    )
+    .await
+    .with_context(|| format!("reading columns => {table}"))?
+    .get(0))
+}
+
+/// Symbols of the tables from the files their load comments name, e.g.
+/// `INFY` of `infy` loaded from `INFY.csv`. Files whose name is not the
+/// symbol part of the table, such as a bhavcopy split into underlyings,
+/// are passed over.
+async fn commented_symbols(
+    c: &Client,
+    tables: &[String],
+    identifiers: &IdentifierPolicy,
+) -> Result<HashMap<String, String>> {
+    let mut symbols = HashMap::new();
+    for table in tables {
+        let comment: Option<String> = c
+            .query_one(
+                "select obj_description($1::text::regclass, 'pg_class')",
+                &[table],
+            )
+            .await
+            .with_context(|| format!("reading comment => {table}"))?
+            .get(0);
+        let Some(source) = comment.as_deref().and_then(|comment| {
+            let rest = comment.strip_prefix("pg_nifty_dump: ")?;
+            rest[rest.find("source=")? + "source=".len()..]
+                .rsplit_once(", rows=")
+                .map(|(source, _)| source)
+        }) else {
+            continue;
+        };
+        let Some(symbol) = compression::strip_extension(Path::new(source))
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+        else {
+            continue;
+        };
+        let folded = identifiers.apply(symbol);
+        if schema::unquoted(table).contains(folded.trim_matches('"')) {
+            symbols.insert(table.clone(), symbol.to_string());
+        }
+    }
+    Ok(symbols)
+}
+
+/// Tables in the current schema that carry every canonical column, quoted
+/// where SQL needs it, e.g. tables loaded with --keep-case. The partitions
+/// of a partitioned table are covered by their parent.
+pub async fn managed_tables(c: &Client) -> Result<Vec<String>> {
+    let columns: Vec<String> = schema::columns().iter().map(|c| c.to_lowercase()).collect();
+    let rows = c
+        .query(
+            r"
+select quote_ident(c.relname)
+from pg_class c
+join pg_namespace n on n.oid = c.relnamespace
+join pg_attribute a on a.attrelid = c.oid and a.attnum > 0 and not a.attisdropped
+where n.nspname = current_schema() and c.relkind in ('r', 'p') and not c.relispartition
+group by c.relname
+having array_agg(a.attname::text) @> $1
+order by 1
+",
+            &[&columns],
+        )
+        .await
+        .context("listing managed tables")?;
+    Ok(rows.iter().map(|r| r.get(0)).collect())
+}
+
+/// Looks the named tables up in the catalog, so they reach queries quoted as
+/// the server spells them rather than as given. An exact, case-sensitive
+/// name is tried before the folded one.
+pub async fn resolve_tables(c: &Client, names: &[String]) -> Result<Vec<String>> {
+    let mut tables = Vec::with_capacity(names.len());
+    for name in names {
+        let table: Option<String> = c
+            .query_one(
+                "select coalesce(to_regclass(quote_ident($1)), to_regclass($1))::text",
+                &[name],
+            )
+            .await
+            .with_context(|| format!("looking up table => {name}"))?
+            .get(0);
+        match table {
+            Some(table) => tables.push(table),
+            None => bail!("no such table: {name}"),
+        }
+    }
+    Ok(tables)
+}
+
+pub async fn copy_out(c: &Client, query: &str, writer: &mut impl Write) -> Result<()> {
+    let stream = c.copy_out(query).await?;
+    pin_mut!(stream);
+    while let Some(chunk) = stream.next().await {
+        writer.write_all(&chunk?)?;
+    }
+    Ok(())
+}
+
+#[derive(Debug, PartialEq)]
+enum Token {
+    Ident(String),
+    Number(String),
+    Op(char),
+    LParen,
+    RParen,
+    Comma,
+}
+
+/// Parses a restricted SQL projection: canonical columns, numeric literals,
+/// arithmetic, a small set of functions and `as` aliases. The projection is
+/// rebuilt from its tokens so nothing else can reach the query.
+fn parse_projection(select: &str) -> Result<String> {
+    let tokens = tokenize(select)?;
+    let mut items = Vec::new();
+    for item in tokens.split(|t| *t == Token::Comma) {
+        items.push(parse_item(item)?);
+    }
+    Ok(items.join(", "))
+}
+
+fn parse_item(tokens: &[Token]) -> Result<String> {
+    let (expr, alias) = match tokens {
+        [expr @ .., Token::Ident(kw), Token::Ident(alias)] if kw == "as" => (expr, Some(alias)),
+        _ => (tokens, None),
+    };
+    if expr.is_empty() {
+        bail!("empty expression in projection");
+    }
+
+    let mut sql = String::new();
+    let mut depth = 0;
+    // Whether the previous token ends an operand, which an operator must
+    // follow.
+    let mut after_operand = false;
+    for (i, token) in expr.iter().enumerate() {
+        let function =
+            matches!(token, Token::Ident(_)) && matches!(expr.get(i + 1), Some(Token::LParen));
+        if after_operand && matches!(token, Token::Ident(_) | Token::Number(_) | Token::LParen) {
+            bail!("missing operator in projection");
+        }
+        after_operand = match token {
+            Token::Ident(_) => !function,
+            Token::Number(_) | Token::RParen => true,
+            _ => false,
+        };
+        match token {
+            Token::Ident(name) if matches!(expr.get(i + 1), Some(Token::LParen)) => {
+                if !ALLOWED_FUNCTIONS.contains(&name.as_str()) {
+                    bail!("function not allowed in projection: {name}");
+                }
+                sql.push_str(name);
+            }
+            Token::Ident(name) => {
+                if !schema::is_column(name) {
+                    bail!("unknown column in projection: {name}");
+                }
+                sql.push_str(name);
+            }
+            Token::Number(n) => sql.push_str(n),
+            Token::Op(op) => {
+                sql.push(' ');
+                sql.push(*op);
+                sql.push(' ');
+            }
+            Token::LParen => {
+                depth += 1;
+                sql.push('(');
+            }
+            Token::RParen => {
+                depth -= 1;
+                if depth < 0 {
+                    bail!("unbalanced parentheses in projection");
+                }
+                sql.push(')');
+            }
+            Token::Comma => unreachable!(),
+        }
+    }
+    if depth != 0 {
+        bail!("unbalanced parentheses in projection");
+    }
+
+    if let Some(alias) = alias {
+        sql.push_str(" as ");
+        sql.push_str(alias);
+    }
+    Ok(sql)
+}
+
+fn tokenize(select: &str) -> Result<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut chars = select.chars().peekable();
+    // A comma inside a function call separates arguments, not projection items.
+    let mut depth = 0;
+    while let Some(&ch) = chars.peek() {
+        match ch {
+            c if c.is_whitespace() => {
+                chars.next();
+            }
+            c if c.is_ascii_alphabetic() || c == '_' => {
+                let mut ident = String::new();
+                while let Some(&c) = chars.peek() {
+                    if !(c.is_ascii_alphanumeric() || c == '_') {
+                        break;
+                    }
+                    ident.push(c.to_ascii_lowercase());
+                    chars.next();
+                }
+                tokens.push(Token::Ident(ident));
+            }
+            c if c.is_ascii_digit() || c == '.' => {
+                let mut number = String::new();
+                while let Some(&c) = chars.peek() {
+                    if !(c.is_ascii_digit() || c == '.') {
+                        break;
+                    }
+                    number.push(c);
+                    chars.next();
+                }
+                if number.parse::<f64>().is_err() {
+                    bail!("invalid number in projection: {number}");
+                }
+                tokens.push(Token::Number(number));
+            }
+            '+' | '-' | '*' | '/' | '%' => {
+                tokens.push(Token::Op(ch));
+                chars.next();
+            }
+            '(' => {
+                depth += 1;
+                tokens.push(Token::LParen);
+                chars.next();
+            }
+            ')' => {
+                depth -= 1;
+                tokens.push(Token::RParen);
+                chars.next();
+            }
+            ',' if depth > 0 => {
+                tokens.push(Token::Op(','));
+                chars.next();
+            }
+            ',' => {
+                tokens.push(Token::Comma);
+                chars.next();
+            }
+            c => bail!("unexpected character in projection: {c:?}"),
+        }
+    }
+    Ok(tokens)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn projection_keeps_columns_functions_and_aliases() {
+        assert_eq!(
+            parse_projection("date, round(close, 2) as c, (close - open)/open as day_return")
+                .unwrap(),
+            "date, round(close , 2) as c, (close - open) / open as day_return"
+        );
+    }
+
+    #[test]
+    fn projection_rejects_unknown_names() {
+        assert!(parse_projection("password").is_err());
+        assert!(parse_projection("pg_sleep(1)").is_err());
+        assert!(parse_projection("close; drop table x").is_err());
+    }
+
+    #[test]
+    fn projection_rejects_adjacent_operands() {
+        assert!(parse_projection("close open").is_err());
+        assert!(parse_projection("close 1").is_err());
+        assert!(parse_projection("close (open)").is_err());
+        assert!(parse_projection("(close) (open)").is_err());
+    }
+
+    #[test]
+    fn projection_rejects_unbalanced_parentheses() {
+        assert!(parse_projection("abs(close").is_err());
+        assert!(parse_projection("close)").is_err());
+    }
+
+    fn selection(anonymizer: Option<Anonymizer>) -> Selection {
+        Selection {
+            projection: None,
+            filter: String::new(),
+            anonymizer,
+            identifiers: IdentifierPolicy::default(),
+            symbols: HashMap::new(),
+        }
+    }
+
+    fn columns(names: &[&str]) -> Vec<String> {
+        names.iter().map(|c| c.to_string()).collect()
+    }
+
+    #[test]
+    fn combined_shared_table_keeps_its_symbols() {
+        let selection = selection(None);
+        assert_eq!(selection.combined_prefix("stocks", true), "");
+        let projection = selection
+            .with_columns(columns(&["date", "close", "symbol"]), true)
+            .projection
+            .unwrap();
+        assert!(projection.starts_with("symbol, "), "{projection}");
+    }
+
+    #[test]
+    fn combined_shared_table_anonymizes_its_symbols() {
+        let anonymizer = Anonymizer::new("secret", None).unwrap();
+        let symbol = format!("{} as symbol, ", anonymizer.symbol("symbol"));
+        let selection = selection(Some(anonymizer));
+        let projection = selection
+            .with_columns(columns(&["date", "close", "symbol"]), true)
+            .projection
+            .unwrap();
+        assert!(projection.starts_with(&symbol), "{projection}");
+        assert_eq!(selection.combined_prefix("stocks", true), "");
+    }
+
+    #[test]
+    fn combined_symbol_table_gets_a_constant() {
+        let selection = selection(None);
+        assert_eq!(
+            selection.combined_prefix("infy", false),
+            "'infy' as symbol, "
+        );
+        let projection = selection
+            .with_columns(columns(&["date", "close"]), false)
+            .projection
+            .unwrap();
+        assert!(!projection.contains("symbol"), "{projection}");
+        let mut selection = selection;
+        selection.projection = Some("date, close".to_string());
+        assert_eq!(selection.combined_prefix("stocks", true), "symbol, ");
+    }
+}
@@ -0,0 +1,84 @@
+use anyhow::{Context, Result};
+use clap::Args;
+use tokio_postgres::Client;
+
+use crate::{export, schema};
+use pg_nifty_dump::postgres;
+
+#[derive(Debug, Args)]
+pub struct FeatureArgs {
+    /// Table the feature vectors are written to.
+    #[clap(long = "target", default_value = "feature_vectors")]
+    target: String,
+
+    /// Tables to take features from. Defaults to every managed table.
+    #[clap(short, long = "tables", value_delimiter = ',')]
+    tables: Vec<String>,
+}
+
+/// Materializes, per symbol and date, the z-score normalized indicator
+/// columns as a pgvector `vector` so market regimes can be compared with
+/// similarity search.
+pub async fn run(client: &Client, args: &FeatureArgs) -> Result<()> {
+    let tables = if args.tables.is_empty() {
+        export::managed_tables(client).await?
+    } else {
+        export::resolve_tables(client, &args.tables).await?
+    };
+    let target = schema::sanitise(args.target.clone());
+    let indicators = schema::indicator_columns();
+
+    println!("Creating table {target}...");
+    create_target(client, &target, indicators.len())
+        .await
+        .with_context(|| format!("error creating table: {target}"))?;
+
+    // Normalize each indicator over the symbol's full history. Missing values
+    // (e.g. indicator warm-up) become 0, the mean.
+    let elements: Vec<String> = indicators
+        .iter()
+        .map(|c| {
+            format!("coalesce(({c} - avg({c}) over ()) / nullif(stddev_pop({c}) over (), 0), 0)")
+        })
+        .collect();
+    let array = format!("array[{}]::vector", elements.join(", "));
+
+    for table in tables {
+        println!("Materializing features of {table}...");
+        let query = format!(
+            r"
+insert into {target} (symbol, date, features)
+select '{symbol}', date, {array} from {table}
+on conflict (symbol, date) do update set features = excluded.features
+",
+            symbol = schema::unquoted(&table).replace('\'', "''"),
+        );
+        let rows = client
+            .execute(&query, &[])
+            .await
+            .with_context(|| format!("error materializing features: {table}"))?;
+        println!("Wrote {rows} vectors");
+    }
+
+    Ok(())
+}
+
+async fn create_target(c: &Client, table_name: &str, dimensions: usize) -> Result<()> {
+    postgres::execute_ddl(c, "create extension if not exists vector")
+        .await
+        .context("creating extension => vector")?;
+    let query = format!(
+        r"
+create table if not exists {table_name} (
+    symbol text not null,
+    date timestamptz not null,
+    features vector({dimensions}) not null,
+    primary key (symbol, date)
+)
+"
+    );
+    postgres::execute_ddl(c, &query)
+        .await
+        .with_context(|| format!("creating table => {table_name}"))?;
+    Ok(())
+}
@@ -0,0 +1,501 @@
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashSet;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+
+use crate::tls::LazyTls;
+
+/// Attempts at a download, each resuming where the last one stopped.
+const ATTEMPTS: u32 = 5;
+/// Redirects followed per request.
+const MAX_REDIRECTS: usize = 5;
+
+/// Downloads a remote input into `dir` and returns its path there. `tls`
+/// is only built for an https URL.
+///
+/// `spec` is an `http://`, `https://` or `s3://bucket/key` URL, optionally
+/// ending in `#sha256=<hex>` to verify the contents. The bytes land in a
+/// `.part` file in `cache` named after the URL, which later attempts, and
+/// later runs, resume with range requests; an `ETag` validator makes sure
+/// the pieces come from the same version of the file.
+///
+/// A complete download is kept in `cache` under its content hash, which
+/// the URL's entry there records with its validators. Later runs ask for
+/// the file only if it changed since, and a pinned hash already downloaded
+/// is not asked for at all; the kept copy is checked against its hash
+/// before it is used. Only the latest download of each URL is kept.
+pub async fn fetch(spec: &str, cache: &Path, dir: &Path, tls: &LazyTls<'_>) -> Result<PathBuf> {
+    let (url, expected) = match spec.split_once("#sha256=") {
+        Some((url, hash)) => (url, Some(hash.to_lowercase())),
+        None => (spec, None),
+    };
+    let name = url
+        .rsplit('/')
+        .next()
+        .and_then(|n| n.split(['?', '#']).next())
+        .filter(|n| !n.is_empty())
+        .with_context(|| format!("URL has no file name: {url}"))?;
+    fs::create_dir_all(cache)
+        .with_context(|| format!("creating directory: {}", cache.display()))?;
+    let key = hex(&Sha256::digest(url.as_bytes())[..16]);
+    let part = cache.join(format!("{key}.part"));
+    let mut entry = Entry::load(&cache.join(format!("{key}.json")))?;
+
+    let cached = entry
+        .sha256
+        .clone()
+        .filter(|digest| verified(&stored(cache, digest), digest));
+    let digest = match (&expected, cached) {
+        (Some(expected), Some(cached)) if *expected == cached => cached,
+        (_, cached) => {
+            let mut attempt = 1;
+            let outcome = loop {
+                // Only a fresh request can be conditional; a resumed one
+                // continues the version it started with.
+                let conditional = cached.is_some() && !part.exists();
+                let result = if url.starts_with("s3://") {
+                    download_s3(url, &part, &mut entry, conditional).await
+                } else {
+                    download_http(url, &part, &mut entry, conditional, tls).await
+                };
+                match result {
+                    Ok(outcome) => break outcome,
+                    Err(e) if attempt < ATTEMPTS => {
+                        let have = fs::metadata(&part).map_or(0, |m| m.len());
+                        eprintln!("download of {url} failed ({e:#}), resuming after {have} bytes");
+                        tokio::time::sleep(Duration::from_secs(2u64.pow(attempt))).await;
+                        attempt += 1;
+                    }
+                    Err(e) => return Err(e).with_context(|| format!("downloading {url}")),
+                }
+            };
+            match (outcome, cached) {
+                (Outcome::NotModified, Some(cached)) => cached,
+                (Outcome::NotModified, None) => bail!("{url} answered not modified unasked"),
+                (Outcome::Downloaded, _) => store(cache, &part, &mut entry)?,
+            }
+        }
+    };
+    // Verified before anything can load the file.
+    if let Some(expected) = expected.filter(|e| *e != digest) {
+        bail!("checksum mismatch for {url}: expected {expected}, got {digest}");
+    }
+
+    let stored = stored(cache, &digest);
+    let target = dir.join(name);
+    if !verified(&target, &digest) {
+        let tmp = dir.join(format!(".{name}.tmp"));
+        fs::copy(&stored, &tmp).with_context(|| format!("writing file: {}", tmp.display()))?;
+        fs::rename(&tmp, &target).with_context(|| format!("writing file: {}", target.display()))?;
+    }
+    Ok(target)
+}
+
+/// What the cache knows of a URL between runs, kept as `<key>.json` next
+/// to its downloads.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Entry {
+    #[serde(skip)]
+    path: PathBuf,
+    /// Strong `ETag` of the version last downloaded, or being downloaded.
+    etag: Option<String>,
+    /// `Last-Modified` of that version.
+    last_modified: Option<String>,
+    /// Digest of the last complete download.
+    sha256: Option<String>,
+}
+
+impl Entry {
+    fn load(path: &Path) -> Result<Self> {
+        let mut entry: Entry = match fs::read(path) {
+            Ok(data) => serde_json::from_slice(&data).unwrap_or_default(),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Entry::default(),
+            Err(e) => {
+                return Err(e)
+                    .with_context(|| format!("reading download entry: {}", path.display()))
+            }
+        };
+        entry.path = path.to_path_buf();
+        Ok(entry)
+    }
+
+    fn save(&self) -> Result<()> {
+        fs::write(&self.path, serde_json::to_vec_pretty(self)?)
+            .with_context(|| format!("writing download entry: {}", self.path.display()))
+    }
+}
+
+/// Whether a request found the file changed.
+enum Outcome {
+    Downloaded,
+    NotModified,
+}
+
+fn stored(cache: &Path, digest: &str) -> PathBuf {
+    cache.join(format!("sha256-{digest}"))
+}
+
+/// Whether the file exists with this SHA-256.
+fn verified(path: &Path, digest: &str) -> bool {
+    let Ok(mut file) = fs::File::open(path) else {
+        return false;
+    };
+    let mut hasher = Sha256::new();
+    io::copy(&mut file, &mut hasher).is_ok() && hex(&hasher.finalize()) == digest
+}
+
+/// Keeps a complete download under its hash, records it as the URL's
+/// latest, and removes the downloads no URL names any more.
+fn store(cache: &Path, part: &Path, entry: &mut Entry) -> Result<String> {
+    let mut hasher = Sha256::new();
+    io::copy(&mut fs::File::open(part)?, &mut hasher)?;
+    let digest = hex(&hasher.finalize());
+    let stored = stored(cache, &digest);
+    fs::rename(part, &stored).with_context(|| format!("storing download: {}", stored.display()))?;
+    entry.sha256 = Some(digest.clone());
+    entry.save()?;
+
+    let mut named = HashSet::new();
+    let mut downloads = Vec::new();
+    for file in fs::read_dir(cache)? {
+        let path = file?.path();
+        let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+        if let Some(digest) = file_name.strip_prefix("sha256-") {
+            downloads.push((digest.to_string(), path.clone()));
+        } else if file_name.ends_with(".json") {
+            if let Some(digest) = Entry::load(&path)?.sha256 {
+                named.insert(digest);
+            }
+        }
+    }
+    for (digest, path) in downloads {
+        if !named.contains(&digest) {
+            fs::remove_file(&path)
+                .with_context(|| format!("removing old download: {}", path.display()))?;
+        }
+    }
+    Ok(digest)
+}
+
+/// Response head of an HTTP request.
+struct Head {
+    status: u16,
+    /// Header names lower-cased.
+    headers: Vec<(String, String)>,
+}
+
+impl Head {
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, v)| v.as_str())
+    }
+}
+
+trait Io: AsyncRead + AsyncWrite + Unpin + Send {}
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> Io for T {}
+
+/// Downloads the URL into `part`, or what is missing after what `part`
+/// holds. A `conditional` request asks for the file only if it differs
+/// from the version `entry` describes.
+async fn download_http(
+    url: &str,
+    part: &Path,
+    entry: &mut Entry,
+    conditional: bool,
+    tls: &LazyTls<'_>,
+) -> Result<Outcome> {
+    let offset = fs::metadata(part).map_or(0, |m| m.len());
+
+    let mut url = url.to_string();
+    for _ in 0..=MAX_REDIRECTS {
+        let (stream, host, path) = open(&url, tls).await?;
+        let mut stream = BufReader::new(stream);
+        let mut request = format!(
+            "GET {path} HTTP/1.1\r\nHost: {host}\r\nUser-Agent: pg_nifty_dump/{}\r\nAccept-Encoding: identity\r\nConnection: close\r\n",
+            env!("CARGO_PKG_VERSION")
+        );
+        if offset > 0 {
+            request.push_str(&format!("Range: bytes={offset}-\r\n"));
+            if let Some(validator) = &entry.etag {
+                request.push_str(&format!("If-Range: {validator}\r\n"));
+            }
+        } else if conditional {
+            if let Some(validator) = &entry.etag {
+                request.push_str(&format!("If-None-Match: {validator}\r\n"));
+            }
+            if let Some(modified) = &entry.last_modified {
+                request.push_str(&format!("If-Modified-Since: {modified}\r\n"));
+            }
+        }
+        request.push_str("\r\n");
+        stream.get_mut().write_all(request.as_bytes()).await?;
+        let head = read_head(&mut stream).await?;
+
+        // Bytes the file has in all, for a piece of it.
+        let mut total = None;
+        let mut file = match head.status {
+            304 if conditional => return Ok(Outcome::NotModified),
+            300..=399 => {
+                let location = head
+                    .header("location")
+                    .context("redirect without a location")?;
+                url = resolve(&url, location);
+                continue;
+            }
+            // The server ignored the range or the file changed: start over.
+            200 => fs::File::create(part)?,
+            206 => {
+                total = content_range_total(&head);
+                OpenOptions::new().append(true).open(part)?
+            }
+            416 if offset > 0 => {
+                // Nothing left after the offset: complete, unless the file
+                // shrank, in which case it is fetched again.
+                if content_range_total(&head) == Some(offset) {
+                    return Ok(Outcome::Downloaded);
+                }
+                fs::remove_file(part)?;
+                bail!("range not satisfiable, restarting");
+            }
+            status => bail!("HTTP {status} for {url}"),
+        };
+        entry.etag = head
+            .header("etag")
+            .filter(|tag| !tag.starts_with("W/"))
+            .map(str::to_string);
+        entry.last_modified = head.header("last-modified").map(str::to_string);
+        entry.save()?;
+
+        let chunked = head
+            .header("transfer-encoding")
+            .is_some_and(|t| t.eq_ignore_ascii_case("chunked"));
+        let length = head
+            .header("content-length")
+            .and_then(|l| l.parse::<u64>().ok());
+        let received = if chunked {
+            copy_chunked(&mut stream, &mut file).await?
+        } else {
+            copy_body(&mut stream, &mut file, length).await?
+        };
+        file.flush()?;
+        if let Some(length) = length.filter(|length| received < *length) {
+            bail!("connection closed after {received} of {length} bytes");
+        }
+        let size = fs::metadata(part)?.len();
+        if let Some(total) = total.filter(|total| size != *total) {
+            fs::remove_file(part)?;
+            bail!("resumed download has {size} of {total} bytes, restarting");
+        }
+        return Ok(Outcome::Downloaded);
+    }
+    bail!("more than {MAX_REDIRECTS} redirects")
+}
+
+/// Size of the whole file from a `Content-Range: bytes a-b/total` header.
+fn content_range_total(head: &Head) -> Option<u64> {
+    head.header("content-range")
+        .and_then(|r| r.rsplit('/').next())
+        .and_then(|t| t.parse().ok())
+}
+
+/// Connects to the URL's host, over TLS for https, returning the stream,
+/// the `Host` header value and the request path.
+async fn open(url: &str, tls: &LazyTls<'_>) -> Result<(Box<dyn Io>, String, String)> {
+    let (secure, rest) = if let Some(rest) = url.strip_prefix("https://") {
+        (true, rest)
+    } else if let Some(rest) = url.strip_prefix("http://") {
+        (false, rest)
+    } else {
+        bail!("unsupported URL: {url}");
+    };
+    let (authority, path) = match rest.find('/') {
+        Some(i) => (&rest[..i], &rest[i..]),
+        None => (rest, "/"),
+    };
+    let path = path.split('#').next().unwrap_or("/");
+    let (host, port) = match authority.rsplit_once(':') {
+        Some((host, port)) => (host, port.parse().context("invalid port")?),
+        None => (authority, if secure { 443 } else { 80 }),
+    };
+    let tcp = TcpStream::connect((host, port))
+        .await
+        .with_context(|| format!("connecting to {authority}"))?;
+    let stream: Box<dyn Io> = if secure {
+        Box::new(tls.get()?.connect(host, tcp).await?)
+    } else {
+        Box::new(tcp)
+    };
+    Ok((stream, authority.to_string(), path.to_string()))
+}
+
+async fn read_head(stream: &mut BufReader<Box<dyn Io>>) -> Result<Head> {
+    let mut line = String::new();
+    stream.read_line(&mut line).await?;
+    let status = line
+        .split_whitespace()
+        .nth(1)
+        .and_then(|s| s.parse().ok())
+        .with_context(|| format!("malformed HTTP status line: {}", line.trim()))?;
+    let mut headers = Vec::new();
+    loop {
+        line.clear();
+        if stream.read_line(&mut line).await? == 0 || line.trim().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            headers.push((name.trim().to_lowercase(), value.trim().to_string()));
+        }
+    }
+    Ok(Head { status, headers })
+}
+
+/// Copies the body up to `length` bytes, or until the connection closes.
+/// Stopping at the length spares relying on a clean TLS shutdown.
+async fn copy_body(
+    stream: &mut BufReader<Box<dyn Io>>,
+    file: &mut fs::File,
+    length: Option<u64>,
+) -> Result<u64> {
+    let mut buf = vec![0; 64 * 1024];
+    let mut received = 0;
+    loop {
+        let want = length.map_or(buf.len(), |l| buf.len().min((l - received) as usize));
+        if want == 0 {
+            return Ok(received);
+        }
+        let n = stream.read(&mut buf[..want]).await?;
+        if n == 0 {
+            return Ok(received);
+        }
+        file.write_all(&buf[..n])?;
+        received += n as u64;
+    }
+}
+
+async fn copy_chunked(stream: &mut BufReader<Box<dyn Io>>, file: &mut fs::File) -> Result<u64> {
+    let mut received = 0;
+    let mut line = String::new();
+    loop {
+        line.clear();
+        stream.read_line(&mut line).await?;
+        let size = line.trim().split(';').next().unwrap_or_default();
+        let size = u64::from_str_radix(size, 16)
+            .with_context(|| format!("malformed chunk size: {}", line.trim()))?;
+        if size == 0 {
+            return Ok(received);
+        }
+        let mut chunk = vec![0; size as usize];
+        stream.read_exact(&mut chunk).await?;
+        file.write_all(&chunk)?;
+        received += size;
+        // The CRLF closing the chunk.
+        line.clear();
+        stream.read_line(&mut line).await?;
+    }
+}
+
+/// Absolute URL of a redirect's location.
+fn resolve(base: &str, location: &str) -> String {
+    if location.contains("://") {
+        return location.to_string();
+    }
+    let scheme_end = base.find("://").map_or(0, |i| i + 3);
+    let origin_end = base[scheme_end..]
+        .find('/')
+        .map_or(base.len(), |i| scheme_end + i);
+    if location.starts_with('/') {
+        format!("{}{location}", &base[..origin_end])
+    } else {
+        let dir_end = base
+            .rfind('/')
+            .filter(|i| *i >= origin_end)
+            .unwrap_or(origin_end);
+        format!("{}/{location}", &base[..dir_end])
+    }
+}
+
+/// Downloads an S3 object through the `aws` CLI, so the host's credentials
+/// apply, fetching only the bytes after those already downloaded.
+#[cfg(feature = "s3")]
+async fn download_s3(
+    url: &str,
+    part: &Path,
+    entry: &mut Entry,
+    conditional: bool,
+) -> Result<Outcome> {
+    let (bucket, key) = url
+        .strip_prefix("s3://")
+        .and_then(|rest| rest.split_once('/'))
+        .with_context(|| format!("expected s3://bucket/key: {url}"))?;
+    let offset = fs::metadata(part).map_or(0, |m| m.len());
+    let piece = part.with_extension("piece");
+
+    let mut cmd = tokio::process::Command::new("aws");
+    cmd.args(["s3api", "get-object", "--bucket", bucket, "--key", key]);
+    if offset > 0 {
+        cmd.arg("--range").arg(format!("bytes={offset}-"));
+        if let Some(validator) = &entry.etag {
+            cmd.arg("--if-match").arg(validator);
+        }
+    } else if let (true, Some(validator)) = (conditional, &entry.etag) {
+        cmd.arg("--if-none-match").arg(validator);
+    }
+    cmd.arg(&piece);
+    let output = cmd.output().await.context("running aws")?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        if conditional && stderr.contains("(304)") {
+            return Ok(Outcome::NotModified);
+        }
+        // The object changed since the first piece, or the range is past
+        // its end: start over.
+        if stderr.contains("PreconditionFailed") || stderr.contains("InvalidRange") {
+            fs::remove_file(part)?;
+            entry.etag = None;
+            entry.save()?;
+        }
+        bail!("aws failed: {}", stderr.trim());
+    }
+    let response: serde_json::Value =
+        serde_json::from_slice(&output.stdout).context("parsing aws output")?;
+    entry.etag = response["ETag"].as_str().map(str::to_string);
+    entry.last_modified = response["LastModified"].as_str().map(str::to_string);
+    entry.save()?;
+
+    let mut file = OpenOptions::new().create(true).append(true).open(part)?;
+    io::copy(&mut fs::File::open(&piece)?, &mut file)?;
+    fs::remove_file(&piece)?;
+    // The whole object's size follows the range of a piece.
+    let total = response["ContentRange"]
+        .as_str()
+        .and_then(|r| r.rsplit('/').next())
+        .and_then(|t| t.parse::<u64>().ok());
+    let size = fs::metadata(part)?.len();
+    if let Some(total) = total.filter(|total| size != *total) {
+        fs::remove_file(part)?;
+        bail!("resumed download has {size} of {total} bytes, restarting");
+    }
+    Ok(Outcome::Downloaded)
+}
+
+#[cfg(not(feature = "s3"))]
+async fn download_s3(
+    url: &str,
+    _part: &Path,
+    _entry: &mut Entry,
+    _conditional: bool,
+) -> Result<Outcome> {
+    bail!("s3:// downloads need a build with the s3 feature: {url}")
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
@@ -0,0 +1,97 @@
+use anyhow::{bail, Context, Result};
+
+use crate::schema;
+use pg_nifty_dump::pipeline::Batch;
+
+/// Daily exchange rates of one currency against INR.
+pub struct FxRates {
+    currency: String,
+    /// `(date, INR per unit of currency)`, sorted by date.
+    rates: Vec<(String, f64)>,
+}
+
+impl FxRates {
+    pub fn load(path: &str, currency: &str) -> Result<Self> {
+        let currency = currency.to_uppercase();
+        let mut rdr =
+            csv::Reader::from_path(path).with_context(|| format!("opening fx rates: {path}"))?;
+        let mut rates = Vec::new();
+        for (line, record) in rdr.records().enumerate() {
+            let record = record.with_context(|| format!("reading fx rates: {path}"))?;
+            let (Some(date), Some(cur), Some(rate)) = (record.get(0), record.get(1), record.get(2))
+            else {
+                bail!("malformed fx rate on line {}: {record:?}", line + 2);
+            };
+            if !cur.eq_ignore_ascii_case(&currency) {
+                continue;
+            }
+            let rate: f64 = rate
+                .trim()
+                .parse()
+                .with_context(|| format!("invalid fx rate on line {}: {rate}", line + 2))?;
+            if rate <= 0.0 {
+                bail!("non-positive fx rate on line {}: {rate}", line + 2);
+            }
+            rates.push((day(date).to_string(), rate));
+        }
+        if rates.is_empty() {
+            bail!("no {currency} rates in {path}");
+        }
+        rates.sort_by(|a, b| a.0.cmp(&b.0));
+
+        Ok(FxRates { currency, rates })
+    }
+
+    /// Rate in effect on `date`: the latest one published on or before it, so
+    /// market holidays of the FX source fall back to the previous fixing.
+    fn rate_on(&self, date: &str) -> Option<f64> {
+        let date = day(date);
+        let i = self.rates.partition_point(|(d, _)| d.as_str() <= date);
+        i.checked_sub(1).map(|i| self.rates[i].1)
+    }
+
+    /// Converts the batch's price columns and records the rate used in an
+    /// extra `fx_rate` column.
+    pub fn convert(&self, batch: &mut Batch) -> Result<()> {
+        let date = batch.column("date").context("missing date column")?;
+        let prices: Vec<usize> = schema::price_columns()
+            .iter()
+            .filter_map(|c| batch.column(c))
+            .collect();
+
+        for record in &mut batch.records {
+            let row_date = String::from_utf8_lossy(&record[date]).into_owned();
+            let Some(rate) = self.rate_on(&row_date) else {
+                bail!("no {} rate on or before {row_date}", self.currency);
+            };
+
+            let mut converted =
+                csv::ByteRecord::with_capacity(record.as_slice().len(), record.len() + 1);
+            for (i, field) in record.iter().enumerate() {
+                let value = std::str::from_utf8(field)
+                    .ok()
+                    .and_then(|f| f.trim().parse::<f64>().ok());
+                match value {
+                    Some(v) if prices.contains(&i) => {
+                        converted.push_field((v / rate).to_string().as_bytes())
+                    }
+                    _ => converted.push_field(field),
+                }
+            }
+            converted.push_field(rate.to_string().as_bytes());
+            *record = converted;
+        }
+
+        batch.columns.push("fx_rate".to_string());
+        batch
+            .extra_columns
+            .push(("fx_rate".to_string(), "double precision".to_string()));
+        Ok(())
+    }
+}
+
+/// The `YYYY-MM-DD` part of a date or timestamp.
+pub fn day(date: &str) -> &str {
+    let date = date.trim();
+    date.get(..10).unwrap_or(date)
+}
@@ -0,0 +1,263 @@
+use anyhow::{bail, Context, Result};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+
+use crate::schema;
+
+/// Persisted mapping from file headers to canonical column names, stored as
+/// a two-column CSV (`header,column`).
+#[derive(Debug, Default)]
+pub struct ColumnMap {
+    path: Option<PathBuf>,
+    entries: BTreeMap<String, String>,
+    dirty: bool,
+}
+
+impl ColumnMap {
+    pub fn load(path: Option<&str>) -> Result<Self> {
+        let mut map = ColumnMap {
+            path: path.map(PathBuf::from),
+            ..Default::default()
+        };
+        let Some(path) = &map.path else {
+            return Ok(map);
+        };
+        if !path.exists() {
+            return Ok(map);
+        }
+
+        let mut rdr = csv::Reader::from_path(path)
+            .with_context(|| format!("opening column map: {}", path.display()))?;
+        for record in rdr.records() {
+            let record =
+                record.with_context(|| format!("reading column map: {}", path.display()))?;
+            let (Some(header), Some(column)) = (record.get(0), record.get(1)) else {
+                bail!("malformed column map entry: {record:?}");
+            };
+            if !schema::is_column(column) {
+                bail!("column map targets unknown column: {column}");
+            }
+            map.entries.insert(header.to_string(), column.to_string());
+        }
+        Ok(map)
+    }
+
+    /// Writes the map if it changed, returning where it went.
+    pub fn save(&mut self) -> Result<Option<&Path>> {
+        let Some(path) = &self.path else {
+            return Ok(None);
+        };
+        if !self.dirty {
+            return Ok(None);
+        }
+
+        let mut wtr = csv::Writer::from_path(path)
+            .with_context(|| format!("writing column map: {}", path.display()))?;
+        wtr.write_record(["header", "column"])?;
+        for (header, column) in &self.entries {
+            wtr.write_record([header, column])?;
+        }
+        wtr.flush()?;
+        self.dirty = false;
+        Ok(Some(path))
+    }
+
+    /// SHA-256 of everything a header is resolved against: the canonical
+    /// columns and the map's entries.
+    pub fn digest(&self) -> String {
+        let mut hasher = Sha256::new();
+        for column in schema::columns() {
+            hasher.update(column.as_bytes());
+            hasher.update([0]);
+        }
+        for (header, column) in &self.entries {
+            hasher.update(header.as_bytes());
+            hasher.update([0]);
+            hasher.update(column.as_bytes());
+            hasher.update([0]);
+        }
+        format!("{:x}", hasher.finalize())
+    }
+
+    fn insert(&mut self, header: &str, column: &str) {
+        self.entries.insert(header.to_string(), column.to_string());
+        self.dirty = true;
+    }
+}
+
+/// Resolves every header of a file to a canonical column, in file order.
+///
+/// Exact names and column map entries are taken as-is. Near misses (e.g.
+/// `EMA_10` for `ema10`) are suggested, and in interactive mode the user can
+/// accept the suggestion, which is recorded in the column map.
+pub fn resolve(
+    headers: &csv::StringRecord,
+    map: &mut ColumnMap,
+    interactive: bool,
+) -> Result<Vec<String>> {
+    let canonical = schema::columns();
+    let mut resolved: Vec<String> = Vec::with_capacity(headers.len());
+
+    for header in headers {
+        // Combined files carry the symbol of each row.
+        if header.eq_ignore_ascii_case("symbol") {
+            resolved.push("symbol".to_string());
+            continue;
+        }
+        if canonical.contains(&header) {
+            resolved.push(header.to_string());
+            continue;
+        }
+        // Adjusted prices, however spelled, e.g. `Adj Close`.
+        if let Some(adjusted) = schema::ADJUSTED_COLUMNS
+            .iter()
+            .find(|c| normalise(c) == normalise(header))
+        {
+            resolved.push(adjusted.to_string());
+            continue;
+        }
+        if let Some(column) = map.entries.get(header) {
+            resolved.push(column.clone());
+            continue;
+        }
+
+        let unused: Vec<&str> = canonical
+            .iter()
+            .filter(|c| !headers.iter().any(|h| h == **c))
+            .filter(|c| !resolved.iter().any(|r| r == *c))
+            .copied()
+            .collect();
+        let Some(suggestion) = suggest(header, &unused) else {
+            bail!("unknown column {header:?}");
+        };
+
+        if !interactive {
+            bail!("unknown column {header:?}, did you mean {suggestion:?}? (use --interactive to accept)");
+        }
+        if !confirm(&format!("Map column {header:?} to {suggestion:?}?"))? {
+            bail!("unknown column {header:?}");
+        }
+        map.insert(header, suggestion);
+        resolved.push(suggestion.to_string());
+    }
+
+    for column in &canonical {
+        if !resolved.iter().any(|r| r == column) {
+            bail!("missing column {column:?}");
+        }
+    }
+    let canonical_count = resolved
+        .iter()
+        .filter(|r| *r != "symbol" && !schema::ADJUSTED_COLUMNS.contains(&r.as_str()))
+        .count();
+    if canonical_count != canonical.len() {
+        bail!("duplicate columns in header");
+    }
+    Ok(resolved)
+}
+
+/// Closest candidate to `header`, comparing names with case, `_`, `-` and
+/// spaces ignored.
+fn suggest<'a>(header: &str, candidates: &[&'a str]) -> Option<&'a str> {
+    let needle = normalise(header);
+    let max_distance = (needle.len() / 4).max(1);
+    candidates
+        .iter()
+        .map(|c| (levenshtein(&needle, &normalise(c)), *c))
+        .filter(|(d, _)| *d <= max_distance)
+        .min_by_key(|(d, _)| *d)
+        .map(|(_, c)| c)
+}
+
+fn normalise(s: &str) -> String {
+    s.chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .map(|c| c.to_ascii_lowercase())
+        .collect()
+}
+
+fn levenshtein(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut prev: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut cur = vec![i + 1; b.len() + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let cost = usize::from(ca != *cb);
+            cur[j + 1] = (prev[j] + cost).min(prev[j + 1] + 1).min(cur[j] + 1);
+        }
+        prev = cur;
+    }
+    prev[b.len()]
+}
+
+/// Asks on stderr, keeping stdout free for progress lines.
+pub fn confirm(question: &str) -> Result<bool> {
+    eprint!("{question} [y/N] ");
+    io::stderr().flush()?;
+    let mut answer = String::new();
+    io::stdin().lock().read_line(&mut answer)?;
+    Ok(matches!(answer.trim(), "y" | "Y" | "yes"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The canonical header with `column` spelled as `header`.
+    fn headers(column: &str, header: &str) -> csv::StringRecord {
+        schema::columns()
+            .into_iter()
+            .map(|c| if c == column { header } else { c })
+            .collect()
+    }
+
+    #[test]
+    fn suggest_takes_the_near_miss() {
+        assert_eq!(suggest("EMA_10", &["ema5", "ema10"]), Some("ema10"));
+        assert_eq!(suggest("Slow-K", &["slowk", "slowd"]), Some("slowk"));
+    }
+
+    #[test]
+    fn suggest_breaks_ties_by_candidate_order() {
+        assert_eq!(suggest("sma25", &["sma20", "sma15"]), Some("sma20"));
+        assert_eq!(suggest("sma25", &["sma15", "sma20"]), Some("sma15"));
+    }
+
+    #[test]
+    fn suggest_gives_up_beyond_the_distance() {
+        assert_eq!(suggest("turnover", &["volume", "trange"]), None);
+        // One edit is allowed however short the name.
+        assert_eq!(suggest("SRA", &["SAR"]), None);
+        assert_eq!(suggest("SA", &["SAR"]), Some("SAR"));
+    }
+
+    #[test]
+    fn resolve_takes_the_canonical_header() {
+        let mut map = ColumnMap::default();
+        let resolved = resolve(&headers("", ""), &mut map, false).unwrap();
+        assert_eq!(resolved, schema::columns());
+    }
+
+    #[test]
+    fn resolve_suggests_a_near_miss() {
+        let mut map = ColumnMap::default();
+        let err = resolve(&headers("ema10", "EMA_10"), &mut map, false).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "unknown column \"EMA_10\", did you mean \"ema10\"? (use --interactive to accept)"
+        );
+
+        map.insert("EMA_10", "ema10");
+        let resolved = resolve(&headers("ema10", "EMA_10"), &mut map, false).unwrap();
+        assert_eq!(resolved, schema::columns());
+    }
+
+    #[test]
+    fn resolve_rejects_an_unlike_header() {
+        let mut map = ColumnMap::default();
+        let err = resolve(&headers("BETA", "turnover"), &mut map, false).unwrap_err();
+        assert_eq!(err.to_string(), "unknown column \"turnover\"");
+    }
+}
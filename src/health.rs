@@ -0,0 +1,112 @@
+use anyhow::{Context, Result};
+use serde_json::json;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::Mutex;
+use tokio_postgres::Client;
+
+use crate::ConnectionArgs;
+
+/// Loader state exposed to the probes.
+pub struct Health {
+    backlog: AtomicUsize,
+}
+
+impl Health {
+    pub fn set_backlog(&self, files: usize) {
+        self.backlog.store(files, Ordering::Relaxed);
+    }
+
+    pub fn file_done(&self) {
+        let _ = self
+            .backlog
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |b| b.checked_sub(1));
+    }
+}
+
+/// Serves `/healthz` (liveness) and `/readyz` (readiness) on `addr`.
+///
+/// Both report database connectivity, watch-directory accessibility and the
+/// backlog of files found but not yet loaded; `/readyz` answers 503 unless
+/// the database and directory are usable.
+pub async fn serve(addr: &str, conn: ConnectionArgs, dir: PathBuf) -> Result<Arc<Health>> {
+    let listener = TcpListener::bind(addr)
+        .await
+        .with_context(|| format!("binding health endpoint: {addr}"))?;
+    let health = Arc::new(Health {
+        backlog: AtomicUsize::new(0),
+    });
+
+    let state = health.clone();
+    // The probes use their own connection so a busy COPY does not stall them.
+    let client: Arc<Mutex<Option<Client>>> = Arc::new(Mutex::new(None));
+    tokio::spawn(async move {
+        loop {
+            let Ok((stream, _)) = listener.accept().await else {
+                continue;
+            };
+            let (state, conn, dir, client) =
+                (state.clone(), conn.clone(), dir.clone(), client.clone());
+            tokio::spawn(async move {
+                let _ = respond(stream, &state, &conn, &dir, &client).await;
+            });
+        }
+    });
+
+    Ok(health)
+}
+
+async fn respond(
+    mut stream: TcpStream,
+    health: &Health,
+    conn: &ConnectionArgs,
+    dir: &Path,
+    client: &Mutex<Option<Client>>,
+) -> Result<()> {
+    let mut buf = [0; 1024];
+    let n = stream.read(&mut buf).await?;
+    let request = String::from_utf8_lossy(&buf[..n]);
+    let path = request.split_whitespace().nth(1).unwrap_or("/");
+
+    let (status, body) = match path {
+        "/healthz" | "/readyz" => {
+            let database = database_ok(conn, client).await;
+            let watch_dir = std::fs::read_dir(dir).is_ok();
+            let ready = database && watch_dir;
+            let status = if path == "/readyz" && !ready {
+                "503 Service Unavailable"
+            } else {
+                "200 OK"
+            };
+            let body = json!({
+                "database": database,
+                "watch_dir": watch_dir,
+                "backlog": health.backlog.load(Ordering::Relaxed),
+            });
+            (status, body.to_string())
+        }
+        _ => ("404 Not Found", json!({ "error": "not found" }).to_string()),
+    };
+
+    let response = format!(
+        "HTTP/1.1 {status}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    );
+    stream.write_all(response.as_bytes()).await?;
+    Ok(())
+}
+
+/// Runs `select 1`, reconnecting when the cached connection is gone.
+async fn database_ok(conn: &ConnectionArgs, client: &Mutex<Option<Client>>) -> bool {
+    let mut client = client.lock().await;
+    if client.as_ref().is_none_or(|c| c.is_closed()) {
+        *client = crate::connect(conn).await.ok();
+    }
+    match client.as_ref() {
+        Some(c) => c.execute("select 1", &[]).await.is_ok(),
+        None => false,
+    }
+}
@@ -0,0 +1,65 @@
+use crate::quality;
+use crate::schema::IdentifierPolicy;
+
+/// Column names and types for a CSV of unknown layout, judged from the first
+/// `sample` rows: `bigint`, `numeric`, `date` or `timestamptz` when every
+/// non-empty value parses as one, `text` otherwise. Names go through the
+/// identifier policy, with repeats numbered.
+pub fn column_types(
+    headers: &csv::StringRecord,
+    records: &[csv::ByteRecord],
+    sample: usize,
+    identifiers: &IdentifierPolicy,
+) -> Vec<(String, &'static str)> {
+    let mut columns: Vec<(String, &'static str)> = Vec::with_capacity(headers.len());
+    for (i, header) in headers.iter().enumerate() {
+        let values: Vec<&str> = records
+            .iter()
+            .take(sample)
+            .filter_map(|r| std::str::from_utf8(r.get(i)?).ok())
+            .map(str::trim)
+            .filter(|v| !v.is_empty())
+            .collect();
+
+        let mut name = identifiers.apply(header);
+        let mut n = 1;
+        while columns.iter().any(|(c, _)| *c == name) {
+            n += 1;
+            name = identifiers.apply(&format!("{header}_{n}"));
+        }
+        columns.push((name, infer(&values)));
+    }
+    columns
+}
+
+fn infer(values: &[&str]) -> &'static str {
+    if values.is_empty() {
+        return "text";
+    }
+    let all = |f: fn(&str) -> bool| values.iter().all(|v| f(v));
+    if all(|v| v.parse::<i64>().is_ok()) {
+        "bigint"
+    } else if all(|v| v.parse::<f64>().is_ok_and(|f| f.is_finite())) {
+        "numeric"
+    } else if all(|v| v.len() == 10 && quality::days_from_date(v.as_bytes()).is_some()) {
+        "date"
+    } else if all(is_timestamp) {
+        "timestamptz"
+    } else {
+        "text"
+    }
+}
+
+/// `YYYY-MM-DD` followed by a time, e.g. `2024-01-02 09:15:00+05:30`.
+fn is_timestamp(v: &str) -> bool {
+    let Some(time) = v.get(10..) else {
+        return false;
+    };
+    quality::days_from_date(v.as_bytes()).is_some()
+        && (time.starts_with(' ') || time.starts_with('T'))
+        && time[1..].get(0..5).is_some_and(|t| {
+            t.as_bytes()[2] == b':'
+                && t[0..2].bytes().all(|b| b.is_ascii_digit())
+                && t[3..5].bytes().all(|b| b.is_ascii_digit())
+        })
+}
@@ -0,0 +1,167 @@
+use anyhow::{bail, Context, Result};
+use clap::Args;
+use std::fs;
+use std::io::{self, BufRead, Write};
+
+use crate::header::confirm;
+use crate::{profile, service, ConnectionArgs};
+
+#[derive(Debug, Args)]
+pub struct InitArgs {
+    /// Name of the profile to write, used as `--profile <name>`.
+    #[clap(long = "name", default_value = "default")]
+    name: String,
+}
+
+/// Asks for the connection, input directory and table layout, checks them
+/// where it can, and saves them as a profile later runs pick up with
+/// `--profile`.
+pub async fn run(conn: &ConnectionArgs, args: &InitArgs) -> Result<()> {
+    let path = profile::path()?;
+    let existing = fs::read_to_string(&path).unwrap_or_default();
+    if service::section(&existing, &args.name).is_some()
+        && !confirm(&format!(
+            "Profile {} already exists in {}. Replace it?",
+            args.name,
+            path.display()
+        ))?
+    {
+        bail!("setup cancelled");
+    }
+    let mut options: Vec<(&str, String)> = Vec::new();
+
+    // Connection.
+    println!("\nConnection");
+    let mut conn = conn.clone();
+    let name = ask(
+        "pg_service.conf service to connect with (blank to enter the server)",
+        "",
+    )?;
+    if name.is_empty() {
+        let host = ask("Host", "localhost")?;
+        let port = ask("Port", "5432")?;
+        let dbname = ask("Database", "nifty_stocks")?;
+        let user = ask("User", "postgres")?;
+        conn.uri = format!("host={host} port={port} dbname={dbname} user={user}");
+        conn.service = None;
+        options.push(("uri", conn.uri.clone()));
+    } else {
+        conn.service = Some(name.clone());
+        options.push(("service", name));
+    }
+    let secret = ask(
+        "Secret holding the password, e.g. vault:secret/nifty#password (blank for none)",
+        "",
+    )?;
+    if !secret.is_empty() {
+        conn.secret_ref = Some(secret.clone());
+        options.push(("secret-ref", secret));
+    }
+    #[cfg(feature = "ssh")]
+    if let Some(bastion) = &conn.ssh_tunnel {
+        options.push(("ssh-tunnel", bastion.clone()));
+        if let Some(port) = conn.ssh_port {
+            options.push(("ssh-port", port.to_string()));
+        }
+        if let Some(identity) = &conn.ssh_identity {
+            options.push(("ssh-identity", identity.clone()));
+        }
+    }
+
+    // Whether TimescaleDB is installable, when the server answers.
+    let timescaledb = match crate::connect(&conn).await {
+        Ok(client) => {
+            let row = client
+                .query_one(
+                    "select current_setting('server_version'),
+                            exists (select from pg_available_extensions where name = 'timescaledb')",
+                    &[],
+                )
+                .await
+                .context("querying server")?;
+            println!("Connected to PostgreSQL {}", row.get::<_, String>(0));
+            Some(row.get::<_, bool>(1))
+        }
+        Err(e) => {
+            println!("Could not connect: {e:#}");
+            if !confirm("Save the profile anyway?")? {
+                bail!("setup cancelled");
+            }
+            None
+        }
+    };
+
+    // Input files.
+    println!("\nInput");
+    let dir = ask("Directory of the stock CSV files", ".")?;
+    let (dir, files) = match fs::read_dir(&dir) {
+        Ok(entries) => {
+            let files = entries
+                .filter_map(Result::ok)
+                .filter(|e| {
+                    e.path()
+                        .extension()
+                        .is_some_and(|ext| ext.eq_ignore_ascii_case("csv"))
+                })
+                .count();
+            println!("Found {files} CSV files");
+            let dir = fs::canonicalize(&dir).with_context(|| format!("resolving {dir}"))?;
+            (dir.display().to_string(), files)
+        }
+        Err(_) => {
+            println!("{dir} does not exist yet");
+            (dir, 0)
+        }
+    };
+    options.push(("dir", dir));
+
+    // Tables.
+    println!("\nTables");
+    let single = confirm(
+        "Load every file into one table with a symbol column, instead of a table per symbol?",
+    )?;
+    if single {
+        options.push(("single-table", ask("Table name", "stocks")?));
+    }
+    let max_tables = ask(
+        "Maximum number of tables to load",
+        &files.max(1000).to_string(),
+    )?;
+    let max_tables: i32 = max_tables
+        .parse()
+        .with_context(|| format!("not a number: {max_tables}"))?;
+    options.push(("max-tables", max_tables.to_string()));
+    match timescaledb {
+        Some(false) => println!("TimescaleDB is not available on the server; using plain tables"),
+        _ => {
+            if confirm("Are the tables distributed TimescaleDB hypertables?")? {
+                let jobs = ask("Connections loading each one", "4")?;
+                let jobs: usize = jobs
+                    .parse()
+                    .with_context(|| format!("not a number: {jobs}"))?;
+                options.push(("distributed-jobs", jobs.to_string()));
+            }
+        }
+    }
+
+    let path = profile::save(&args.name, &options)?;
+    println!("\nSaved profile {} to {}", args.name, path.display());
+    println!("Load with: pg_nifty_dump --profile {}", args.name);
+    Ok(())
+}
+
+/// Prompts for a line, returning `default` when it is left empty.
+fn ask(question: &str, default: &str) -> Result<String> {
+    if default.is_empty() {
+        print!("{question}: ");
+    } else {
+        print!("{question} [{default}]: ");
+    }
+    io::stdout().flush()?;
+    let mut answer = String::new();
+    if io::stdin().lock().read_line(&mut answer)? == 0 {
+        bail!("setup cancelled");
+    }
+    let answer = answer.trim();
+    Ok(if answer.is_empty() { default } else { answer }.to_string())
+}
@@ -0,0 +1,8 @@
+//! Library side of pg_nifty_dump: the pluggable [`pipeline`] stages the
+//! loader is built from, so new input formats and targets can be added
+//! without changing the loader itself.
+
+pub mod compression;
+pub mod copy;
+pub mod pipeline;
+pub mod postgres;
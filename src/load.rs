@@ -0,0 +1,2320 @@
+use anyhow::{bail, Context, Result};
+use clap::{Args, ValueEnum};
+use futures::channel::mpsc;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio_postgres::types::ToSql;
+use tokio_postgres::Client;
+
+use crate::audit;
+use crate::definition;
+use crate::derivatives;
+use crate::events::Events;
+use crate::fetch;
+use crate::fx::FxRates;
+use crate::header::{self, ColumnMap};
+use crate::health;
+use crate::infer;
+use crate::manifest::{self, FileEntry, HeaderEntry, Manifest, PartialEntry};
+use crate::progress::{self, Jsonl, Plain, Reporter};
+use crate::quality::{Quality, RowCheck};
+use crate::queue::Queue;
+use crate::ranks;
+use crate::replay;
+use crate::report::{FileReport, ResourceUsage, RunReport, Status};
+use crate::schema::{self, IdentifierPolicy, ReservedWords};
+use crate::telemetry::Telemetry;
+use crate::tls::{LazyTls, TlsArgs};
+use crate::transform::{self, AdjustedSeries, PercentRule, TrimWarmup};
+#[cfg(feature = "tui")]
+use crate::tui::Dashboard;
+use crate::universe::Universe;
+use crate::ConnectionArgs;
+use pg_nifty_dump::compression;
+use pg_nifty_dump::copy::CommitEvery;
+use pg_nifty_dump::pipeline::{
+    Batch, CsvOptions, CsvSource, Layout, Loaded, NotifySink, Registry, Script, Sink, Source,
+    SqlFileSink, TeeSink,
+};
+use pg_nifty_dump::postgres::{self, PostgresSink, RelationKind};
+
+/// Rows handed to the sink at once when a file is streamed.
+const STREAM_CHUNK_ROWS: usize = 100_000;
+
+#[derive(Debug, Args)]
+pub struct LoadArgs {
+    /// Path for stock files.
+    #[clap(short, long = "dir", required_unless_present = "replay")]
+    dir: Option<String>,
+
+    /// Maximum number of tables to be dumped.
+    #[clap(short, long = "max-tables", required_unless_present = "replay")]
+    max_tables: Option<i32>,
+
+    /// Also write every statement and row of the load to this psql script,
+    /// which --replay can run again.
+    #[clap(long = "record", conflicts_with = "sink")]
+    record: Option<String>,
+
+    /// Run a script written by --record against the database instead of
+    /// loading files.
+    #[clap(long = "replay", conflicts_with = "record")]
+    replay: Option<String>,
+
+    /// CSV file mapping file headers to canonical columns (`header,column`).
+    #[clap(long = "column-map")]
+    column_map: Option<String>,
+
+    /// Prompt to accept suggested mappings for near-miss headers.
+    #[clap(long = "interactive")]
+    interactive: bool,
+
+    /// Show a live terminal dashboard instead of log lines.
+    #[clap(long = "tui", conflicts_with_all = ["interactive", "progress_format"])]
+    tui: bool,
+
+    /// Format of the progress output.
+    #[clap(long = "progress-format", value_enum, default_value = "text")]
+    progress_format: progress::Format,
+
+    /// Lines to skip before the header, such as a download timestamp.
+    #[clap(long = "skip-lines", default_value_t = 0)]
+    skip_lines: usize,
+
+    /// Ignore lines starting with this character, e.g. `#`.
+    #[clap(long = "comment-char")]
+    comment_char: Option<char>,
+
+    /// Drop rows with an unparsable date or number, failing the file once
+    /// more than this many are found.
+    #[clap(long = "max-errors")]
+    max_errors: Option<u64>,
+
+    /// Stop the run once more than this many files failed or had an
+    /// invalid header.
+    #[clap(long = "max-failed-files")]
+    max_failed_files: Option<usize>,
+
+    /// Order files are loaded in: by name, oldest modified first, or largest
+    /// first.
+    #[clap(long = "order", value_enum, default_value_t = FileOrder::Name)]
+    order: FileOrder,
+
+    /// Columns whose quoted empty strings ("") load as null, like unquoted
+    /// empty fields. Applies to files the server reads directly.
+    #[clap(long = "force-null", value_delimiter = ',')]
+    force_null: Vec<String>,
+
+    /// Columns whose unquoted empty fields load as empty strings instead of
+    /// null. Applies to files the server reads directly.
+    #[clap(long = "force-not-null", value_delimiter = ',')]
+    force_not_null: Vec<String>,
+
+    /// Indicators ranked across symbols per date after the load, into
+    /// `<single-table>_ranks`, e.g. `RSI14,MOM10`.
+    #[clap(
+        long = "ranks",
+        value_delimiter = ',',
+        requires = "single_table",
+        conflicts_with = "sink"
+    )]
+    ranks: Vec<String>,
+
+    /// Load CSVs of any layout, creating each table with column types
+    /// inferred from the first rows instead of checking the header.
+    #[clap(long = "infer-types", conflicts_with_all = ["single_table", "format"])]
+    infer_types: bool,
+
+    /// Rows sampled by --infer-types.
+    #[clap(long = "infer-rows", default_value_t = 1000, requires = "infer_types")]
+    infer_rows: usize,
+
+    /// Only load these symbols: `nifty50` for the bundled list, or a file of
+    /// symbols (one per line, or a CSV with a `Symbol` column) such as NSE's
+    /// `ind_nifty500list.csv`.
+    #[clap(long = "universe")]
+    universe: Option<String>,
+
+    /// Share the directory's files with other hosts running with --queue,
+    /// through a `load_queue` table each host claims files from.
+    #[clap(long = "queue", conflicts_with = "sink")]
+    queue: bool,
+
+    /// Log manual updates and deletes of loaded tables into
+    /// `pg_nifty_dump_audit`.
+    #[clap(long = "audit-triggers", conflicts_with = "sink")]
+    audit_triggers: bool,
+
+    /// Once every file is loaded, rewrite the loaded tables in date order
+    /// and set their fillfactor to --fillfactor.
+    #[clap(long = "cluster-on-date", conflicts_with_all = ["watch", "sink"])]
+    cluster_on_date: bool,
+
+    /// Percentage of each page --cluster-on-date fills. The default packs
+    /// pages completely, suiting tables only ever appended to; leave room
+    /// (e.g. 90) when rows are updated in place, as with `--if-exists upsert`.
+    #[clap(
+        long = "fillfactor",
+        default_value_t = 100,
+        value_parser = clap::value_parser!(u8).range(10..=100),
+        requires = "cluster_on_date"
+    )]
+    fillfactor: u8,
+
+    /// Layout of the input files.
+    #[clap(long = "format", value_enum, default_value_t = FileFormat::Indicators)]
+    format: FileFormat,
+
+    /// Load every file into this one table with a `symbol` column, instead of
+    /// a table per symbol.
+    #[clap(long = "single-table")]
+    single_table: Option<String>,
+
+    /// For files with `adj_close` (and `adj_open`...) columns, store the raw
+    /// and adjusted series apart instead of keeping the `adj_*` columns.
+    #[clap(long = "adjusted-series", value_enum)]
+    adjusted_series: Option<AdjustedSeries>,
+
+    /// Drop the indicator warm-up rows at the start of each series.
+    #[clap(long = "trim-warmup", value_enum)]
+    trim_warmup: Option<TrimWarmup>,
+
+    /// Add rolling volatility (10/20/30 day stddev of returns) and ATR band
+    /// columns.
+    #[clap(long = "volatility")]
+    volatility: bool,
+
+    /// Threads computing --volatility, each taking one symbol at a time
+    /// [default: one per CPU].
+    #[clap(long = "compute-threads", requires = "volatility")]
+    compute_threads: Option<usize>,
+
+    /// Time zone of naive timestamps in the files, e.g. `Asia/Kolkata` for
+    /// intraday data. Values are stored as UTC.
+    #[clap(long = "source-tz")]
+    source_tz: Option<String>,
+
+    /// Keep the case of file names in table names, quoting them.
+    #[clap(long = "keep-case")]
+    keep_case: bool,
+
+    /// How file names that are SQL reserved words become table names.
+    #[clap(long = "reserved-words", value_enum, default_value_t = ReservedWords::Suffix)]
+    reserved_words: ReservedWords,
+
+    /// Pattern of table names, `{symbol}` standing for the file or symbol
+    /// name, e.g. `nse_{symbol}_daily`. See `migrate-names` for renaming
+    /// tables loaded under another one.
+    #[clap(long = "table-template")]
+    table_template: Option<String>,
+
+    /// JSON manifest recording every loaded file (hash, tables, rows, quality).
+    #[clap(long = "manifest")]
+    manifest: Option<PathBuf>,
+
+    /// Write a JSON summary of the run to this file.
+    #[clap(long = "report")]
+    report: Option<String>,
+
+    /// Write to a registered sink instead of the database, e.g.
+    /// `sql-file:dump.sql`.
+    #[clap(long = "sink")]
+    sink: Option<String>,
+
+    /// What to do with target tables that already exist.
+    #[clap(long = "if-exists", value_enum, default_value = "append")]
+    if_exists: IfExists,
+
+    /// Keep running and load files as they appear in the directory.
+    #[clap(long = "watch")]
+    watch: bool,
+
+    /// Seconds between directory scans in watch mode.
+    #[clap(long = "watch-interval", default_value_t = 10, requires = "watch")]
+    watch_interval: u64,
+
+    /// In watch mode, wait until a file has not been modified for this many
+    /// seconds before reading it, so half-written files are left alone.
+    #[clap(long = "watch-debounce", default_value_t = 5, requires = "watch")]
+    watch_debounce: u64,
+
+    /// When upserting, how rows whose date (and symbol) is already loaded
+    /// are resolved. Rows replacing a loaded one are flagged in a `revised`
+    /// column, and each row's file modification time is kept in
+    /// `source_modified`.
+    #[clap(long = "on-conflict", value_enum, conflicts_with = "sink")]
+    on_conflict: Option<OnConflict>,
+
+    /// What to do with the rows of a file that is rewritten while watching.
+    #[clap(long = "reload", value_enum, default_value_t = Reload::Replace, requires = "watch")]
+    reload: Reload,
+
+    /// Commit the client-side COPY every this many rows, so a failed load
+    /// keeps the rows before it and, with --manifest, resumes after them.
+    #[clap(long = "commit-every-rows", conflicts_with = "sink")]
+    commit_every_rows: Option<u64>,
+
+    /// Commit the client-side COPY at least this often, in seconds.
+    #[clap(long = "commit-every-seconds", conflicts_with = "sink")]
+    commit_every_seconds: Option<u64>,
+
+    /// Turn off synchronous_commit: commits return before their WAL is
+    /// flushed to disk. A crash can lose the last commits but never
+    /// corrupts the tables.
+    #[clap(long = "async-commit")]
+    async_commit: bool,
+
+    /// Connections loading into a distributed TimescaleDB hypertable, each
+    /// copying a share of the symbols through the access node.
+    #[clap(
+        long = "distributed-jobs",
+        default_value_t = 1,
+        conflicts_with = "sink"
+    )]
+    distributed_jobs: usize,
+
+    /// Export traces and metrics of the run to this OTLP/HTTP collector,
+    /// e.g. `http://localhost:4318`.
+    #[clap(long = "otlp-endpoint", env = "OTEL_EXPORTER_OTLP_ENDPOINT")]
+    otlp_endpoint: Option<String>,
+
+    /// Download this http(s):// or s3:// file into --dir before loading,
+    /// resuming interrupted transfers; append `#sha256=<hex>` to verify it.
+    #[clap(long = "fetch")]
+    fetch: Vec<String>,
+
+    /// Where partial and verified downloads are kept between runs
+    /// [default: <dir>/.downloads].
+    #[clap(long = "download-dir")]
+    download_dir: Option<String>,
+
+    #[clap(flatten)]
+    tls: TlsArgs,
+
+    /// Serve `/healthz` and `/readyz` on this address in watch mode.
+    #[clap(long = "health-addr", requires = "watch")]
+    health_addr: Option<String>,
+
+    /// CSV of exchange rates (`date,currency,rate`, rate in INR per unit)
+    /// used by --convert-to.
+    #[clap(long = "fx-rates", requires = "convert_to")]
+    fx_rates: Option<String>,
+
+    /// Convert price columns from INR into this currency at each row's date.
+    #[clap(long = "convert-to", requires = "fx_rates")]
+    convert_to: Option<String>,
+
+    /// CSV of trading halts and circuit-limit days (`date,symbol,event`,
+    /// event `halt`, `upper_circuit` or `lower_circuit`) flagged in
+    /// `is_trading_halt` and `circuit_limit` columns.
+    #[clap(long = "events")]
+    events: Option<String>,
+
+    /// What to do with a file that is empty or holds only its header. A
+    /// blank header line is a header error when rows follow it, or with fail.
+    #[clap(long = "empty-files", value_enum, default_value = "skip")]
+    empty_files: EmptyFiles,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum FileFormat {
+    /// Daily prices and indicators of one symbol, or several with a
+    /// `symbol` column.
+    Indicators,
+    /// NSE futures and options bhavcopy, loaded into a derivatives table per
+    /// underlying.
+    FoBhavcopy,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum EmptyFiles {
+    /// Create the file's table without rows.
+    Create,
+    /// Leave the file out with a warning.
+    Skip,
+    /// Fail the file.
+    Fail,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum FileOrder {
+    Name,
+    Mtime,
+    SizeDesc,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Reload {
+    /// Delete the rows loaded from the old file, then load the new one.
+    Replace,
+    /// Delete only the rows whose dates the new file has again, then load it.
+    Upsert,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum IfExists {
+    /// Leave the table untouched.
+    Skip,
+    /// Add the rows to the existing data.
+    Append,
+    /// Truncate the table, then load.
+    Replace,
+    /// Replace the rows with the same date (and symbol), see --on-conflict,
+    /// and add the rest.
+    Upsert,
+    /// Abort the run.
+    Fail,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum OnConflict {
+    /// Keep the row already loaded.
+    KeepExisting,
+    /// Replace it with the file's row.
+    TakeNew,
+    /// Keep whichever row comes from the more recently modified file.
+    NewerFile,
+}
+
+/// Rows of a table committed by a load: the first rows of its batch, or
+/// the first rows of each connection's share of a distributed batch.
+enum Progress {
+    Rows(u64),
+    Shards(Vec<u64>),
+}
+
+/// Applies --if-exists to tables that existed before this run; tables the
+/// run itself created are always appended to.
+struct Existing {
+    policy: IfExists,
+    /// Whether each table seen so far is being loaded.
+    decisions: HashMap<String, bool>,
+    /// The --record script, which the truncates of `replace` go to.
+    script: Option<Script>,
+}
+
+impl Existing {
+    async fn admit(&mut self, c: &Client, table_name: &str) -> Result<bool> {
+        if let Some(admit) = self.decisions.get(table_name) {
+            return Ok(*admit);
+        }
+
+        let exists: bool = c
+            .query_one("select to_regclass($1) is not null", &[&table_name])
+            .await
+            .with_context(|| format!("checking table => {table_name}"))?
+            .get(0);
+        let admit = match admission(exists, self.policy, table_name)? {
+            Admission::Load => true,
+            Admission::Skip => false,
+            Admission::Truncate => {
+                let query = format!("truncate table {table_name}");
+                c.execute(&query, &[])
+                    .await
+                    .with_context(|| format!("truncating table => {table_name}"))?;
+                if let Some(script) = &self.script {
+                    script.statement(&query)?;
+                }
+                true
+            }
+        };
+        self.decisions.insert(table_name.to_string(), admit);
+        Ok(admit)
+    }
+}
+
+/// What --if-exists does with a table.
+#[derive(Debug, PartialEq, Eq)]
+enum Admission {
+    Load,
+    Skip,
+    /// Empty it, then load.
+    Truncate,
+}
+
+fn admission(exists: bool, policy: IfExists, table_name: &str) -> Result<Admission> {
+    Ok(match (exists, policy) {
+        (false, _) | (true, IfExists::Append | IfExists::Upsert) => Admission::Load,
+        (true, IfExists::Skip) => Admission::Skip,
+        (true, IfExists::Replace) => Admission::Truncate,
+        (true, IfExists::Fail) => bail!("table already exists: {table_name}"),
+    })
+}
+
+pub async fn run(client: &Client, conn: &ConnectionArgs, args: &LoadArgs) -> Result<()> {
+    if let Some(script) = &args.replay {
+        let statements = replay::run(client, script).await?;
+        println!("Replayed {statements} statements from {script}");
+        return Ok(());
+    }
+    // Statements the load runs outside the sink are recorded as they
+    // succeed, the sink's batches by the `SqlFileSink`.
+    let script = args.record.as_deref().map(Script::create).transpose()?;
+    let record = |statement: &str| match &script {
+        Some(script) => script.statement(statement),
+        None => Ok(()),
+    };
+    let dir = args.dir.as_deref().context("--dir is required")?;
+    let max_tables = args.max_tables.context("--max-tables is required")?;
+    for column in args.force_null.iter().chain(&args.force_not_null) {
+        if !schema::is_column(column) {
+            bail!("unknown column in --force-null/--force-not-null: {column}");
+        }
+    }
+    if args.on_conflict.is_some()
+        && args.if_exists != IfExists::Upsert
+        && !(args.watch && args.reload == Reload::Upsert)
+    {
+        bail!("--on-conflict only applies with --if-exists upsert or --reload upsert");
+    }
+    if let Some(tz) = &args.source_tz {
+        crate::set_time_zone(client, tz).await?;
+        for statement in crate::time_zone_statements(tz) {
+            record(&statement)?;
+        }
+    }
+    if args.async_commit {
+        client
+            .batch_execute("set synchronous_commit to off")
+            .await
+            .context("turning off synchronous_commit")?;
+    }
+    let mut registry = Registry::with_builtins();
+    let csv_options = CsvOptions {
+        skip_lines: args.skip_lines,
+        comment: match args.comment_char {
+            Some(c) if c.is_ascii() => Some(c as u8),
+            Some(c) => bail!("--comment-char must be an ASCII character: {c:?}"),
+            None => None,
+        },
+    };
+    registry.register_source("csv", move |path| {
+        Ok(Box::new(CsvSource::open_with(path, csv_options)?))
+    });
+    let commit_every = CommitEvery {
+        rows: args.commit_every_rows,
+        seconds: args.commit_every_seconds,
+    };
+    let committed = Arc::new(AtomicU64::new(0));
+    let shards = Arc::new(Mutex::new(Vec::new()));
+    let mut workers = Vec::new();
+    for _ in 1..args.distributed_jobs {
+        let worker = crate::connect(conn).await?;
+        if let Some(tz) = &args.source_tz {
+            crate::set_time_zone(&worker, tz).await?;
+        }
+        workers.push(worker);
+    }
+    let postgres_sink = PostgresSink::new(client)
+        .commit_every(commit_every, committed.clone())
+        .distribute_over(workers, shards.clone());
+    let sink: Box<dyn Sink + '_> = match (&args.sink, &script) {
+        (Some(spec), _) => registry.open_sink(spec)?,
+        (None, Some(script)) => Box::new(TeeSink::new(
+            postgres_sink,
+            SqlFileSink::new(script.clone()),
+        )),
+        (None, None) => Box::new(postgres_sink),
+    };
+    let (sink, loaded) = NotifySink::new(sink);
+    if let Some(template) = &args.table_template {
+        schema::check_template(template)?;
+    }
+    let identifiers = IdentifierPolicy {
+        keep_case: args.keep_case,
+        reserved_words: args.reserved_words,
+        table_template: args.table_template.clone(),
+    };
+    let reporter: Box<dyn Reporter> = match (args.tui, args.progress_format) {
+        #[cfg(feature = "tui")]
+        (true, _) => Box::new(Dashboard::start()?),
+        #[cfg(not(feature = "tui"))]
+        (true, _) => bail!("--tui needs a build with the tui feature"),
+        (false, progress::Format::Text) => Box::new(Plain),
+        (false, progress::Format::Jsonl) => Box::new(Jsonl::default()),
+    };
+    let mut loader = Loader {
+        client,
+        args,
+        column_map: ColumnMap::load(args.column_map.as_deref())?,
+        identifiers: identifiers.clone(),
+        fx: match (&args.fx_rates, &args.convert_to) {
+            (Some(path), Some(currency)) => Some(FxRates::load(path, currency)?),
+            _ => None,
+        },
+        events: args
+            .events
+            .as_deref()
+            .map(|path| Events::load(path, &identifiers))
+            .transpose()?,
+        compute: args
+            .volatility
+            .then(|| transform::compute_pool(args.compute_threads))
+            .transpose()?,
+        existing: Existing {
+            policy: args.if_exists,
+            decisions: HashMap::new(),
+            script: script.clone(),
+        },
+        reporter,
+        sink: Box::new(sink),
+        loaded,
+        manifest: args.manifest.as_deref().map(Manifest::load).transpose()?,
+        report: RunReport::default(),
+        reload: None,
+        universe: args.universe.as_deref().map(Universe::load).transpose()?,
+        telemetry: args
+            .otlp_endpoint
+            .as_deref()
+            .map(|endpoint| Telemetry::new(endpoint, &args.tls))
+            .transpose()?,
+        percent: Vec::new(),
+        commit_every,
+        committed,
+        shards,
+        script,
+    };
+    // The reporter hears how the run ended, failed or not.
+    let result: Result<()> = async {
+        if let Some(universe) = &loader.universe {
+            loader.reporter.log(&format!(
+                "Restricting the load to {} symbols",
+                universe.len()
+            ));
+        }
+
+        if let Some(table_name) = &args.single_table {
+            if !loader.existing.admit(client, table_name).await? {
+                loader
+                    .reporter
+                    .log(&format!("Skipping {table_name}: table already exists"));
+                return Ok(());
+            }
+        }
+
+        let health = match &args.health_addr {
+            Some(addr) => Some(health::serve(addr, conn.clone(), PathBuf::from(dir)).await?),
+            None => None,
+        };
+
+        if args.audit_triggers {
+            audit::prepare(client, loader.script.as_ref()).await?;
+        }
+        let queue = if args.queue {
+            Some(Queue::open(client).await?)
+        } else {
+            None
+        };
+        if !args.fetch.is_empty() {
+            let cache = match &args.download_dir {
+                Some(cache) => PathBuf::from(cache),
+                None => Path::new(dir).join(".downloads"),
+            };
+            let tls = LazyTls::new(&args.tls);
+            for url in &args.fetch {
+                let path = fetch::fetch(url, &cache, Path::new(dir), &tls).await?;
+                loader
+                    .reporter
+                    .log(&format!("Downloaded {url} => {}", path.display()));
+            }
+        }
+        // Size and modification time of each file when it was handled.
+        let mut seen: HashMap<PathBuf, (SystemTime, u64)> = HashMap::new();
+        let settle = Duration::from_secs(if args.watch { args.watch_debounce } else { 0 });
+        let mut dump_count = 0;
+        let mut failed_files = 0;
+        let mut unchanged = 0;
+        'watch: loop {
+            let pending = pending_files(dir, &registry, &seen, args.order, settle)?;
+            if let Some(health) = &health {
+                health.set_backlog(pending.len());
+            }
+
+            if let Some(queue) = &queue {
+                queue.enqueue(client, &pending).await?;
+            }
+            let mut pending = pending.into_iter();
+
+            loop {
+                if max_tables > 0 && dump_count > max_tables {
+                    break 'watch;
+                }
+                let next = match &queue {
+                    Some(queue) => queue.claim(client, dir).await?,
+                    None => pending.next(),
+                };
+                let Some((path, modified, size)) = next else {
+                    break;
+                };
+                let rewritten = seen.insert(path.clone(), (modified, size)).is_some();
+                if rewritten {
+                    let file_name = path.file_name().unwrap().to_string_lossy();
+                    loader
+                        .reporter
+                        .log(&format!("{file_name} was rewritten, reloading"));
+                }
+                loader.reload = (rewritten && args.sink.is_none()).then_some(args.reload);
+                if loader.unchanged(&path).await? {
+                    let file_name = path.file_name().unwrap().to_string_lossy().into_owned();
+                    loader.reporter.log(&format!("{file_name} unchanged"));
+                    loader.reporter.file_finished(&file_name, Status::Unchanged);
+                    loader
+                        .report
+                        .add(FileReport::new(&file_name, Status::Unchanged));
+                    unchanged += 1;
+                    if let Some(health) = &health {
+                        health.file_done();
+                    }
+                    if let Some(queue) = &queue {
+                        queue.finish(client, &path, Status::Unchanged, None).await?;
+                    }
+                    continue;
+                }
+                dump_count += 1;
+
+                let file_name = path.file_name().unwrap().to_string_lossy().into_owned();
+                if let Some(telemetry) = &mut loader.telemetry {
+                    telemetry.start_file(&file_name);
+                }
+                let result = match registry.open_source(&path) {
+                    Ok(source) => loader.load_file(&path, source).await,
+                    Err(e) => Err(e),
+                };
+                if let Some(health) = &health {
+                    health.file_done();
+                }
+                if let Some(queue) = &queue {
+                    let (status, error) = match &result {
+                        Ok(report) => (report.status, report.error.clone()),
+                        Err(e) => (Status::Failed, Some(format!("{e:#}"))),
+                    };
+                    queue
+                        .finish(client, &path, status, error.as_deref())
+                        .await?;
+                }
+                match result {
+                    Ok(report) => {
+                        if report.status == Status::Invalid {
+                            failed_files += 1;
+                        }
+                        if args.audit_triggers {
+                            for table_name in &report.tables {
+                                audit::install(client, table_name, loader.script.as_ref()).await?;
+                            }
+                        }
+                        loader.end_file(&report).await;
+                        loader.record(&path, report)?;
+                    }
+                    // A daemon, or a run allowed some failures, keeps going; the
+                    // failure is reported and the file is not retried until it
+                    // changes.
+                    Err(e) => {
+                        failed_files += 1;
+                        loader.reporter.file_failed(&file_name, &format!("{e:#}"));
+                        let mut report = FileReport::new(&file_name, Status::Failed);
+                        report.error = Some(format!("{e:#}"));
+                        loader.end_file(&report).await;
+                        loader.record(&path, report)?;
+                        if !args.watch && args.max_failed_files.is_none() {
+                            loader.write_report()?;
+                            return Err(e);
+                        }
+                    }
+                }
+                if let Some(max) = args.max_failed_files.filter(|max| failed_files > *max) {
+                    loader.write_report()?;
+                    bail!("{failed_files} files failed, more than --max-failed-files {max}");
+                }
+            }
+
+            if !args.watch {
+                break;
+            }
+            tokio::time::sleep(Duration::from_secs(args.watch_interval)).await;
+        }
+
+        loader.sink.finish().await?;
+        if args.sink.is_none() {
+            loader.summarize_data_nodes().await?;
+        }
+        if args.cluster_on_date {
+            loader.cluster_on_date().await?;
+        }
+        if let (Some(table_name), false) = (&args.single_table, args.ranks.is_empty()) {
+            loader.reporter.log(&format!(
+                "Ranking {} across symbols...",
+                args.ranks.join(",")
+            ));
+            let rows = ranks::compute(client, table_name, &args.ranks, loader.script.as_ref())
+                .await
+                .with_context(|| format!("error ranking table: {table_name}"))?;
+            loader
+                .reporter
+                .log(&format!("Wrote {rows} rows to {table_name}_ranks"));
+        }
+        if let Some(script) = &loader.script {
+            script.flush()?;
+        }
+        loader.write_report()?;
+        if let Some(usage) = loader.report.resources {
+            loader.reporter.log(&format!("Resources: {usage}"));
+        }
+        if dump_count == 0 && unchanged > 0 {
+            loader.reporter.log(&format!(
+                "Nothing to do: all {unchanged} files match the manifest"
+            ));
+        }
+        if let Some(telemetry) = &mut loader.telemetry {
+            if let Err(e) = telemetry.finish().await {
+                loader.reporter.log(&format!("WARNING: {e:#}"));
+            }
+        }
+        Ok(())
+    }
+    .await;
+    let error = result.as_ref().err().map(|e| format!("{e:#}"));
+    loader.reporter.finished(error.as_deref());
+    result
+}
+
+/// Files in `dir` some source can read and that this run has not handled
+/// in their current state, with their modification time and size. Files
+/// modified less than `settle` ago are left for a later scan.
+fn pending_files(
+    dir: &str,
+    registry: &Registry,
+    seen: &HashMap<PathBuf, (SystemTime, u64)>,
+    order: FileOrder,
+    settle: Duration,
+) -> Result<Vec<(PathBuf, SystemTime, u64)>> {
+    let mut pending = Vec::new();
+    for entry in fs::read_dir(dir).with_context(|| format!("reading directory: {dir}"))? {
+        let entry = entry?;
+        let path = entry.path();
+        if !path.is_file() || !registry.handles(&path) {
+            continue;
+        }
+        let metadata = entry.metadata()?;
+        let state = (metadata.modified()?, metadata.len());
+        let settled = state.0.elapsed().is_ok_and(|age| age >= settle);
+        if settled && seen.get(&path) != Some(&state) {
+            pending.push((path, state.0, state.1));
+        }
+    }
+    match order {
+        FileOrder::Name => pending.sort_by(|a, b| a.0.cmp(&b.0)),
+        FileOrder::Mtime => pending.sort_by(|a, b| a.1.cmp(&b.1).then_with(|| a.0.cmp(&b.0))),
+        FileOrder::SizeDesc => pending.sort_by(|a, b| b.2.cmp(&a.2).then_with(|| a.0.cmp(&b.0))),
+    }
+    Ok(pending)
+}
+
+/// State shared by the files of one run.
+struct Loader<'a> {
+    client: &'a Client,
+    args: &'a LoadArgs,
+    column_map: ColumnMap,
+    identifiers: IdentifierPolicy,
+    fx: Option<FxRates>,
+    events: Option<Events>,
+    /// Threads computing --volatility, shared by the files of the run.
+    compute: Option<transform::ComputePool>,
+    existing: Existing,
+    reporter: Box<dyn Reporter>,
+    sink: Box<dyn Sink + 'a>,
+    /// Batches the sink has written, passed on to the reporter.
+    loaded: mpsc::UnboundedReceiver<Loaded>,
+    manifest: Option<Manifest>,
+    report: RunReport,
+    /// Policy for the rows of the file being loaded, when it was rewritten.
+    reload: Option<Reload>,
+    universe: Option<Universe>,
+    telemetry: Option<Telemetry>,
+    /// Percentage normalization of the file being loaded.
+    percent: Vec<(String, PercentRule)>,
+    commit_every: CommitEvery,
+    /// Rows of the batch being written that the sink has committed.
+    committed: Arc<AtomicU64>,
+    /// Rows of each share of a distributed batch committed, as `committed`.
+    shards: Arc<Mutex<Vec<u64>>>,
+    /// The --record script.
+    script: Option<Script>,
+}
+
+impl Loader<'_> {
+    /// Adds the file's outcome to the report and, once loaded, the manifest.
+    fn record(&mut self, path: &Path, report: FileReport) -> Result<()> {
+        if let (Some(manifest), Status::Loaded) = (&mut self.manifest, report.status) {
+            let (size, modified, sha256) = manifest::fingerprint(path)?;
+            let loaded_at = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |d| d.as_secs());
+            manifest.files.insert(
+                report.file.clone(),
+                FileEntry {
+                    size,
+                    modified,
+                    sha256,
+                    tables: report.tables.clone(),
+                    rows: report.rows,
+                    loaded_at,
+                    quality: report.quality.clone(),
+                },
+            );
+            manifest.partial.remove(&report.file);
+            manifest.save()?;
+        }
+        self.report.add(report);
+        Ok(())
+    }
+
+    /// Records a statement run outside the sink to the --record script.
+    fn record_statement(&self, statement: &str) -> Result<()> {
+        match &self.script {
+            Some(script) => script.statement(statement),
+            None => Ok(()),
+        }
+    }
+
+    fn write_report(&mut self) -> Result<()> {
+        self.report.resources = Some(ResourceUsage::measure());
+        match &self.args.report {
+            Some(path) => self.report.write(path),
+            None => Ok(()),
+        }
+    }
+
+    /// Whether the file is exactly the one the manifest recorded as loaded
+    /// and its tables still exist, so loading it again would change
+    /// nothing. Size and modification time are compared first, sparing the
+    /// hash of untouched files. Runs replacing tables or writing to another
+    /// sink always load.
+    async fn unchanged(&self, path: &Path) -> Result<bool> {
+        let Some(manifest) = &self.manifest else {
+            return Ok(false);
+        };
+        if self.args.sink.is_some() || self.args.if_exists == IfExists::Replace {
+            return Ok(false);
+        }
+        let file_name = path.file_name().unwrap().to_string_lossy();
+        let Some(entry) = manifest.files.get(file_name.as_ref()) else {
+            return Ok(false);
+        };
+        let meta =
+            fs::metadata(path).with_context(|| format!("reading metadata: {}", path.display()))?;
+        if meta.len() != entry.size {
+            return Ok(false);
+        }
+        if manifest::modified(&meta)? != entry.modified {
+            let (_, _, sha256) = manifest::fingerprint(path)?;
+            if sha256 != entry.sha256 {
+                return Ok(false);
+            }
+        }
+        let exists: bool = self
+            .client
+            .query_one(
+                "select coalesce(bool_and(to_regclass(t) is not null), true) from unnest($1::text[]) t",
+                &[&entry.tables],
+            )
+            .await?
+            .get(0);
+        Ok(exists)
+    }
+
+    /// Header validation result the manifest holds for this exact file and
+    /// column map. As for `unchanged`, the file is only hashed when its
+    /// modification time moved. Cached failures are retried in interactive
+    /// mode, where the user may now accept a mapping.
+    fn cached_header(&self, path: &Path) -> Result<Option<Result<Vec<String>, String>>> {
+        let Some(manifest) = &self.manifest else {
+            return Ok(None);
+        };
+        let file_name = path.file_name().unwrap().to_string_lossy();
+        let Some(entry) = manifest.headers.get(file_name.as_ref()) else {
+            return Ok(None);
+        };
+        if entry.config != self.column_map.digest() {
+            return Ok(None);
+        }
+        let meta =
+            fs::metadata(path).with_context(|| format!("reading metadata: {}", path.display()))?;
+        if meta.len() != entry.size {
+            return Ok(None);
+        }
+        if manifest::modified(&meta)? != entry.modified {
+            let (_, _, sha256) = manifest::fingerprint(path)?;
+            if sha256 != entry.sha256 {
+                return Ok(None);
+            }
+        }
+        Ok(match (&entry.columns, &entry.error) {
+            (Some(columns), _) => Some(Ok(columns.clone())),
+            (None, Some(error)) if !self.args.interactive => Some(Err(error.clone())),
+            _ => None,
+        })
+    }
+
+    fn cache_header(&mut self, path: &Path, resolved: &Result<Vec<String>, String>) -> Result<()> {
+        let Some(manifest) = &mut self.manifest else {
+            return Ok(());
+        };
+        let (size, modified, sha256) = manifest::fingerprint(path)?;
+        let file_name = path.file_name().unwrap().to_string_lossy().into_owned();
+        manifest.headers.insert(
+            file_name,
+            HeaderEntry {
+                size,
+                modified,
+                sha256,
+                config: self.column_map.digest(),
+                columns: resolved.as_ref().ok().cloned(),
+                error: resolved.as_ref().err().cloned(),
+            },
+        );
+        manifest.save()
+    }
+
+    /// Every table this run loaded into, once each.
+    fn loaded_tables(&self) -> Vec<String> {
+        let mut tables: Vec<String> = self
+            .report
+            .files
+            .iter()
+            .flat_map(|f| f.tables.iter().cloned())
+            .collect();
+        tables.sort();
+        tables.dedup();
+        tables
+    }
+
+    /// Clusters every local table this run loaded.
+    async fn cluster_on_date(&mut self) -> Result<()> {
+        for table_name in self.loaded_tables() {
+            if postgres::relation_kind(self.client, &table_name)
+                .await?
+                .is_remote()
+            {
+                continue;
+            }
+            self.reporter
+                .log(&format!("Clustering {table_name} on date..."));
+            let shared = self.args.single_table.as_ref() == Some(&table_name);
+            postgres::cluster_on_date(
+                self.client,
+                &table_name,
+                shared,
+                self.args.fillfactor,
+                self.script.as_ref(),
+            )
+            .await?;
+        }
+        Ok(())
+    }
+
+    /// Logs, and adds to the report, how the distributed hypertables loaded
+    /// are spread over their data nodes.
+    async fn summarize_data_nodes(&mut self) -> Result<()> {
+        for table_name in self.loaded_tables() {
+            if postgres::relation_kind(self.client, &table_name).await?
+                != RelationKind::DistributedHypertable
+            {
+                continue;
+            }
+            let usage = postgres::data_node_usage(self.client, &table_name).await?;
+            for node in &usage {
+                self.reporter.log(&format!(
+                    "{table_name} on {}: {} chunks, {} bytes",
+                    node.node, node.chunks, node.bytes
+                ));
+            }
+            self.report.data_nodes.insert(table_name, usage);
+        }
+        Ok(())
+    }
+
+    /// Quality is only scored when something records it.
+    fn wants_quality(&self) -> bool {
+        self.manifest.is_some() || self.args.report.is_some() || self.telemetry.is_some()
+    }
+
+    fn normalize_percent(&mut self, columns: &[String], records: &mut [csv::ByteRecord]) {
+        let changed = transform::normalize_percent(columns, records, &self.percent);
+        if changed > 0 {
+            self.reporter
+                .log(&format!("Normalized {changed} percentage values"));
+        }
+    }
+
+    /// Traces a stage of the current file that began at `started`.
+    fn stage(&mut self, name: &str, started: SystemTime, attributes: &[(&str, serde_json::Value)]) {
+        if let Some(telemetry) = &mut self.telemetry {
+            telemetry.stage(name, started, attributes);
+        }
+    }
+
+    /// Ends the file's trace and exports it. Export failures are only
+    /// warned about; they never fail the load.
+    async fn end_file(&mut self, report: &FileReport) {
+        self.reporter.file_finished(&report.file, report.status);
+        while let Ok(Some(batch)) = self.loaded.try_next() {
+            self.reporter.batch_loaded(&batch);
+        }
+        let Some(telemetry) = &mut self.telemetry else {
+            return;
+        };
+        telemetry.end_file(report);
+        if let Err(e) = telemetry.flush().await {
+            self.reporter.log(&format!("WARNING: {e:#}"));
+        }
+    }
+
+    async fn load_file(&mut self, path: &Path, mut source: Box<dyn Source>) -> Result<FileReport> {
+        let client = self.client;
+        let args = self.args;
+        let file_name = path.file_name().unwrap().to_str().unwrap();
+        self.reporter.file_started(file_name);
+        self.reporter.log(&format!("Reading {file_name}..."));
+
+        let definition = definition::discover(path)?.unwrap_or_default();
+        self.percent = definition.percent;
+        // Caught here, as the header check or COPY would fail with a
+        // message that hides the cause.
+        let empty = source.is_empty()?;
+        let no_header = source.headers()?.iter().all(str::is_empty);
+        let has_rows = source.has_rows()?;
+        // A blank header line is a header error, like any other the header
+        // check finds, unless nothing follows it and blank files are let be.
+        if no_header && !empty && (has_rows || args.empty_files == EmptyFiles::Fail) {
+            let error = "invalid header: the header line is blank";
+            self.reporter.file_failed(file_name, error);
+            let mut report = FileReport::new(file_name, Status::Invalid);
+            report.error = Some(error.to_string());
+            return Ok(report);
+        }
+        if no_header || !has_rows {
+            let what = match (empty, no_header) {
+                (true, _) => "empty",
+                (false, true) => "blank",
+                (false, false) => "only a header",
+            };
+            match args.empty_files {
+                EmptyFiles::Fail => bail!("{file_name} is {what}"),
+                EmptyFiles::Skip => {
+                    self.reporter.log(&format!(
+                        "WARNING: skipping {file_name}: the file is {what}"
+                    ));
+                    return Ok(FileReport::new(file_name, Status::Skipped));
+                }
+                EmptyFiles::Create if no_header => {
+                    return self.create_empty(path, definition.columns).await;
+                }
+                // The header names the columns; the load goes on without rows.
+                EmptyFiles::Create => {}
+            }
+        }
+        if !definition.columns.is_empty() {
+            let headers = source.headers()?;
+            return self
+                .load_defined(path, &headers, source, definition.columns)
+                .await;
+        }
+        if args.format == FileFormat::FoBhavcopy {
+            let headers = source.headers()?;
+            return self.load_bhavcopy(path, &headers, source).await;
+        }
+        if args.infer_types {
+            let headers = source.headers()?;
+            return self.load_inferred(path, &headers, source).await;
+        }
+
+        // Verify the CSV header, unless an earlier run did for this file.
+        let started = SystemTime::now();
+        let resolved = match self.cached_header(path)? {
+            Some(resolved) => resolved,
+            None => {
+                let headers = source.headers()?;
+                let resolved = header::resolve(&headers, &mut self.column_map, args.interactive)
+                    .map_err(|e| e.to_string());
+                if let Some(saved) = self.column_map.save()? {
+                    self.reporter
+                        .log(&format!("Saved column map to {}", saved.display()));
+                }
+                self.cache_header(path, &resolved)?;
+                resolved
+            }
+        };
+        self.stage("validate", started, &[]);
+        let columns = match resolved {
+            Ok(columns) => columns,
+            Err(e) => {
+                self.reporter
+                    .file_failed(file_name, &format!("invalid header: {e}"));
+                let mut report = FileReport::new(file_name, Status::Invalid);
+                report.error = Some(format!("invalid header: {e}"));
+                return Ok(report);
+            }
+        };
+        self.reporter.log("Header valid");
+
+        let mut report = FileReport::new(file_name, Status::Loaded);
+        let symbol = compression::strip_extension(path)
+            .file_stem()
+            .unwrap()
+            .to_string_lossy()
+            .into_owned();
+        let source_path = path.display().to_string();
+        let symbol_column = columns.iter().position(|c| c == "symbol");
+        if let Some(universe) = &self.universe {
+            if symbol_column.is_none() && !universe.contains(&symbol) {
+                self.reporter.log(&format!(
+                    "Skipping {file_name}: {symbol} is not in the universe"
+                ));
+                report.status = Status::Skipped;
+                return Ok(report);
+            }
+        }
+
+        // Files that need no reshaping are read by the server directly.
+        let server_path = source
+            .server_path()
+            .and_then(|p| p.to_str())
+            .map(str::to_string);
+        let plain = args.sink.is_none()
+            && args.single_table.is_none()
+            && symbol_column.is_none()
+            && self.fx.is_none()
+            && self.events.is_none()
+            && !columns
+                .iter()
+                .any(|c| schema::ADJUSTED_COLUMNS.contains(&c.as_str()))
+            && args.trim_warmup.is_none()
+            && !args.volatility
+            && args.max_errors.is_none()
+            && args.record.is_none()
+            && self.percent.is_empty()
+            && !self.commit_every.is_set()
+            && self.reload != Some(Reload::Upsert)
+            && args.if_exists != IfExists::Upsert;
+        if plain && server_path.is_none() && !self.wants_quality() {
+            let table_name = self.identifiers.table(&symbol);
+            self.reporter
+                .log(&format!("Streaming data from {source_path}"));
+            self.write_streamed(source, table_name, columns, &source_path, &mut report)
+                .await?;
+            return Ok(report);
+        }
+        // Foreign tables and distributed hypertables go through the sink,
+        // which leaves their DDL alone.
+        let direct = plain
+            && !postgres::relation_kind(client, &self.identifiers.table(&symbol))
+                .await?
+                .is_remote();
+        if let (true, Some(abs_path)) = (direct, server_path.as_deref()) {
+            // Create the table.
+            let table_name = self.identifiers.table(&symbol);
+            if self.reload.is_some() {
+                clear_table(client, self.script.as_ref(), &table_name).await?;
+            } else if !self.existing.admit(client, &table_name).await? {
+                self.reporter
+                    .log(&format!("Skipping {table_name}: table already exists"));
+                report.status = Status::Skipped;
+                return Ok(report);
+            }
+            if self.wants_quality() {
+                let started = SystemTime::now();
+                report.quality = Some(Quality::compute(&columns, &source.records()?));
+                self.stage("verify", started, &[]);
+            }
+            self.reporter
+                .log(&format!("Creating table {table_name}..."));
+            let started = SystemTime::now();
+            postgres::create_table(client, &table_name, Layout::Indicators, false)
+                .await
+                .with_context(|| format!("error creating table: {table_name}"))?;
+            self.stage("create", started, &[("table", table_name.clone().into())]);
+
+            // Filling data in the table.
+            self.reporter
+                .log(&format!("Filling data from {}", abs_path));
+            let started = SystemTime::now();
+            let rows = fill_data(client, &table_name, abs_path, &columns, args)
+                .await
+                .with_context(|| format!("error filling table: {table_name}"))?;
+            self.stage(
+                "copy",
+                started,
+                &[("table", table_name.clone().into()), ("rows", rows.into())],
+            );
+
+            // Stamp the load metadata into the table comment.
+            postgres::stamp_comment(client, &table_name, abs_path, rows)
+                .await
+                .with_context(|| format!("error commenting table: {table_name}"))?;
+            // The server read the file itself, so no batch passed the sink.
+            self.reporter.batch_loaded(&Loaded {
+                table_name: table_name.clone(),
+                symbols: Vec::new(),
+                first_date: None,
+                last_date: None,
+                rows,
+            });
+            self.reporter.rows_committed(&table_name, rows);
+            report.tables.push(table_name);
+            report.rows = rows;
+
+            self.reporter
+                .log("------------------------------------------------------------------\n\n");
+            return Ok(report);
+        }
+
+        let mut records = source.records()?;
+        self.normalize_percent(&columns, &mut records);
+        let started = SystemTime::now();
+        if self.wants_quality() {
+            report.quality = Some(Quality::compute(&columns, &records));
+        }
+        if let (Some(universe), Some(i)) = (&self.universe, symbol_column) {
+            records.retain(|r| universe.contains(&String::from_utf8_lossy(&r[i])));
+        }
+        if let Some(max_errors) = args.max_errors {
+            let check = RowCheck::new(&columns);
+            let mut errors = 0;
+            records.retain(|r| {
+                let Some(i) = check.invalid_column(r) else {
+                    return true;
+                };
+                errors += 1;
+                if errors <= 10 {
+                    let line = r.position().map_or(0, |p| p.line());
+                    self.reporter
+                        .log(&format!("Dropping line {line}: invalid {}", columns[i]));
+                }
+                false
+            });
+            if errors > max_errors {
+                bail!("{errors} invalid rows, more than --max-errors {max_errors}");
+            }
+            if errors > 0 {
+                self.reporter
+                    .log(&format!("Dropped {errors} invalid rows from {file_name}"));
+            }
+        }
+        if self.wants_quality() || args.max_errors.is_some() {
+            self.stage("verify", started, &[]);
+        }
+        let mut batches = match (&args.single_table, symbol_column) {
+            // All rows go to the shared table, tagged with their symbol.
+            (Some(table_name), _) => {
+                vec![single_table_batch(table_name, &symbol, columns, records)]
+            }
+            // A combined file is split into one table per symbol.
+            (None, Some(i)) => {
+                let batches = split_by_symbol(columns, records, i, &self.identifiers);
+                self.reporter.log(&format!(
+                    "Splitting {file_name} into {} symbols",
+                    batches.len()
+                ));
+                batches
+            }
+            (None, None) => vec![Batch {
+                table_name: self.identifiers.table(&symbol),
+                columns,
+                records,
+                extra_columns: Vec::new(),
+                shared: false,
+                source: String::new(),
+                layout: Layout::Indicators,
+            }],
+        };
+
+        for batch in &mut batches {
+            batch.source = source_path.clone();
+            if let Some(mode) = args.trim_warmup {
+                let dropped = transform::trim_warmup(batch, mode);
+                self.reporter.log(&format!(
+                    "Trimmed {dropped} warm-up rows from {}",
+                    batch.table_name
+                ));
+            }
+        }
+        // Before the conversion, so both series are converted.
+        let mut batches: Vec<Batch> = batches
+            .into_iter()
+            .flat_map(|batch| transform::split_adjusted(batch, args.adjusted_series))
+            .collect();
+        for batch in &mut batches {
+            if let Some(fx) = &self.fx {
+                fx.convert(batch)
+                    .with_context(|| format!("error converting currency: {file_name}"))?;
+            }
+        }
+        // After the conversion, so the bands are in the same currency as the
+        // prices.
+        if let Some(pool) = &self.compute {
+            let started = SystemTime::now();
+            transform::add_volatility(&mut batches, pool);
+            self.stage("compute", started, &[]);
+        }
+        for batch in &mut batches {
+            if let Some(events) = &self.events {
+                let flagged = events
+                    .flag(batch)
+                    .with_context(|| format!("error flagging events: {file_name}"))?;
+                if flagged > 0 {
+                    self.reporter.log(&format!(
+                        "Flagged {flagged} halt or circuit rows in {}",
+                        batch.table_name
+                    ));
+                }
+            }
+        }
+
+        self.reporter
+            .log(&format!("Filling data from {}", source_path));
+        self.write_batches(path, batches, &mut report).await?;
+        Ok(report)
+    }
+
+    /// Loads an F&O bhavcopy into the derivatives table of each underlying.
+    async fn load_bhavcopy(
+        &mut self,
+        path: &Path,
+        headers: &csv::StringRecord,
+        mut source: Box<dyn Source>,
+    ) -> Result<FileReport> {
+        let file_name = path.file_name().unwrap().to_str().unwrap();
+        let records = source.records()?;
+        let started = SystemTime::now();
+        let batches = derivatives::batches(
+            headers,
+            records,
+            &self.identifiers,
+            self.args.single_table.as_deref(),
+        );
+        self.stage("validate", started, &[]);
+        let mut batches = match batches {
+            Ok(batches) => batches,
+            Err(e) => {
+                self.reporter
+                    .file_failed(file_name, &format!("invalid header: {e}"));
+                let mut report = FileReport::new(file_name, Status::Invalid);
+                report.error = Some(format!("invalid header: {e}"));
+                return Ok(report);
+            }
+        };
+        self.reporter.log(&format!(
+            "Splitting {file_name} into {} underlyings",
+            batches.len()
+        ));
+        for batch in &mut batches {
+            batch.source = path.display().to_string();
+        }
+
+        let mut report = FileReport::new(file_name, Status::Loaded);
+        self.write_batches(path, batches, &mut report).await?;
+        Ok(report)
+    }
+
+    /// Loads a CSV of arbitrary layout into a table of inferred column types.
+    async fn load_inferred(
+        &mut self,
+        path: &Path,
+        headers: &csv::StringRecord,
+        mut source: Box<dyn Source>,
+    ) -> Result<FileReport> {
+        let records = source.records()?;
+        let columns =
+            infer::column_types(headers, &records, self.args.infer_rows, &self.identifiers);
+        let summary: Vec<String> = columns.iter().map(|(c, t)| format!("{c} {t}")).collect();
+        self.reporter
+            .log(&format!("Inferred columns: {}", summary.join(", ")));
+
+        let names = columns.iter().map(|(c, _)| c.clone()).collect();
+        let definition = columns
+            .into_iter()
+            .map(|(c, t)| (c, t.to_string()))
+            .collect();
+        self.write_defined(path, names, records, definition).await
+    }
+
+    /// Loads a file whose table definition sits next to it. Every header
+    /// must name one of its columns.
+    async fn load_defined(
+        &mut self,
+        path: &Path,
+        headers: &csv::StringRecord,
+        mut source: Box<dyn Source>,
+        definition: Vec<(String, String)>,
+    ) -> Result<FileReport> {
+        let file_name = path.file_name().unwrap().to_str().unwrap();
+        let mut columns = Vec::with_capacity(headers.len());
+        for header in headers {
+            let name = self.identifiers.apply(header.trim());
+            match definition.iter().find(|(c, _)| {
+                c.trim_matches('"')
+                    .eq_ignore_ascii_case(name.trim_matches('"'))
+            }) {
+                Some((column, _)) => columns.push(column.clone()),
+                None => {
+                    let error = format!("invalid header: {header} is not in the file's definition");
+                    self.reporter.file_failed(file_name, &error);
+                    let mut report = FileReport::new(file_name, Status::Invalid);
+                    report.error = Some(error);
+                    return Ok(report);
+                }
+            }
+        }
+        self.reporter.log(&format!(
+            "Using the file's own definition of {} columns",
+            definition.len()
+        ));
+        let mut records = source.records()?;
+        self.normalize_percent(&columns, &mut records);
+        self.write_defined(path, columns, records, definition).await
+    }
+
+    /// Creates the table of a file without a header, from its own definition
+    /// or the built-in one.
+    async fn create_empty(
+        &mut self,
+        path: &Path,
+        definition: Vec<(String, String)>,
+    ) -> Result<FileReport> {
+        let file_name = path.file_name().unwrap().to_str().unwrap();
+        if !definition.is_empty() {
+            let columns = definition.iter().map(|(c, _)| c.clone()).collect();
+            return self
+                .write_defined(path, columns, Vec::new(), definition)
+                .await;
+        }
+        if self.args.format == FileFormat::FoBhavcopy || self.args.infer_types {
+            self.reporter.log(&format!(
+                "WARNING: skipping {file_name}: the file is empty and has no columns to create a table from"
+            ));
+            return Ok(FileReport::new(file_name, Status::Skipped));
+        }
+        let symbol = compression::strip_extension(path)
+            .file_stem()
+            .unwrap()
+            .to_string_lossy()
+            .into_owned();
+        let columns = schema::columns().into_iter().map(String::from).collect();
+        let mut batch = match &self.args.single_table {
+            Some(table_name) => single_table_batch(table_name, &symbol, columns, Vec::new()),
+            None => Batch {
+                table_name: self.identifiers.table(&symbol),
+                columns,
+                records: Vec::new(),
+                extra_columns: Vec::new(),
+                shared: false,
+                source: String::new(),
+                layout: Layout::Indicators,
+            },
+        };
+        batch.source = path.display().to_string();
+        self.reporter
+            .log(&format!("Creating the table of empty file {file_name}"));
+        let mut report = FileReport::new(file_name, Status::Loaded);
+        self.write_batches(path, vec![batch], &mut report).await?;
+        Ok(report)
+    }
+
+    /// Writes the rows to a table named after the file, with the given
+    /// columns instead of the built-in definition.
+    async fn write_defined(
+        &mut self,
+        path: &Path,
+        columns: Vec<String>,
+        records: Vec<csv::ByteRecord>,
+        definition: Vec<(String, String)>,
+    ) -> Result<FileReport> {
+        let file_name = path.file_name().unwrap().to_str().unwrap();
+        let stem = compression::strip_extension(path)
+            .file_stem()
+            .unwrap()
+            .to_string_lossy()
+            .into_owned();
+        let batch = Batch {
+            table_name: self.identifiers.table(&stem),
+            columns,
+            records,
+            extra_columns: definition,
+            shared: false,
+            source: path.display().to_string(),
+            layout: Layout::Inferred,
+        };
+        let mut report = FileReport::new(file_name, Status::Loaded);
+        self.write_batches(path, vec![batch], &mut report).await?;
+        Ok(report)
+    }
+
+    /// Rows of the table an earlier, failed load of this exact file
+    /// committed. Only appending runs resume; others rewrite the rows.
+    fn resume_point(&self, path: &Path, table_name: &str) -> Result<u64> {
+        Ok(self
+            .partial(path)?
+            .and_then(|entry| entry.tables.get(table_name).copied())
+            .unwrap_or(0))
+    }
+
+    /// Rows of each share of a distributed table an earlier, failed load of
+    /// this exact file committed, as for `resume_point`.
+    fn resumed_shards(&self, path: &Path, table_name: &str) -> Result<Vec<u64>> {
+        Ok(self
+            .partial(path)?
+            .and_then(|entry| entry.shards.get(table_name).cloned())
+            .unwrap_or_default())
+    }
+
+    /// What an earlier, failed load of this exact file committed, when this
+    /// run appends.
+    fn partial(&self, path: &Path) -> Result<Option<&PartialEntry>> {
+        let Some(manifest) = &self.manifest else {
+            return Ok(None);
+        };
+        if self.args.if_exists != IfExists::Append || self.reload.is_some() {
+            return Ok(None);
+        }
+        let file_name = path.file_name().unwrap().to_string_lossy();
+        let Some(entry) = manifest.partial.get(file_name.as_ref()) else {
+            return Ok(None);
+        };
+        let meta =
+            fs::metadata(path).with_context(|| format!("reading metadata: {}", path.display()))?;
+        if meta.len() != entry.size || manifest::modified(&meta)? != entry.modified {
+            return Ok(None);
+        }
+        Ok(Some(entry))
+    }
+
+    /// Records in the manifest how many rows of the table are committed.
+    fn save_partial(&mut self, path: &Path, table_name: &str, progress: Progress) -> Result<()> {
+        let Some(manifest) = &mut self.manifest else {
+            return Ok(());
+        };
+        let meta =
+            fs::metadata(path).with_context(|| format!("reading metadata: {}", path.display()))?;
+        let (size, modified) = (meta.len(), manifest::modified(&meta)?);
+        let file_name = path.file_name().unwrap().to_string_lossy().into_owned();
+        let entry = manifest
+            .partial
+            .entry(file_name)
+            .or_insert_with(|| PartialEntry {
+                size,
+                modified,
+                tables: BTreeMap::new(),
+                shards: BTreeMap::new(),
+            });
+        if entry.size != size || entry.modified != modified {
+            *entry = PartialEntry {
+                size,
+                modified,
+                tables: BTreeMap::new(),
+                shards: BTreeMap::new(),
+            };
+        }
+        match progress {
+            Progress::Rows(rows) => {
+                entry.tables.insert(table_name.to_string(), rows);
+            }
+            Progress::Shards(rows) => {
+                entry.shards.insert(table_name.to_string(), rows);
+            }
+        }
+        manifest.save()
+    }
+
+    /// Writes rows needing no reshaping to the sink a chunk at a time, so
+    /// inputs the server cannot read itself, e.g. compressed ones, are never
+    /// held whole.
+    async fn write_streamed(
+        &mut self,
+        mut source: Box<dyn Source>,
+        table_name: String,
+        columns: Vec<String>,
+        source_path: &str,
+        report: &mut FileReport,
+    ) -> Result<()> {
+        if self.reload.is_some() {
+            clear_table(self.client, self.script.as_ref(), &table_name).await?;
+        } else if !self.existing.admit(self.client, &table_name).await? {
+            self.reporter
+                .log(&format!("Skipping {table_name}: table already exists"));
+            report.status = Status::Skipped;
+            return Ok(());
+        }
+        let started = SystemTime::now();
+        let mut rows = 0;
+        let mut chunks = 0;
+        loop {
+            let mut records = Vec::with_capacity(STREAM_CHUNK_ROWS);
+            while records.len() < STREAM_CHUNK_ROWS {
+                match source.next_record()? {
+                    Some(record) => records.push(record),
+                    None => break,
+                }
+            }
+            let last = records.len() < STREAM_CHUNK_ROWS;
+            // The first chunk creates the table, even without rows.
+            if chunks == 0 || !records.is_empty() {
+                let batch = Batch {
+                    table_name: table_name.clone(),
+                    columns: columns.clone(),
+                    records,
+                    extra_columns: Vec::new(),
+                    shared: false,
+                    source: source_path.to_string(),
+                    layout: Layout::Indicators,
+                };
+                rows += self
+                    .sink
+                    .write(batch)
+                    .await
+                    .with_context(|| format!("error filling table: {table_name}"))?;
+                chunks += 1;
+            }
+            if last {
+                break;
+            }
+        }
+        self.stage(
+            "copy",
+            started,
+            &[("table", table_name.clone().into()), ("rows", rows.into())],
+        );
+        self.reporter.rows_committed(&table_name, rows);
+        report.tables.push(table_name);
+        report.rows = rows;
+        self.reporter
+            .log("------------------------------------------------------------------\n\n");
+        Ok(())
+    }
+
+    /// Deletes the rows of the batch's table that it replaces: those of the
+    /// same dates for an upsert, with --on-conflict deciding row by row, or
+    /// those of the file's earlier load.
+    async fn replace_previous(
+        &mut self,
+        path: &Path,
+        batch: &mut Batch,
+        upsert: bool,
+    ) -> Result<()> {
+        let table_name = batch.table_name.clone();
+        if let (true, Some(mode)) = (upsert, self.args.on_conflict) {
+            let modified = file_modified(self.client, path).await?;
+            let (revised, kept) =
+                resolve_conflicts(self.client, self.script.as_ref(), batch, mode, &modified)
+                    .await?;
+            if revised + kept > 0 {
+                self.reporter.log(&format!(
+                    "Resolved conflicts in {table_name}: {revised} rows revised, {kept} kept"
+                ));
+            }
+            return Ok(());
+        }
+        let reload = if upsert {
+            Reload::Upsert
+        } else {
+            self.reload.unwrap_or(Reload::Replace)
+        };
+        let deleted = delete_previous(self.client, self.script.as_ref(), batch, reload).await?;
+        self.reporter.log(&format!(
+            "Deleted {deleted} previous rows from {table_name}"
+        ));
+        Ok(())
+    }
+
+    /// Writes the batches to the sink, skipping tables the `--if-exists`
+    /// policy rules out.
+    async fn write_batches(
+        &mut self,
+        path: &Path,
+        batches: Vec<Batch>,
+        report: &mut FileReport,
+    ) -> Result<()> {
+        for mut batch in batches {
+            let table_name = batch.table_name.clone();
+            let upsert = self.reload == Some(Reload::Upsert)
+                || (self.reload.is_none() && self.args.if_exists == IfExists::Upsert);
+            let replaces = upsert || self.reload.is_some();
+            if !replaces && !batch.shared && !self.existing.admit(self.client, &table_name).await? {
+                self.reporter
+                    .log(&format!("Skipping {table_name}: table already exists"));
+                continue;
+            }
+            // The rows being replaced are deleted in the transaction their
+            // successors are written in, so a failed write keeps them.
+            // Partial commits and other connections (a --sink, data nodes)
+            // cannot share it.
+            let atomic = replaces
+                && !self.commit_every.is_set()
+                && self.args.sink.is_none()
+                && self.args.distributed_jobs <= 1;
+            // DDL first, as its retry on a creation race cannot run in the
+            // transaction, which the losing attempt aborts.
+            if atomic
+                && !postgres::relation_kind(self.client, &table_name)
+                    .await?
+                    .is_remote()
+            {
+                postgres::prepare_table(self.client, &batch).await?;
+            }
+            if let (true, Some(_)) = (upsert, self.args.on_conflict) {
+                add_conflict_columns(self.client, self.script.as_ref(), &table_name).await?;
+            }
+            if atomic {
+                self.client
+                    .batch_execute("begin")
+                    .await
+                    .with_context(|| format!("starting transaction => {table_name}"))?;
+                self.record_statement("begin")?;
+            }
+            let replaced = if replaces {
+                self.replace_previous(path, &mut batch, upsert).await
+            } else {
+                Ok(())
+            };
+            let resumed = self.resume_point(path, &table_name)?;
+            if resumed > 0 {
+                let skipped = skip_resumed(&mut batch.records, resumed);
+                self.reporter.log(&format!(
+                    "Resuming {table_name} after {skipped} rows committed by an earlier run"
+                ));
+            }
+            *self.shards.lock().unwrap() = self.resumed_shards(path, &table_name)?;
+            let started = SystemTime::now();
+            let result = match replaced {
+                Ok(()) => self.sink.write(batch).await,
+                Err(e) => Err(e),
+            };
+            let shards = std::mem::take(&mut *self.shards.lock().unwrap());
+            if atomic {
+                let end = if result.is_ok() { "commit" } else { "rollback" };
+                self.client
+                    .batch_execute(end)
+                    .await
+                    .with_context(|| format!("{end} => {table_name}"))?;
+                self.record_statement(end)?;
+            }
+            if !shards.is_empty() {
+                self.save_partial(path, &table_name, Progress::Shards(shards))?;
+            } else if self.commit_every.is_set() {
+                let committed = match &result {
+                    Ok(rows) => *rows,
+                    Err(_) => self.committed.load(Ordering::Relaxed),
+                };
+                self.save_partial(path, &table_name, Progress::Rows(resumed + committed))?;
+            }
+            let rows = result.with_context(|| format!("error filling table: {table_name}"))?;
+            self.stage(
+                "copy",
+                started,
+                &[("table", table_name.clone().into()), ("rows", rows.into())],
+            );
+            self.reporter.rows_committed(&table_name, rows);
+            report.tables.push(table_name);
+            report.rows += rows;
+        }
+        if report.tables.is_empty() {
+            report.status = Status::Skipped;
+        }
+
+        self.reporter
+            .log("------------------------------------------------------------------\n\n");
+        Ok(())
+    }
+}
+
+fn single_table_batch(
+    table_name: &str,
+    symbol: &str,
+    mut columns: Vec<String>,
+    mut records: Vec<csv::ByteRecord>,
+) -> Batch {
+    if !columns.iter().any(|c| c == "symbol") {
+        columns.push("symbol".to_string());
+        for record in &mut records {
+            record.push_field(symbol.as_bytes());
+        }
+    }
+    Batch {
+        table_name: table_name.to_string(),
+        columns,
+        records,
+        extra_columns: Vec::new(),
+        shared: true,
+        source: String::new(),
+        layout: Layout::Indicators,
+    }
+}
+
+fn split_by_symbol(
+    mut columns: Vec<String>,
+    records: Vec<csv::ByteRecord>,
+    symbol_column: usize,
+    identifiers: &IdentifierPolicy,
+) -> Vec<Batch> {
+    columns.remove(symbol_column);
+    let mut by_symbol: BTreeMap<String, Vec<csv::ByteRecord>> = BTreeMap::new();
+    for record in records {
+        let symbol = String::from_utf8_lossy(&record[symbol_column]).into_owned();
+        let record: csv::ByteRecord = record
+            .iter()
+            .enumerate()
+            .filter(|(j, _)| *j != symbol_column)
+            .map(|(_, f)| f)
+            .collect();
+        by_symbol.entry(symbol).or_default().push(record);
+    }
+
+    by_symbol
+        .into_iter()
+        .map(|(symbol, records)| Batch {
+            table_name: identifiers.table(&symbol),
+            columns: columns.clone(),
+            records,
+            extra_columns: Vec::new(),
+            shared: false,
+            source: String::new(),
+            layout: Layout::Indicators,
+        })
+        .collect()
+}
+
+/// Empties the table, if it exists, before a rewritten file is loaded again.
+async fn clear_table(c: &Client, script: Option<&Script>, table_name: &str) -> Result<()> {
+    let exists: bool = c
+        .query_one("select to_regclass($1) is not null", &[&table_name])
+        .await
+        .with_context(|| format!("checking table => {table_name}"))?
+        .get(0);
+    if exists {
+        execute_recorded(c, script, &format!("truncate table {table_name}"), &[])
+            .await
+            .with_context(|| format!("truncating table => {table_name}"))?;
+    }
+    Ok(())
+}
+
+/// Executes a statement whose parameters are text arrays and records it
+/// to the script, with the arrays written out.
+async fn execute_recorded(
+    c: &Client,
+    script: Option<&Script>,
+    query: &str,
+    arrays: &[&Vec<String>],
+) -> Result<u64> {
+    let params: Vec<&(dyn ToSql + Sync)> = arrays
+        .iter()
+        .map(|values| *values as &(dyn ToSql + Sync))
+        .collect();
+    let rows = c.execute(query, &params).await?;
+    if let Some(script) = script {
+        let mut statement = query.to_string();
+        // From the last, so `$1` does not match `$10`.
+        for (i, values) in arrays.iter().enumerate().rev() {
+            statement = statement.replace(
+                &format!("${}", i + 1),
+                &postgres::text_array_literal(values),
+            );
+        }
+        script.statement(&statement)?;
+    }
+    Ok(rows)
+}
+
+/// Deletes what a rewritten file loaded before: under replace, all rows
+/// of the table (of the batch's symbols in a shared table); under upsert,
+/// the rows the batch has again, by symbol and date.
+async fn delete_previous(
+    c: &Client,
+    script: Option<&Script>,
+    batch: &Batch,
+    reload: Reload,
+) -> Result<u64> {
+    let table_name = &batch.table_name;
+    let exists: bool = c
+        .query_one("select to_regclass($1) is not null", &[&table_name])
+        .await
+        .with_context(|| format!("checking table => {table_name}"))?
+        .get(0);
+    if !exists {
+        return Ok(0);
+    }
+    let values = |name: &str| -> Vec<String> {
+        let i = batch.column(name);
+        batch
+            .records
+            .iter()
+            .filter_map(|r| r.get(i?))
+            .map(|f| String::from_utf8_lossy(f).into_owned())
+            .collect()
+    };
+
+    let deleted = match (reload, batch.shared) {
+        (Reload::Replace, false) => {
+            execute_recorded(c, script, &format!("delete from {table_name}"), &[]).await
+        }
+        (Reload::Replace, true) => {
+            let mut symbols = values("symbol");
+            symbols.sort();
+            symbols.dedup();
+            execute_recorded(
+                c,
+                script,
+                &format!("delete from {table_name} where symbol = any($1::text[])"),
+                &[&symbols],
+            )
+            .await
+        }
+        (Reload::Upsert, false) => {
+            execute_recorded(
+                c,
+                script,
+                &format!("delete from {table_name} where date = any($1::text[]::timestamptz[])"),
+                &[&values("date")],
+            )
+            .await
+        }
+        (Reload::Upsert, true) => {
+            execute_recorded(
+                c,
+                script,
+                &format!(
+                    "delete from {table_name} t using unnest($1::text[], $2::text[]) as r(symbol, date) \
+                     where t.symbol = r.symbol and t.date = r.date::timestamptz"
+                ),
+                &[&values("symbol"), &values("date")],
+            )
+            .await
+        }
+    };
+    deleted.with_context(|| format!("deleting previous rows => {table_name}"))
+}
+
+/// Modification time of the file as a timestamp literal.
+async fn file_modified(c: &Client, path: &Path) -> Result<String> {
+    let meta =
+        fs::metadata(path).with_context(|| format!("reading metadata: {}", path.display()))?;
+    let seconds = manifest::modified(&meta)? as f64;
+    Ok(c.query_one("select to_timestamp($1)::text", &[&seconds])
+        .await?
+        .get(0))
+}
+
+/// Drops the first `resumed` rows, an earlier run having committed them.
+/// Returns the rows dropped, at most all of them.
+fn skip_resumed(records: &mut Vec<csv::ByteRecord>, resumed: u64) -> usize {
+    let skipped = (resumed as usize).min(records.len());
+    records.drain(..skipped);
+    skipped
+}
+
+/// Adds the `revised` and `source_modified` columns --on-conflict keeps to
+/// an existing table. DDL, so run before the load's transaction begins.
+async fn add_conflict_columns(c: &Client, script: Option<&Script>, table_name: &str) -> Result<()> {
+    let query = format!(
+        "do $$ begin if to_regclass({}) is not null then \
+         alter table {table_name} add column if not exists revised boolean not null default false, \
+         add column if not exists source_modified timestamptz; end if; end $$",
+        postgres::literal(table_name)
+    );
+    postgres::execute_ddl(c, &query)
+        .await
+        .with_context(|| format!("adding conflict columns => {table_name}"))?;
+    if let Some(script) = script {
+        script.statement(&query)?;
+    }
+    Ok(())
+}
+
+/// Resolves the batch's rows whose date (and symbol, in a shared table) the
+/// table already holds. Rows taken replace the loaded ones, which are
+/// deleted; the rest are dropped from the batch. Every row gets its
+/// `revised` flag and `source_modified` time. Returns the rows revised and
+/// the loaded rows kept.
+async fn resolve_conflicts(
+    c: &Client,
+    script: Option<&Script>,
+    batch: &mut Batch,
+    mode: OnConflict,
+    modified: &str,
+) -> Result<(u64, u64)> {
+    let table_name = batch.table_name.clone();
+    let exists: bool = c
+        .query_one("select to_regclass($1) is not null", &[&table_name])
+        .await
+        .with_context(|| format!("checking table => {table_name}"))?
+        .get(0);
+
+    let mut taken = HashSet::new();
+    let mut kept = HashSet::new();
+    if exists {
+        let values = |name: &str| -> Vec<String> {
+            let i = batch.column(name);
+            batch
+                .records
+                .iter()
+                .map(|r| i.and_then(|i| r.get(i)).unwrap_or_default())
+                .map(|f| String::from_utf8_lossy(f).into_owned())
+                .collect()
+        };
+        let (dates, symbols) = (values("date"), values("symbol"));
+        let rows = if batch.shared {
+            c.query(
+                &format!(
+                    "select r.i, coalesce(t.source_modified >= $2::text::timestamptz, false) \
+                     from unnest($1::text[], $3::text[]) with ordinality as r(date, symbol, i) \
+                     join {table_name} t on t.date = r.date::timestamptz and t.symbol = r.symbol"
+                ),
+                &[&dates, &modified, &symbols],
+            )
+            .await
+        } else {
+            c.query(
+                &format!(
+                    "select r.i, coalesce(t.source_modified >= $2::text::timestamptz, false) \
+                     from unnest($1::text[]) with ordinality as r(date, i) \
+                     join {table_name} t on t.date = r.date::timestamptz"
+                ),
+                &[&dates, &modified],
+            )
+            .await
+        };
+        let rows = rows.with_context(|| format!("finding conflicting rows => {table_name}"))?;
+        for row in rows {
+            // Ordinality counts from 1.
+            let i = (row.get::<_, i64>(0) - 1) as usize;
+            if takes_new(mode, row.get(1)) {
+                taken.insert(i);
+            } else {
+                kept.insert(i);
+            }
+        }
+
+        if !taken.is_empty() {
+            let mut keys: Vec<(String, String)> = taken
+                .iter()
+                .map(|&i| (symbols[i].clone(), dates[i].clone()))
+                .collect();
+            keys.sort();
+            let (symbols, dates): (Vec<String>, Vec<String>) = keys.into_iter().unzip();
+            let deleted = if batch.shared {
+                execute_recorded(
+                    c,
+                    script,
+                    &format!(
+                        "delete from {table_name} t using unnest($1::text[], $2::text[]) as r(symbol, date) \
+                         where t.symbol = r.symbol and t.date = r.date::timestamptz"
+                    ),
+                    &[&symbols, &dates],
+                )
+                .await
+            } else {
+                execute_recorded(
+                    c,
+                    script,
+                    &format!(
+                        "delete from {table_name} where date = any($1::text[]::timestamptz[])"
+                    ),
+                    &[&dates],
+                )
+                .await
+            };
+            deleted.with_context(|| format!("deleting revised rows => {table_name}"))?;
+        }
+    }
+
+    mark_conflicts(batch, &taken, &kept, modified);
+    Ok((taken.len() as u64, kept.len() as u64))
+}
+
+/// Whether a conflicting row of the file replaces the loaded one, given
+/// whether the loaded one comes from a file modified since.
+fn takes_new(mode: OnConflict, existing_newer: bool) -> bool {
+    match mode {
+        OnConflict::KeepExisting => false,
+        OnConflict::TakeNew => true,
+        OnConflict::NewerFile => !existing_newer,
+    }
+}
+
+/// Drops the `kept` rows from the batch and adds the `revised` and
+/// `source_modified` columns, `revised` set on the `taken` rows.
+fn mark_conflicts(
+    batch: &mut Batch,
+    taken: &HashSet<usize>,
+    kept: &HashSet<usize>,
+    modified: &str,
+) {
+    let records = std::mem::take(&mut batch.records);
+    batch.records = records
+        .into_iter()
+        .enumerate()
+        .filter(|(i, _)| !kept.contains(i))
+        .map(|(i, mut record)| {
+            record.push_field(if taken.contains(&i) {
+                b"true"
+            } else {
+                b"false"
+            });
+            record.push_field(modified.as_bytes());
+            record
+        })
+        .collect();
+    for (column, data_type) in [
+        ("revised", "boolean not null default false"),
+        ("source_modified", "timestamptz"),
+    ] {
+        batch.columns.push(column.to_string());
+        batch
+            .extra_columns
+            .push((column.to_string(), data_type.to_string()));
+    }
+}
+
+async fn fill_data(
+    c: &Client,
+    table_name: &str,
+    csv_file_path: &str,
+    columns: &[String],
+    args: &LoadArgs,
+) -> Result<u64> {
+    let mut options = "format csv, header true, delimiter ','".to_string();
+    if !args.force_null.is_empty() {
+        options += &format!(", force_null ({})", args.force_null.join(","));
+    }
+    if !args.force_not_null.is_empty() {
+        options += &format!(", force_not_null ({})", args.force_not_null.join(","));
+    }
+    let query = format!(
+        r"
+copy {} ({})
+FROM {}
+with ({})
+",
+        table_name,
+        columns.join(","),
+        postgres::server_path_literal(csv_file_path),
+        options
+    );
+    let rows = c
+        .execute(&query, &[])
+        .await
+        .with_context(|| format!("filling data => {table_name}"))?;
+    Ok(rows)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn batch(rows: &[&[&str]]) -> Batch {
+        Batch {
+            table_name: "infy".to_string(),
+            columns: vec!["date".to_string(), "close".to_string()],
+            records: rows
+                .iter()
+                .map(|r| csv::ByteRecord::from(r.to_vec()))
+                .collect(),
+            extra_columns: Vec::new(),
+            shared: false,
+            source: "INFY.csv".to_string(),
+            layout: Layout::Indicators,
+        }
+    }
+
+    #[test]
+    fn new_tables_are_always_loaded() {
+        for policy in [
+            IfExists::Append,
+            IfExists::Upsert,
+            IfExists::Replace,
+            IfExists::Skip,
+            IfExists::Fail,
+        ] {
+            assert_eq!(admission(false, policy, "infy").unwrap(), Admission::Load);
+        }
+    }
+
+    #[test]
+    fn existing_tables_follow_the_policy() {
+        let admit = |policy| admission(true, policy, "infy");
+        assert_eq!(admit(IfExists::Append).unwrap(), Admission::Load);
+        assert_eq!(admit(IfExists::Upsert).unwrap(), Admission::Load);
+        assert_eq!(admit(IfExists::Replace).unwrap(), Admission::Truncate);
+        assert_eq!(admit(IfExists::Skip).unwrap(), Admission::Skip);
+        let err = admit(IfExists::Fail).unwrap_err();
+        assert_eq!(err.to_string(), "table already exists: infy");
+    }
+
+    #[test]
+    fn resuming_drops_the_committed_rows() {
+        let mut records = batch(&[&["2024-01-01", "1"], &["2024-01-02", "2"]]).records;
+        assert_eq!(skip_resumed(&mut records, 1), 1);
+        assert_eq!(
+            records,
+            vec![csv::ByteRecord::from(vec!["2024-01-02", "2"])]
+        );
+    }
+
+    #[test]
+    fn resuming_past_the_end_drops_every_row() {
+        let mut records = batch(&[&["2024-01-01", "1"]]).records;
+        assert_eq!(skip_resumed(&mut records, 5), 1);
+        assert!(records.is_empty());
+    }
+
+    #[test]
+    fn conflicts_follow_the_mode() {
+        for existing_newer in [false, true] {
+            assert!(!takes_new(OnConflict::KeepExisting, existing_newer));
+            assert!(takes_new(OnConflict::TakeNew, existing_newer));
+        }
+        assert!(takes_new(OnConflict::NewerFile, false));
+        assert!(!takes_new(OnConflict::NewerFile, true));
+    }
+
+    #[test]
+    fn marked_rows_gain_the_conflict_columns() {
+        let mut batch = batch(&[
+            &["2024-01-01", "1"],
+            &["2024-01-02", "2"],
+            &["2024-01-03", "3"],
+        ]);
+        let modified = "2024-02-01T00:00:00Z";
+        mark_conflicts(
+            &mut batch,
+            &HashSet::from([0]),
+            &HashSet::from([1]),
+            modified,
+        );
+
+        assert_eq!(
+            batch.columns,
+            ["date", "close", "revised", "source_modified"]
+        );
+        assert_eq!(
+            batch.extra_columns,
+            [
+                (
+                    "revised".to_string(),
+                    "boolean not null default false".to_string()
+                ),
+                ("source_modified".to_string(), "timestamptz".to_string()),
+            ]
+        );
+        assert_eq!(
+            batch.records,
+            vec![
+                csv::ByteRecord::from(vec!["2024-01-01", "1", "true", modified]),
+                csv::ByteRecord::from(vec!["2024-01-03", "3", "false", modified]),
+            ]
+        );
+    }
+}
@@ -1,118 +1,244 @@
 use anyhow::{Context, Result};
-use clap::Parser;
-use csv;
-use std::fs;
-use tokio;
-use tokio_postgres::{Client, NoTls};
+use clap::parser::ValueSource;
+use clap::{Args, CommandFactory, FromArgMatches, Parser, Subcommand};
+#[cfg(feature = "ssh")]
+use tokio_postgres::config::Host;
+use tokio_postgres::{Client, Config, NoTls};
+
+#[cfg(feature = "ssh")]
+use crate::tunnel::Tunnel;
+use pg_nifty_dump::postgres;
+
+mod anonymize;
+mod audit;
+#[cfg(feature = "bundle")]
+mod bundle;
+mod definition;
+mod derivatives;
+mod diff;
+mod docs;
+mod events;
+mod export;
+mod features;
+mod fetch;
+mod fx;
+mod header;
+mod health;
+mod infer;
+mod init;
+mod load;
+mod manifest;
+mod migrate;
+mod profile;
+mod progress;
+mod quality;
+mod queue;
+mod ranks;
+mod replay;
+mod report;
+mod schema;
+mod secret;
+mod service;
+mod telemetry;
+mod tls;
+mod tolerance;
+mod transform;
+#[cfg(feature = "tui")]
+mod tui;
+#[cfg(feature = "ssh")]
+mod tunnel;
+mod universe;
 
 static TARGET_DB_URI: &str = "host=localhost dbname=nifty_stocks port=5432 user=postgres";
-static VERIFY_CSV_HEADER: &str = "date,close,high,low,open,volume,sma5,sma10,sma15,sma20,ema5,ema10,ema15,ema20,upperband,middleband,lowerband,HT_TRENDLINE,KAMA10,KAMA20,KAMA30,SAR,TRIMA5,TRIMA10,TRIMA20,ADX5,ADX10,ADX20,APO,CCI5,CCI10,CCI15,macd510,macd520,macd1020,macd1520,macd1226,MFI,MOM10,MOM15,MOM20,ROC5,ROC10,ROC20,PPO,RSI14,RSI8,slowk,slowd,fastk,fastd,fastksr,fastdsr,ULTOSC,WILLR,ATR,Trange,TYPPRICE,HT_DCPERIOD,BETA";
 
 #[derive(Debug, Parser)]
-struct CLI {
-    /// Path for stock files.
-    #[clap(short, long = "dir", required = true)]
-    dir: String,
-
-    /// Maximum number of tables to be dumped.
-    #[clap(short, long = "max-tables", required = true)]
-    max_tables: i32,
+#[clap(subcommand_negates_reqs = true, args_override_self = true)]
+struct Cli {
+    #[clap(subcommand)]
+    command: Option<Command>,
+
+    /// Read options from this profile written by `init`; options on the
+    /// command line override it.
+    #[clap(long = "profile", global = true, env = "PG_NIFTY_DUMP_PROFILE")]
+    #[allow(dead_code)] // applied by profile::expand before parsing
+    profile: Option<String>,
+
+    #[clap(flatten)]
+    connection: ConnectionArgs,
+
+    #[clap(flatten)]
+    load: load::LoadArgs,
+}
+
+#[derive(Debug, Clone, Args)]
+struct ConnectionArgs {
+    /// Connection string of the target database.
+    #[clap(long = "uri", global = true, env = "PG_NIFTY_DUMP_URI", default_value = TARGET_DB_URI)]
+    uri: String,
+
+    /// Connect with the parameters of this service from `pg_service.conf`
+    /// instead of --uri. A --uri on the command line takes precedence.
+    #[clap(long = "service", global = true, env = "PGSERVICE")]
+    service: Option<String>,
+
+    /// Fetch the database password from a secret backend, e.g.
+    /// `vault:secret/nifty#password` or `aws:prod/nifty#password`.
+    #[clap(long = "secret-ref", global = true)]
+    secret_ref: Option<String>,
+
+    /// Reach the database through an SSH tunnel via this jump host, e.g.
+    /// `user@bastion`.
+    #[cfg(feature = "ssh")]
+    #[clap(long = "ssh-tunnel", global = true)]
+    ssh_tunnel: Option<String>,
+
+    /// SSH port of the jump host.
+    #[cfg(feature = "ssh")]
+    #[clap(long = "ssh-port", global = true, requires = "ssh_tunnel")]
+    ssh_port: Option<u16>,
+
+    /// Private key used to authenticate with the jump host. Defaults to
+    /// the first of `~/.ssh/id_ed25519`, `id_ecdsa` and `id_rsa`.
+    #[cfg(feature = "ssh")]
+    #[clap(long = "ssh-identity", global = true, requires = "ssh_tunnel")]
+    ssh_identity: Option<String>,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Compare the managed tables, and optionally their values, of two
+    /// databases.
+    DiffSchema(diff::DiffSchemaArgs),
+
+    /// Write a Markdown or HTML data dictionary of the table layouts.
+    Docs(docs::DocsArgs),
+
+    /// Export managed tables back into CSV files.
+    Export(export::ExportArgs),
+
+    /// Materialize normalized indicator vectors for pgvector similarity search.
+    Features(features::FeatureArgs),
+
+    /// Walk through the connection, input and table settings and save them
+    /// as a profile.
+    Init(init::InitArgs),
+
+    /// Rename the managed tables loaded under one --table-template to the
+    /// names of another.
+    MigrateNames(migrate::MigrateNamesArgs),
 }
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    let cli = CLI::parse();
-    let valid_header: Vec<&str> = VERIFY_CSV_HEADER.split(",").collect();
-    let valid_header_record = csv::StringRecord::from(valid_header.clone());
+    let matches = Cli::command().get_matches_from(profile::expand(std::env::args_os().collect())?);
+    let mut cli = Cli::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+    if matches.value_source("uri") == Some(ValueSource::CommandLine) {
+        cli.connection.service = None;
+    }
+    // Connects to the two databases it compares instead of --uri.
+    if let Some(Command::DiffSchema(args)) = &cli.command {
+        return diff::run(args).await;
+    }
+    // Runs before there are settings to connect with.
+    if let Some(Command::Init(args)) = &cli.command {
+        return init::run(&cli.connection, args).await;
+    }
+
+    let client = connect(&cli.connection).await?;
+    verify_connection(&client).await?;
 
-    let (client, connection) = tokio_postgres::connect(TARGET_DB_URI, NoTls).await?;
+    match cli.command {
+        Some(Command::DiffSchema(_) | Command::Init(_)) => {
+            unreachable!("handled before connecting")
+        }
+        Some(Command::Docs(args)) => docs::run(&client, &args).await,
+        Some(Command::Export(args)) => export::run(&client, &cli.connection, &args).await,
+        Some(Command::Features(args)) => features::run(&client, &args).await,
+        Some(Command::MigrateNames(args)) => migrate::run(&client, &args).await,
+        None => load::run(&client, &cli.connection, &cli.load).await,
+    }
+}
 
+async fn connect(args: &ConnectionArgs) -> Result<Client> {
+    let mut config: Config = match &args.service {
+        Some(name) => service::config(name)?,
+        None => args.uri.parse().context("invalid --uri")?,
+    };
+    if let Some(reference) = &args.secret_ref {
+        let password = secret::fetch(reference)
+            .await
+            .with_context(|| format!("fetching secret: {reference}"))?;
+        config.password(password);
+    }
+
+    #[cfg(feature = "ssh")]
+    if let Some(bastion) = &args.ssh_tunnel {
+        return connect_through(&config, bastion, args).await;
+    }
+    let (client, connection) = config.connect(NoTls).await?;
     tokio::spawn(async move {
         if let Err(e) = connection.await {
             eprintln!("connection error: {}", e);
         }
     });
+    Ok(client)
+}
 
-    verify_connection(&client).await?;
-
-    let paths = fs::read_dir(&cli.dir).unwrap();
-    for (dump_count, path) in paths.enumerate() {
-        if cli.max_tables > 0 && dump_count > cli.max_tables.try_into().unwrap() {
-            break;
-        }
+/// Connects through an SSH tunnel via the jump host `bastion`.
+#[cfg(feature = "ssh")]
+async fn connect_through(config: &Config, bastion: &str, args: &ConnectionArgs) -> Result<Client> {
+    let host = match config.get_hosts().first() {
+        Some(Host::Tcp(host)) => host.clone(),
+        Some(_) => anyhow::bail!("--ssh-tunnel needs a TCP host in --uri"),
+        None => "localhost".to_string(),
+    };
+    let port = config.get_ports().first().copied().unwrap_or(5432);
+    let tunnel = Tunnel::open(
+        bastion,
+        args.ssh_port,
+        args.ssh_identity.as_deref(),
+        &host,
+        port,
+    )
+    .await?;
+    let stream = tunnel.connect().await?;
+    let (client, connection) = config.connect_raw(stream, NoTls).await?;
 
-        let path = path.unwrap().path();
-        let file_name = path.file_name().unwrap().to_str().unwrap();
-        println!("Reading {file_name}...");
-
-        // Verify the CSV header.
-        let file = fs::File::open(&path).unwrap();
-        let mut rdr = csv::Reader::from_reader(&file);
-        let headers = rdr
-            .headers()
-            .with_context(|| format!("fetching headers from file: {:?}", file))?;
-        if *headers != valid_header_record {
-            println!("ERROR: {file_name} has an invalid header: {headers:?}");
+    tokio::spawn(async move {
+        // The tunnel lives as long as the connection using it.
+        let _tunnel = tunnel;
+        if let Err(e) = connection.await {
+            eprintln!("connection error: {}", e);
         }
-        println!("Header valid");
-
-        // Create the table.
-        let mut table_name = file_name.clone().to_string();
-        table_name.truncate(table_name.len() - 4);
-        table_name = sanitise(table_name);
+    });
 
-        println!("Creating table {table_name}...");
-        create_table(&client, &table_name)
-            .await
-            .with_context(|| format!("error creating table: {table_name}"))?;
+    Ok(client)
+}
 
-        // Filling data in the table.
-        let can = fs::canonicalize(path).unwrap();
-        let abs_path = can.to_str().unwrap();
-        println!("Filling data from {}", abs_path);
-        fill_data(&client, &table_name, &abs_path)
+/// Sets the session time zone, so naive timestamps are read as local times
+/// of `tz` (and stored as UTC) and timestamptz values are printed in it.
+///
+/// Postgres reads the abbreviation `IST` as Israel time by default; for
+/// Indian zones the `India` abbreviation set makes it +05:30.
+async fn set_time_zone(c: &Client, tz: &str) -> Result<()> {
+    for statement in time_zone_statements(tz) {
+        c.batch_execute(&statement)
             .await
-            .with_context(|| format!("error filling table: {table_name}"))?;
-
-        println!("------------------------------------------------------------------\n\n");
+            .with_context(|| format!("invalid time zone: {tz}"))?;
     }
-
-    Ok(())
-}
-
-async fn verify_connection(c: &Client) -> anyhow::Result<()> {
-    c.execute("select 1", &[]).await?;
     Ok(())
 }
 
-async fn create_table(c: &Client, table_name: &str) -> anyhow::Result<()> {
-    let definition = include_str!("static/table_definition.sql");
-    let query = format!("create table if not exists {table_name} {definition}");
-    c.execute(&query, &[])
-        .await
-        .with_context(|| format!("creating table => {table_name}"))?;
-    Ok(())
+/// Statements `set_time_zone` runs.
+fn time_zone_statements(tz: &str) -> Vec<String> {
+    let mut statements = vec![format!("set time zone {}", postgres::literal(tz))];
+    if matches!(tz, "Asia/Kolkata" | "Asia/Calcutta") {
+        statements.push("set timezone_abbreviations = 'India'".to_string());
+    }
+    statements
 }
 
-async fn fill_data(c: &Client, table_name: &str, csv_file_path: &str) -> anyhow::Result<()> {
-    let query = format!(
-        r"
-copy {} (date,close,high,low,open,volume,sma5,sma10,sma15,sma20,ema5,ema10,ema15,ema20,upperband,middleband,lowerband,HT_TRENDLINE,KAMA10,KAMA20,KAMA30,SAR,TRIMA5,TRIMA10,TRIMA20,ADX5,ADX10,ADX20,APO,CCI5,CCI10,CCI15,macd510,macd520,macd1020,macd1520,macd1226,MFI,MOM10,MOM15,MOM20,ROC5,ROC10,ROC20,PPO,RSI14,RSI8,slowk,slowd,fastk,fastd,fastksr,fastdsr,ULTOSC,WILLR,ATR,Trange,TYPPRICE,HT_DCPERIOD,BETA)
-FROM '{}'
-delimiter ','
-csv header
-",
-        table_name, csv_file_path
-    );
-    c.execute(&query, &[])
-        .await
-        .with_context(|| format!("filling data => {table_name}"))?;
+async fn verify_connection(c: &Client) -> Result<()> {
+    c.execute("select 1", &[]).await?;
     Ok(())
 }
-
-fn sanitise(mut s: String) -> String {
-    s = s.replace("-", "_");
-    s = s.replace(" ", "_");
-    s = s.replace(".", "_");
-    s
-}
@@ -0,0 +1,113 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use crate::quality::Quality;
+
+/// Record of the files loaded so far, kept as JSON next to the data.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Manifest {
+    #[serde(skip)]
+    path: PathBuf,
+    /// Entries by file name.
+    pub files: BTreeMap<String, FileEntry>,
+    /// Header validation results by file name.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub headers: BTreeMap<String, HeaderEntry>,
+    /// Files whose load failed after committing some rows, by file name.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub partial: BTreeMap<String, PartialEntry>,
+}
+
+/// Rows of a file committed before its load failed, so a rerun resumes
+/// after them. Size and modification time tie it to the file's contents.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PartialEntry {
+    pub size: u64,
+    pub modified: u64,
+    /// Committed rows by table.
+    pub tables: BTreeMap<String, u64>,
+    /// Committed rows of each connection's share, by distributed table.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub shards: BTreeMap<String, Vec<u64>>,
+}
+
+/// Outcome of validating a file's header, valid for the file's contents and
+/// the column map it was resolved against.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HeaderEntry {
+    pub size: u64,
+    pub modified: u64,
+    #[serde(default)]
+    pub sha256: String,
+    /// `ColumnMap::digest` of the map the header was resolved with.
+    #[serde(default)]
+    pub config: String,
+    /// Resolved columns of a valid header.
+    pub columns: Option<Vec<String>>,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FileEntry {
+    pub size: u64,
+    /// Modification time in seconds since the epoch.
+    pub modified: u64,
+    pub sha256: String,
+    pub tables: Vec<String>,
+    pub rows: u64,
+    /// Load time in seconds since the epoch.
+    pub loaded_at: u64,
+    pub quality: Option<Quality>,
+}
+
+impl Manifest {
+    pub fn load(path: &Path) -> Result<Self> {
+        let mut manifest: Manifest = if path.exists() {
+            let data =
+                fs::read(path).with_context(|| format!("reading manifest: {}", path.display()))?;
+            serde_json::from_slice(&data)
+                .with_context(|| format!("parsing manifest: {}", path.display()))?
+        } else {
+            Manifest::default()
+        };
+        manifest.path = path.to_path_buf();
+        Ok(manifest)
+    }
+
+    /// Writes the manifest through a temporary file so an interrupted run
+    /// never leaves it truncated.
+    pub fn save(&self) -> Result<()> {
+        let tmp = self.path.with_extension("tmp");
+        fs::write(&tmp, serde_json::to_vec_pretty(self)?)
+            .with_context(|| format!("writing manifest: {}", tmp.display()))?;
+        fs::rename(&tmp, &self.path)
+            .with_context(|| format!("writing manifest: {}", self.path.display()))?;
+        Ok(())
+    }
+}
+
+/// Modification time in seconds since the epoch.
+pub fn modified(meta: &fs::Metadata) -> Result<u64> {
+    Ok(meta
+        .modified()?
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs()))
+}
+
+/// Size, modification time and SHA-256 of a file.
+pub fn fingerprint(path: &Path) -> Result<(u64, u64, String)> {
+    let meta =
+        fs::metadata(path).with_context(|| format!("reading metadata: {}", path.display()))?;
+    let modified = modified(&meta)?;
+    let mut hasher = Sha256::new();
+    let mut file =
+        fs::File::open(path).with_context(|| format!("opening file: {}", path.display()))?;
+    io::copy(&mut file, &mut hasher)?;
+    Ok((meta.len(), modified, format!("{:x}", hasher.finalize())))
+}
@@ -0,0 +1,181 @@
+use anyhow::{bail, Context, Result};
+use clap::Args;
+use std::collections::{BTreeMap, HashSet};
+use std::path::PathBuf;
+use tokio_postgres::Client;
+
+use crate::export;
+use crate::manifest::Manifest;
+use crate::schema::{self, IdentifierPolicy, ReservedWords};
+
+#[derive(Debug, Args)]
+pub struct MigrateNamesArgs {
+    /// Table template the tables were loaded with, `{symbol}` standing for
+    /// the file or symbol name.
+    #[clap(long = "from-template")]
+    from_template: String,
+
+    /// Table template to rename them to, e.g. `nse_{symbol}_daily`.
+    #[clap(long = "to-template")]
+    to_template: String,
+
+    /// The tables were loaded with --keep-case.
+    #[clap(long = "keep-case")]
+    keep_case: bool,
+
+    /// How the tables were loaded with --reserved-words.
+    #[clap(long = "reserved-words", value_enum, default_value_t = ReservedWords::Suffix)]
+    reserved_words: ReservedWords,
+
+    /// Manifest of the loads, whose table names are updated too.
+    #[clap(long = "manifest")]
+    manifest: Option<PathBuf>,
+
+    /// Only list the renames.
+    #[clap(long = "dry-run")]
+    dry_run: bool,
+}
+
+/// Renames the managed tables named by one table template to the names of
+/// another, with the indexes named after them, so a new naming convention
+/// needs no reload. The renames are made in one transaction, through
+/// temporary names, so tables may take each other's names.
+pub async fn run(client: &Client, args: &MigrateNamesArgs) -> Result<()> {
+    let policy = |template: &str| -> Result<IdentifierPolicy> {
+        schema::check_template(template)?;
+        Ok(IdentifierPolicy {
+            keep_case: args.keep_case,
+            reserved_words: args.reserved_words,
+            table_template: Some(template.to_string()),
+        })
+    };
+    let from = policy(&args.from_template)?;
+    let to = policy(&args.to_template)?;
+
+    let tables: Vec<String> = export::managed_tables(client)
+        .await?
+        .iter()
+        .map(|table| schema::unquoted(table))
+        .collect();
+    let mut renames = BTreeMap::new();
+    for table in &tables {
+        let Some(symbol) = from.symbol_of(table) else {
+            continue;
+        };
+        let (old, new) = (from.table(&symbol), to.table(&symbol));
+        if old != new {
+            renames.insert(old, new);
+        }
+    }
+    // Checked against the names once every rename is made: a table may
+    // take the name of one renamed away, but not of one that stays.
+    let renamed: HashSet<String> = renames.keys().map(|old| schema::unquoted(old)).collect();
+    let mut names: HashSet<String> = tables
+        .iter()
+        .filter(|table| !renamed.contains(*table))
+        .cloned()
+        .collect();
+    for (old, new) in &renames {
+        let name = schema::unquoted(new);
+        if !renamed.contains(&name) && relation_exists(client, new).await? {
+            names.insert(name.clone());
+        }
+        if !names.insert(name) {
+            bail!("cannot rename {old} to {new}: the name is taken");
+        }
+    }
+    if renames.is_empty() {
+        println!("No tables named by {}", args.from_template);
+        return Ok(());
+    }
+    for (old, new) in &renames {
+        println!("{old} -> {new}");
+    }
+    if args.dry_run {
+        return Ok(());
+    }
+
+    // Every table and index first takes a temporary name, then its new one.
+    let mut query = String::from("begin;\n");
+    let mut finals = Vec::new();
+    for (i, (old, new)) in renames.iter().enumerate() {
+        let temporary = format!("\"pg_nifty_dump_migrate_{i}\"");
+        query.push_str(&format!("alter table {old} rename to {temporary};\n"));
+        finals.push(format!("alter table {temporary} rename to {new};\n"));
+        let (old, new) = (schema::unquoted(old), schema::unquoted(new));
+        for (j, index) in indexes(client, &old).await?.into_iter().enumerate() {
+            let temporary = format!("\"pg_nifty_dump_migrate_{i}_{j}\"");
+            let renamed = format!("{new}{}", &index[old.len()..]);
+            query.push_str(&format!(
+                "alter index {} rename to {temporary};\n",
+                quote(&index)
+            ));
+            finals.push(format!(
+                "alter index {temporary} rename to {};\n",
+                quote(&renamed)
+            ));
+        }
+    }
+    finals
+        .iter()
+        .for_each(|statement| query.push_str(statement));
+    query.push_str("commit;");
+    if let Err(e) = client.batch_execute(&query).await {
+        let _ = client.batch_execute("rollback").await;
+        return Err(e).context("renaming tables");
+    }
+    println!("Renamed {} tables", renames.len());
+
+    if let Some(path) = &args.manifest {
+        let mut manifest = Manifest::load(path)?;
+        for entry in manifest.files.values_mut() {
+            for table in &mut entry.tables {
+                if let Some(new) = renames.get(table) {
+                    *table = new.clone();
+                }
+            }
+        }
+        for entry in manifest.partial.values_mut() {
+            entry.tables = std::mem::take(&mut entry.tables)
+                .into_iter()
+                .map(|(table, rows)| (renames.get(&table).cloned().unwrap_or(table), rows))
+                .collect();
+            entry.shards = std::mem::take(&mut entry.shards)
+                .into_iter()
+                .map(|(table, rows)| (renames.get(&table).cloned().unwrap_or(table), rows))
+                .collect();
+        }
+        manifest.save()?;
+        println!("Updated manifest {}", path.display());
+    }
+    Ok(())
+}
+
+/// Whether a relation of this name, as SQL spells it, exists.
+async fn relation_exists(c: &Client, name: &str) -> Result<bool> {
+    Ok(c.query_one("select to_regclass($1) is not null", &[&name])
+        .await
+        .with_context(|| format!("looking up relation => {name}"))?
+        .get(0))
+}
+
+/// Indexes of the table whose names start with its own, as the server
+/// spells them, e.g. the `<table>_date_idx` of --cluster-on-date.
+async fn indexes(c: &Client, table: &str) -> Result<Vec<String>> {
+    let rows = c
+        .query(
+            "select i.relname::text from pg_index x
+             join pg_class i on i.oid = x.indexrelid
+             where x.indrelid = quote_ident($1)::regclass and starts_with(i.relname, $1)
+             order by 1",
+            &[&table],
+        )
+        .await
+        .with_context(|| format!("listing indexes => {table}"))?;
+    Ok(rows.iter().map(|r| r.get(0)).collect())
+}
+
+/// `name` as an SQL identifier.
+fn quote(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
@@ -0,0 +1,741 @@
+use anyhow::{bail, Context, Result};
+use async_trait::async_trait;
+use futures::channel::mpsc;
+use std::collections::HashMap;
+use std::fs;
+use std::io::{BufRead, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+#[cfg(any(feature = "parquet", feature = "bundle"))]
+use std::process::{Child, ChildStdout, Command, Stdio};
+use std::sync::{Arc, Mutex};
+
+use crate::compression;
+
+/// Rows of one input headed for one table.
+#[derive(Debug, Clone)]
+pub struct Batch {
+    pub table_name: String,
+    pub columns: Vec<String>,
+    pub records: Vec<csv::ByteRecord>,
+    /// Columns beyond the canonical definition, as `(name, type)`.
+    pub extra_columns: Vec<(String, String)>,
+    /// Whether the table holds every symbol, keyed by a `symbol` column.
+    pub shared: bool,
+    /// Where the rows were read from.
+    pub source: String,
+    pub layout: Layout,
+}
+
+/// Table definition a batch is written to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Layout {
+    /// Daily prices and indicators of the cash market.
+    #[default]
+    Indicators,
+    /// Futures and options contracts of one underlying, as in the NSE F&O
+    /// bhavcopy.
+    Derivatives,
+    /// Columns inferred from the data, all given in `extra_columns`.
+    Inferred,
+}
+
+impl Batch {
+    /// Position of `name` within the columns, ignoring case.
+    pub fn column(&self, name: &str) -> Option<usize> {
+        self.columns
+            .iter()
+            .position(|c| c.eq_ignore_ascii_case(name))
+    }
+}
+
+/// Produces the header and rows of one input, a row at a time.
+pub trait Source: Send {
+    fn headers(&mut self) -> Result<csv::StringRecord>;
+
+    /// The next row, or `None` after the last.
+    fn next_record(&mut self) -> Result<Option<csv::ByteRecord>>;
+
+    /// Every remaining row, for stages that need them all at once.
+    fn records(&mut self) -> Result<Vec<csv::ByteRecord>> {
+        let mut records = Vec::new();
+        while let Some(record) = self.next_record()? {
+            records.push(record);
+        }
+        Ok(records)
+    }
+
+    /// Whether any row follows the header. Sources that cannot tell without
+    /// reading every row answer true.
+    fn has_rows(&mut self) -> Result<bool> {
+        Ok(true)
+    }
+
+    /// Whether the input holds no bytes at all, unlike one whose header
+    /// line is blank. Sources that cannot tell answer whether they hold
+    /// neither header nor rows.
+    fn is_empty(&mut self) -> Result<bool> {
+        Ok(self.headers()?.iter().all(str::is_empty) && !self.has_rows()?)
+    }
+
+    /// Local CSV file the database server can read directly, letting the
+    /// loader skip reading rows in Rust when they need no reshaping.
+    fn server_path(&self) -> Option<&Path> {
+        None
+    }
+}
+
+impl<'a> dyn Source + 'a {
+    /// The remaining rows as an iterator.
+    pub fn rows(&mut self) -> Rows<'_, 'a> {
+        Rows { source: self }
+    }
+}
+
+/// Iterator over the rows of a [`Source`].
+pub struct Rows<'s, 'a> {
+    source: &'s mut (dyn Source + 'a),
+}
+
+impl Iterator for Rows<'_, '_> {
+    type Item = Result<csv::ByteRecord>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.source.next_record().transpose()
+    }
+}
+
+/// Receives batches of rows.
+#[async_trait]
+pub trait Sink: Send {
+    /// Writes the batch, creating its table when needed, and returns the
+    /// number of rows written.
+    async fn write(&mut self, batch: Batch) -> Result<u64>;
+
+    /// Called once after the last batch.
+    async fn finish(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Boxed sinks, e.g. from a [`Registry`], can be wrapped like any other.
+#[async_trait]
+impl<S: Sink + ?Sized> Sink for Box<S> {
+    async fn write(&mut self, batch: Batch) -> Result<u64> {
+        (**self).write(batch).await
+    }
+
+    async fn finish(&mut self) -> Result<()> {
+        (**self).finish().await
+    }
+}
+
+/// Sink writing every batch to a second sink too, e.g. to keep a
+/// [`SqlFileSink`] script of what was loaded. The copy is only written once
+/// the first sink wrote the batch, so it holds nothing that failed.
+pub struct TeeSink<A, B> {
+    inner: A,
+    copy: B,
+}
+
+impl<A: Sink, B: Sink> TeeSink<A, B> {
+    pub fn new(inner: A, copy: B) -> Self {
+        TeeSink { inner, copy }
+    }
+}
+
+#[async_trait]
+impl<A: Sink, B: Sink> Sink for TeeSink<A, B> {
+    async fn write(&mut self, batch: Batch) -> Result<u64> {
+        let rows = self.inner.write(batch.clone()).await?;
+        self.copy.write(batch).await?;
+        Ok(rows)
+    }
+
+    async fn finish(&mut self) -> Result<()> {
+        self.inner.finish().await?;
+        self.copy.finish().await
+    }
+}
+
+/// Summary of a batch once its sink has written it.
+#[derive(Debug, Clone)]
+pub struct Loaded {
+    pub table_name: String,
+    /// Distinct symbols of a shared batch; empty for a per-symbol table.
+    pub symbols: Vec<String>,
+    /// Earliest and latest `date` values, as written in the input.
+    pub first_date: Option<String>,
+    pub last_date: Option<String>,
+    pub rows: u64,
+}
+
+impl Loaded {
+    fn summarize(batch: &Batch, rows: u64) -> Self {
+        let field = |i: Option<usize>| {
+            batch
+                .records
+                .iter()
+                .filter_map(move |r| r.get(i?))
+                .map(|f| String::from_utf8_lossy(f).into_owned())
+        };
+        let mut symbols: Vec<String> = field(batch.column("symbol")).collect();
+        symbols.sort();
+        symbols.dedup();
+        // ISO dates and timestamps sort as text.
+        let date = batch.column("date");
+        Loaded {
+            table_name: batch.table_name.clone(),
+            symbols,
+            first_date: field(date).min(),
+            last_date: field(date).max(),
+            rows,
+        }
+    }
+}
+
+/// Sink wrapper announcing every written batch on a stream, for embedding
+/// applications to react to new data as it lands.
+pub struct NotifySink<S> {
+    inner: S,
+    subscriber: mpsc::UnboundedSender<Loaded>,
+}
+
+impl<S: Sink> NotifySink<S> {
+    /// Wraps `inner`, returning the stream of loaded batches. A dropped
+    /// stream does not stop the load.
+    pub fn new(inner: S) -> (Self, mpsc::UnboundedReceiver<Loaded>) {
+        let (subscriber, stream) = mpsc::unbounded();
+        (NotifySink { inner, subscriber }, stream)
+    }
+}
+
+#[async_trait]
+impl<S: Sink> Sink for NotifySink<S> {
+    async fn write(&mut self, batch: Batch) -> Result<u64> {
+        let summary = Loaded::summarize(&batch, 0);
+        let rows = self.inner.write(batch).await?;
+        let _ = self.subscriber.unbounded_send(Loaded { rows, ..summary });
+        Ok(rows)
+    }
+
+    async fn finish(&mut self) -> Result<()> {
+        self.inner.finish().await
+    }
+}
+
+pub type SourceFactory = Box<dyn Fn(&Path) -> Result<Box<dyn Source>> + Send + Sync>;
+pub type SinkFactory = Box<dyn Fn(&str) -> Result<Box<dyn Sink>> + Send + Sync>;
+
+/// Sources by file extension and sinks by scheme. Compressed files are
+/// recognised by their contents and read by the source of the name inside
+/// the compression suffix, or as CSV when there is none. Sinks are named as
+/// `scheme:target`, e.g. `sql-file:dump.sql`.
+///
+/// Sources read local files; remote ones are downloaded into the input
+/// directory first (`--fetch`) and read from there.
+#[derive(Default)]
+pub struct Registry {
+    sources: HashMap<String, SourceFactory>,
+    sinks: HashMap<String, SinkFactory>,
+}
+
+impl Registry {
+    /// A registry with the built-in CSV and Parquet sources and SQL-file
+    /// and DuckDB sinks, Parquet and DuckDB as the `parquet` and `bundle`
+    /// features allow. The Postgres sink needs a live connection and is
+    /// built by the caller.
+    pub fn with_builtins() -> Self {
+        let mut registry = Registry::default();
+        registry.register_source("csv", |path| Ok(Box::new(CsvSource::open(path)?)));
+        #[cfg(feature = "parquet")]
+        registry.register_source("parquet", |path| Ok(Box::new(ParquetSource::open(path)?)));
+        registry.register_sink("sql-file", |target| {
+            Ok(Box::new(SqlFileSink::create(target)?))
+        });
+        #[cfg(feature = "bundle")]
+        registry.register_sink("duckdb", |target| Ok(Box::new(DuckDbSink::create(target)?)));
+        registry
+    }
+
+    pub fn register_source<F>(&mut self, extension: &str, factory: F)
+    where
+        F: Fn(&Path) -> Result<Box<dyn Source>> + Send + Sync + 'static,
+    {
+        self.sources
+            .insert(extension.to_lowercase(), Box::new(factory));
+    }
+
+    pub fn register_sink<F>(&mut self, scheme: &str, factory: F)
+    where
+        F: Fn(&str) -> Result<Box<dyn Sink>> + Send + Sync + 'static,
+    {
+        self.sinks.insert(scheme.to_string(), Box::new(factory));
+    }
+
+    /// Whether some source handles the file.
+    pub fn handles(&self, path: &Path) -> bool {
+        self.source_extension(path).is_some()
+    }
+
+    pub fn open_source(&self, path: &Path) -> Result<Box<dyn Source>> {
+        let ext = self
+            .source_extension(path)
+            .or_else(|| extension(path))
+            .unwrap_or_default();
+        match self.sources.get(&ext) {
+            Some(factory) => factory(path),
+            None => bail!("no source for {:?} files: {}", ext, path.display()),
+        }
+    }
+
+    fn source_extension(&self, path: &Path) -> Option<String> {
+        let registered = |e: &String| self.sources.contains_key(e);
+        if let Some(ext) = extension(path).filter(registered) {
+            return Some(ext);
+        }
+        match compression::sniff(path) {
+            Ok(Some(_)) => Some(
+                extension(compression::strip_extension(path))
+                    .filter(registered)
+                    .unwrap_or_else(|| "csv".to_string()),
+            ),
+            _ => None,
+        }
+    }
+
+    pub fn open_sink(&self, spec: &str) -> Result<Box<dyn Sink>> {
+        let (scheme, target) = spec.split_once(':').unwrap_or((spec, ""));
+        match self.sinks.get(scheme) {
+            Some(factory) => factory(target),
+            None => bail!("no sink registered for: {scheme}"),
+        }
+    }
+}
+
+fn extension(path: &Path) -> Option<String> {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_lowercase())
+}
+
+/// How [`CsvSource`] reads files that do not start with their header.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CsvOptions {
+    /// Lines before the header, e.g. a download timestamp or disclaimer.
+    pub skip_lines: usize,
+    /// Lines starting with this byte are ignored anywhere in the file.
+    pub comment: Option<u8>,
+}
+
+/// Comma separated file with a header line, optionally compressed.
+pub struct CsvSource {
+    path: PathBuf,
+    /// Whether the server's COPY can read the file as it is.
+    plain: bool,
+    reader: csv::Reader<Box<dyn Read + Send>>,
+    /// First row, read ahead by `has_rows`.
+    peeked: Option<csv::ByteRecord>,
+}
+
+impl CsvSource {
+    pub fn open(path: &Path) -> Result<Self> {
+        Self::open_with(path, CsvOptions::default())
+    }
+
+    pub fn open_with(path: &Path, options: CsvOptions) -> Result<Self> {
+        let compressed = compression::sniff(path)?.is_some();
+        let mut input = BufReader::new(compression::open(path)?);
+        let mut line = Vec::new();
+        for _ in 0..options.skip_lines {
+            line.clear();
+            input
+                .read_until(b'\n', &mut line)
+                .with_context(|| format!("skipping lines: {}", path.display()))?;
+        }
+        let input: Box<dyn Read + Send> = Box::new(input);
+        let reader = csv::ReaderBuilder::new()
+            .comment(options.comment)
+            .from_reader(input);
+        Ok(CsvSource {
+            path: fs::canonicalize(path)?,
+            plain: !compressed && options.skip_lines == 0 && options.comment.is_none(),
+            reader,
+            peeked: None,
+        })
+    }
+}
+
+impl Source for CsvSource {
+    fn headers(&mut self) -> Result<csv::StringRecord> {
+        let headers = self
+            .reader
+            .headers()
+            .with_context(|| format!("fetching headers from file: {}", self.path.display()))?;
+        Ok(headers.clone())
+    }
+
+    fn next_record(&mut self) -> Result<Option<csv::ByteRecord>> {
+        if let Some(record) = self.peeked.take() {
+            return Ok(Some(record));
+        }
+        let mut record = csv::ByteRecord::new();
+        let read = self
+            .reader
+            .read_byte_record(&mut record)
+            .with_context(|| format!("reading record: {}", self.path.display()))?;
+        Ok(read.then_some(record))
+    }
+
+    fn has_rows(&mut self) -> Result<bool> {
+        if self.peeked.is_none() {
+            let mut record = csv::ByteRecord::new();
+            if self
+                .reader
+                .read_byte_record(&mut record)
+                .with_context(|| format!("reading record: {}", self.path.display()))?
+            {
+                self.peeked = Some(record);
+            }
+        }
+        Ok(self.peeked.is_some())
+    }
+
+    fn is_empty(&mut self) -> Result<bool> {
+        // Reading the header consumes the blank lines before it.
+        self.headers()?;
+        Ok(self.reader.position().byte() == 0)
+    }
+
+    fn server_path(&self) -> Option<&Path> {
+        // The server's COPY only skips the header line.
+        self.plain.then_some(self.path.as_path())
+    }
+}
+
+/// Parquet file, read as CSV from the `duckdb` CLI as it decodes it, so no
+/// Parquet library is linked in.
+#[cfg(feature = "parquet")]
+pub struct ParquetSource {
+    path: PathBuf,
+    child: Child,
+    reader: csv::Reader<ChildStdout>,
+}
+
+#[cfg(feature = "parquet")]
+impl ParquetSource {
+    pub fn open(path: &Path) -> Result<Self> {
+        let query = format!(
+            "select * from read_parquet('{}')",
+            path.display().to_string().replace('\'', "''")
+        );
+        let mut child = Command::new("duckdb")
+            .args(["-csv", "-c", &query])
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .spawn()
+            .context("running duckdb; is it installed?")?;
+        let stdout = child.stdout.take().unwrap();
+        Ok(ParquetSource {
+            path: path.to_path_buf(),
+            child,
+            reader: csv::Reader::from_reader(stdout),
+        })
+    }
+}
+
+#[cfg(feature = "parquet")]
+impl Source for ParquetSource {
+    fn headers(&mut self) -> Result<csv::StringRecord> {
+        let headers = self
+            .reader
+            .headers()
+            .with_context(|| format!("fetching headers from file: {}", self.path.display()))?;
+        Ok(headers.clone())
+    }
+
+    fn next_record(&mut self) -> Result<Option<csv::ByteRecord>> {
+        let mut record = csv::ByteRecord::new();
+        let read = self
+            .reader
+            .read_byte_record(&mut record)
+            .with_context(|| format!("reading record: {}", self.path.display()))?;
+        if !read {
+            let status = self.child.wait()?;
+            if !status.success() {
+                bail!("duckdb failed reading {}: {status}", self.path.display());
+            }
+        }
+        Ok(read.then_some(record))
+    }
+}
+
+#[cfg(feature = "parquet")]
+impl Drop for ParquetSource {
+    fn drop(&mut self) {
+        // A source dropped before its last row leaves no duckdb behind.
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// psql script of statements, one per line ending with `;`, shared by a
+/// [`SqlFileSink`] and the code running statements around its batches so
+/// that they land in the order they ran.
+#[derive(Clone)]
+pub struct Script {
+    writer: Arc<Mutex<BufWriter<fs::File>>>,
+}
+
+impl Script {
+    pub fn create(path: &str) -> Result<Self> {
+        let file = fs::File::create(path).with_context(|| format!("creating file: {path}"))?;
+        Ok(Script {
+            writer: Arc::new(Mutex::new(BufWriter::new(file))),
+        })
+    }
+
+    /// Appends a statement, or several separated by `;`, that ran.
+    pub fn statement(&self, sql: &str) -> Result<()> {
+        let mut w = self.writer.lock().unwrap();
+        writeln!(w, "{};", sql.trim().trim_end_matches(';'))?;
+        Ok(())
+    }
+
+    pub fn flush(&self) -> Result<()> {
+        self.writer.lock().unwrap().flush()?;
+        Ok(())
+    }
+}
+
+/// psql script recreating the tables and their rows with `COPY ... FROM
+/// stdin`, for loading somewhere the tool cannot connect to.
+pub struct SqlFileSink {
+    script: Script,
+}
+
+impl SqlFileSink {
+    pub fn create(path: &str) -> Result<Self> {
+        if path.is_empty() {
+            bail!("sql-file sink needs a path, e.g. sql-file:dump.sql");
+        }
+        Ok(SqlFileSink::new(Script::create(path)?))
+    }
+
+    /// Writes the batches to a script other statements go to as well.
+    pub fn new(script: Script) -> Self {
+        SqlFileSink { script }
+    }
+}
+
+#[async_trait]
+impl Sink for SqlFileSink {
+    async fn write(&mut self, batch: Batch) -> Result<u64> {
+        use crate::postgres;
+
+        let mut w = self.script.writer.lock().unwrap();
+        let w = &mut *w;
+        writeln!(w, "-- source: {}", batch.source)?;
+        if let Some(schema) = postgres::schema_of(&batch.table_name) {
+            writeln!(w, "create schema if not exists {schema};")?;
+        }
+        writeln!(
+            w,
+            "{};",
+            postgres::create_table_sql(&batch.table_name, batch.layout, batch.shared)
+        )?;
+        for (column, data_type) in &batch.extra_columns {
+            writeln!(
+                w,
+                "alter table {} add column if not exists {column} {data_type};",
+                batch.table_name
+            )?;
+        }
+        writeln!(
+            w,
+            "copy {} ({}) from stdin with (format csv);",
+            batch.table_name,
+            batch.columns.join(",")
+        )?;
+        let rows = batch.records.len() as u64;
+        let mut wtr = csv::Writer::from_writer(&mut *w);
+        for record in &batch.records {
+            wtr.write_byte_record(record)?;
+        }
+        wtr.flush()?;
+        drop(wtr);
+        writeln!(w, "\\.")?;
+        // Stamped with the time the script runs, as a load would be.
+        let comment = postgres::load_comment("\0", &batch.source, rows);
+        let (head, tail) = comment.split_once('\0').unwrap();
+        writeln!(
+            w,
+            "do $$ begin execute format('comment on table %s is %L', {}, {} || now() || {}); end $$;\n",
+            postgres::literal(&batch.table_name),
+            postgres::literal(head),
+            postgres::literal(tail),
+        )?;
+        Ok(rows)
+    }
+
+    async fn finish(&mut self) -> Result<()> {
+        self.script.flush()
+    }
+}
+
+/// DuckDB database file, for analysis without Postgres. Each batch is
+/// spooled to a temporary CSV and appended by the `duckdb` CLI to its table,
+/// which is created from the batch's definition when missing.
+#[cfg(feature = "bundle")]
+pub struct DuckDbSink {
+    path: PathBuf,
+}
+
+#[cfg(feature = "bundle")]
+impl DuckDbSink {
+    pub fn create(path: &str) -> Result<Self> {
+        if path.is_empty() {
+            bail!("duckdb sink needs a path, e.g. duckdb:nifty.duckdb");
+        }
+        Ok(DuckDbSink {
+            path: PathBuf::from(path),
+        })
+    }
+}
+
+#[cfg(feature = "bundle")]
+#[async_trait]
+impl Sink for DuckDbSink {
+    async fn write(&mut self, batch: Batch) -> Result<u64> {
+        let (schema, table) = duckdb_name(&batch.table_name);
+        let mut definition: Vec<String> = Vec::new();
+        if batch.shared {
+            definition.push("symbol text not null".to_string());
+        }
+        for (column, data_type) in crate::postgres::table_columns(batch.layout) {
+            definition.push(format!("\"{}\" {data_type}", column.to_lowercase()));
+        }
+        // Inferred tables have no definition besides these.
+        for (column, data_type) in &batch.extra_columns {
+            definition.push(format!("{column} {data_type}"));
+        }
+        let mut script = String::new();
+        if let Some(schema) = schema {
+            script.push_str(&format!("create schema if not exists {schema};\n"));
+        }
+        script.push_str(&format!(
+            "create table if not exists {table} ({});\n",
+            definition.join(", ")
+        ));
+        for (column, data_type) in &batch.extra_columns {
+            script.push_str(&format!(
+                "alter table {table} add column if not exists {column} {data_type};\n"
+            ));
+        }
+
+        let spool = tempfile::NamedTempFile::new().context("creating spool file")?;
+        let mut wtr = csv::Writer::from_writer(BufWriter::new(spool.as_file()));
+        for record in &batch.records {
+            wtr.write_byte_record(record)?;
+        }
+        wtr.flush()?;
+        drop(wtr);
+        script.push_str(&format!(
+            "copy {table} ({}) from '{}' (header false);\n",
+            batch.columns.join(", "),
+            spool.path().display().to_string().replace('\'', "''")
+        ));
+
+        let mut child = Command::new("duckdb")
+            .arg(&self.path)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped())
+            .spawn()
+            .context("running duckdb; is it installed?")?;
+        child.stdin.take().unwrap().write_all(script.as_bytes())?;
+        let output = child.wait_with_output().context("running duckdb")?;
+        if !output.status.success() {
+            bail!(
+                "duckdb failed loading {table}: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+        Ok(batch.records.len() as u64)
+    }
+}
+
+/// The schema, if any, and the schema-qualified table of a Postgres table
+/// name, each part quoted for DuckDB.
+#[cfg(any(feature = "bundle", test))]
+fn duckdb_name(table_name: &str) -> (Option<String>, String) {
+    let mut parts = vec![String::new()];
+    let mut quoted = false;
+    let mut chars = table_name.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                chars.next();
+                parts.last_mut().unwrap().push('"');
+            }
+            '"' => quoted = !quoted,
+            '.' if !quoted => parts.push(String::new()),
+            c => parts.last_mut().unwrap().push(c),
+        }
+    }
+    let quote = |part: &String| format!("\"{}\"", part.replace('"', "\"\""));
+    let schema = (parts.len() > 1).then(|| quote(&parts[0]));
+    let table = parts.iter().map(quote).collect::<Vec<_>>().join(".");
+    (schema, table)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn open(contents: &str) -> (tempfile::TempDir, CsvSource) {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("INFY.csv");
+        fs::write(&path, contents).unwrap();
+        let source = CsvSource::open(&path).unwrap();
+        (dir, source)
+    }
+
+    #[test]
+    fn zero_byte_file_is_empty() {
+        let (_dir, mut source) = open("");
+        assert!(source.is_empty().unwrap());
+        assert!(!source.has_rows().unwrap());
+    }
+
+    #[test]
+    fn blank_header_line_is_not_empty() {
+        let (_dir, mut source) = open("\n");
+        assert!(!source.is_empty().unwrap());
+        assert!(source.headers().unwrap().iter().all(str::is_empty));
+    }
+
+    #[test]
+    fn header_only_file_has_no_rows() {
+        let (_dir, mut source) = open("date,close\n");
+        assert!(!source.is_empty().unwrap());
+        assert!(!source.has_rows().unwrap());
+        let (_dir, mut source) = open("date,close\n2024-01-01,10\n");
+        assert!(source.has_rows().unwrap());
+        assert_eq!(source.records().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn duckdb_name_quotes_each_part() {
+        assert_eq!(duckdb_name("infy"), (None, "\"infy\"".to_string()));
+        assert_eq!(duckdb_name("\"INFY\""), (None, "\"INFY\"".to_string()));
+        assert_eq!(
+            duckdb_name("nse.infy"),
+            (Some("\"nse\"".to_string()), "\"nse\".\"infy\"".to_string())
+        );
+        assert_eq!(
+            duckdb_name("\"NSE\".\"M.M\""),
+            (Some("\"NSE\"".to_string()), "\"NSE\".\"M.M\"".to_string())
+        );
+        assert_eq!(duckdb_name("\"a\"\"b\""), (None, "\"a\"\"b\"".to_string()));
+    }
+}
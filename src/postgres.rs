@@ -0,0 +1,657 @@
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::Duration;
+use tokio_postgres::error::SqlState;
+use tokio_postgres::Client;
+
+use crate::copy::{self, CommitEvery};
+use crate::pipeline::{Batch, Layout, Script, Sink};
+
+static TABLE_DEFINITION: &str = include_str!("static/table_definition.sql");
+static FO_BHAVCOPY_DEFINITION: &str = include_str!("static/fo_bhavcopy_definition.sql");
+
+/// Attempts at `if not exists` DDL racing with other workers.
+const DDL_ATTEMPTS: u64 = 5;
+
+/// Longest Windows path usable without the `\\?\` prefix, counting the
+/// terminating NUL.
+const MAX_PATH: usize = 260;
+
+/// `create table` statement of a per-symbol table, or of the shared table
+/// with its extra `symbol` column.
+pub fn create_table_sql(table_name: &str, layout: Layout, shared: bool) -> String {
+    let definition = match layout {
+        Layout::Indicators => TABLE_DEFINITION,
+        Layout::Derivatives => FO_BHAVCOPY_DEFINITION,
+        Layout::Inferred => "()",
+    };
+    let definition = if shared {
+        definition.replacen('(', "(\n    symbol text not null,", 1)
+    } else {
+        definition.to_string()
+    };
+    format!(
+        "create table if not exists {table_name} {}",
+        definition.trim_end().trim_end_matches(';')
+    )
+}
+
+/// Columns and types of the table definition, in order.
+pub fn table_columns(layout: Layout) -> Vec<(&'static str, &'static str)> {
+    let definition = match layout {
+        Layout::Indicators => TABLE_DEFINITION,
+        Layout::Derivatives => FO_BHAVCOPY_DEFINITION,
+        Layout::Inferred => "()",
+    };
+    definition
+        .lines()
+        .filter_map(|line| {
+            let line = line.trim().trim_end_matches(',');
+            let (name, data_type) = line.split_once(' ')?;
+            Some((name, data_type.trim()))
+        })
+        .collect()
+}
+
+/// Creates the table, and its schema when the name is qualified.
+pub async fn create_table(
+    c: &Client,
+    table_name: &str,
+    layout: Layout,
+    shared: bool,
+) -> Result<()> {
+    if let Some(schema) = schema_of(table_name) {
+        execute_ddl(c, &format!("create schema if not exists {schema}"))
+            .await
+            .with_context(|| format!("creating schema => {schema}"))?;
+    }
+    execute_ddl(c, &create_table_sql(table_name, layout, shared))
+        .await
+        .with_context(|| format!("creating table => {table_name}"))?;
+    Ok(())
+}
+
+/// Creates the batch's table and adds its extra columns, where missing.
+/// Through [`execute_ddl`], so not inside a transaction: a load writing in
+/// one prepares the table before it begins, leaving these no-ops.
+pub async fn prepare_table(c: &Client, batch: &Batch) -> Result<()> {
+    create_table(c, &batch.table_name, batch.layout, batch.shared).await?;
+    for (column, data_type) in &batch.extra_columns {
+        let query = format!(
+            "alter table {} add column if not exists {column} {data_type}",
+            batch.table_name
+        );
+        execute_ddl(c, &query)
+            .await
+            .with_context(|| format!("adding column => {column}"))?;
+    }
+    Ok(())
+}
+
+/// String literal of a file path for the server's `COPY ... FROM`.
+///
+/// Windows paths are put in the form the server's file API accepts:
+/// verbatim paths as `fs::canonicalize` returns them (`\\?\C:\...`,
+/// `\\?\UNC\server\share\...`) lose their prefix when short enough to
+/// do without it, and drive or UNC paths too long for `MAX_PATH` gain it.
+/// Quotes and backslashes are escaped whatever `standard_conforming_strings`
+/// is set to.
+pub fn server_path_literal(path: &str) -> String {
+    literal(&windows_path(path))
+}
+
+/// SQL string literal of a value, read the same whatever
+/// `standard_conforming_strings` is set to.
+pub fn literal(value: &str) -> String {
+    let value = value.replace('\'', "''");
+    if value.contains('\\') {
+        format!("E'{}'", value.replace('\\', "\\\\"))
+    } else {
+        format!("'{value}'")
+    }
+}
+
+/// SQL `text[]` literal of the values.
+pub fn text_array_literal(values: &[String]) -> String {
+    let values: Vec<String> = values.iter().map(|v| literal(v)).collect();
+    format!("array[{}]::text[]", values.join(","))
+}
+
+fn windows_path(path: &str) -> String {
+    let plain = if let Some(rest) = path.strip_prefix(r"\\?\UNC\") {
+        format!(r"\\{rest}")
+    } else if let Some(rest) = path
+        .strip_prefix(r"\\?\")
+        .filter(|rest| is_drive_path(rest))
+    {
+        rest.to_string()
+    } else if path.starts_with(r"\\?\") {
+        // Other verbatim paths, such as volume GUIDs, need their prefix.
+        return path.to_string();
+    } else {
+        path.to_string()
+    };
+    let unc = plain.starts_with(r"\\");
+    if plain.chars().count() < MAX_PATH || !(unc || is_drive_path(&plain)) {
+        return plain;
+    }
+    // Verbatim paths take no forward slashes.
+    let plain = plain.replace('/', "\\");
+    match plain.strip_prefix(r"\\") {
+        Some(rest) => format!(r"\\?\UNC\{rest}"),
+        None => format!(r"\\?\{plain}"),
+    }
+}
+
+/// Whether the path starts with a drive letter, e.g. `C:\`.
+fn is_drive_path(path: &str) -> bool {
+    let bytes = path.as_bytes();
+    bytes.len() >= 2 && bytes[0].is_ascii_alphabetic() && bytes[1] == b':'
+}
+
+/// Runs `if not exists` DDL, retrying when it lost a race with another
+/// session creating the same object: the existence check passes in both,
+/// and the slower one fails on the catalog's unique index (23505) or with
+/// 42P07. Must not run inside a transaction, which the failure aborts.
+pub async fn execute_ddl(c: &Client, query: &str) -> Result<(), tokio_postgres::Error> {
+    let mut attempt = 1;
+    loop {
+        match c.batch_execute(query).await {
+            Err(e) if attempt < DDL_ATTEMPTS && is_creation_race(&e) => {
+                tokio::time::sleep(Duration::from_millis(50 * attempt)).await;
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+fn is_creation_race(e: &tokio_postgres::Error) -> bool {
+    e.code().is_some_and(|code| {
+        [
+            SqlState::UNIQUE_VIOLATION,
+            SqlState::DUPLICATE_TABLE,
+            SqlState::DUPLICATE_SCHEMA,
+            SqlState::DUPLICATE_OBJECT,
+            SqlState::DUPLICATE_COLUMN,
+        ]
+        .contains(code)
+    })
+}
+
+/// Schema of a qualified table name, e.g. `market` of `market.infy` or
+/// `"Market"` of `"Market"."INFY"`.
+pub fn schema_of(table_name: &str) -> Option<&str> {
+    let mut quoted = false;
+    for (i, ch) in table_name.char_indices() {
+        match ch {
+            '"' => quoted = !quoted,
+            '.' if !quoted => return Some(&table_name[..i]),
+            _ => {}
+        }
+    }
+    None
+}
+
+pub async fn stamp_comment(
+    c: &Client,
+    table_name: &str,
+    csv_file_path: &str,
+    rows: u64,
+) -> Result<()> {
+    let loaded_at: String = c.query_one("select now()::text", &[]).await?.get(0);
+    let comment = load_comment(&loaded_at, csv_file_path, rows);
+    let query = format!("comment on table {table_name} is {}", literal(&comment));
+    c.execute(&query, &[])
+        .await
+        .with_context(|| format!("commenting table => {table_name}"))?;
+    Ok(())
+}
+
+/// Comment `stamp_comment` gives a table.
+pub fn load_comment(loaded_at: &str, csv_file_path: &str, rows: u64) -> String {
+    format!(
+        "pg_nifty_dump: loaded_at={loaded_at}, source={csv_file_path}, rows={rows}, version={}",
+        env!("CARGO_PKG_VERSION")
+    )
+}
+
+/// Rewrites the table in date order (symbol and date for a shared table)
+/// by clustering on an index over it, which is created if missing, then
+/// sets the fillfactor for later writes and refreshes the statistics.
+pub async fn cluster_on_date(
+    c: &Client,
+    table_name: &str,
+    shared: bool,
+    fillfactor: u8,
+    script: Option<&Script>,
+) -> Result<()> {
+    let index = format!("{}_date_idx", table_name.trim_matches('"'));
+    let key = if shared { "symbol, date" } else { "date" };
+    let query = format!(
+        "create index if not exists \"{index}\" on {table_name} ({key});
+         alter table {table_name} set (fillfactor = {fillfactor});
+         cluster {table_name} using \"{index}\";
+         analyze {table_name}"
+    );
+    c.batch_execute(&query)
+        .await
+        .with_context(|| format!("clustering table => {table_name}"))?;
+    if let Some(script) = script {
+        script.statement(&query)?;
+    }
+    Ok(())
+}
+
+/// Copies batches into Postgres over the client-side COPY path, stamping
+/// each table's comment with the load metadata.
+pub struct PostgresSink<'a> {
+    client: &'a Client,
+    /// Remote tables found to reject COPY, loaded with INSERT instead.
+    insert_only: HashSet<String>,
+    commit_every: CommitEvery,
+    /// Rows of the current batch committed so far.
+    committed: Arc<AtomicU64>,
+    /// Extra connections spreading distributed hypertable loads.
+    workers: Vec<Client>,
+    /// Rows of each connection's share of the current distributed batch
+    /// committed: set beforehand to what an earlier run committed, which is
+    /// skipped, and updated as the shares are written.
+    shards: Arc<Mutex<Vec<u64>>>,
+}
+
+impl<'a> PostgresSink<'a> {
+    pub fn new(client: &'a Client) -> Self {
+        PostgresSink {
+            client,
+            insert_only: HashSet::new(),
+            commit_every: CommitEvery::default(),
+            committed: Arc::new(AtomicU64::new(0)),
+            workers: Vec::new(),
+            shards: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+
+    /// Loads the symbols of batches into distributed hypertables over these
+    /// extra connections as well as the main one, so the access node
+    /// forwards several COPY streams to the data nodes at once. `shards`
+    /// holds the rows of each connection's share committed, so a failed
+    /// batch can resume share by share.
+    pub fn distribute_over(mut self, workers: Vec<Client>, shards: Arc<Mutex<Vec<u64>>>) -> Self {
+        self.workers = workers;
+        self.shards = shards;
+        self
+    }
+
+    /// Commits local tables at the given interval, publishing the rows of
+    /// the current batch committed so far in `committed`.
+    pub fn commit_every(mut self, every: CommitEvery, committed: Arc<AtomicU64>) -> Self {
+        self.commit_every = every;
+        self.committed = committed;
+        self
+    }
+
+    /// Loads into a foreign table or distributed hypertable. Their DDL
+    /// belongs to the remote side, so the table is neither created, altered
+    /// nor commented; COPY is tried first and INSERT used if it is refused.
+    async fn write_remote(&mut self, batch: Batch) -> Result<u64> {
+        let c = self.client;
+        let table_name = &batch.table_name;
+        if !self.insert_only.contains(table_name) {
+            // A refused COPY fails before any row is sent, so the rows are
+            // only cloned once COPY is underway.
+            let result =
+                copy::copy_records(c, table_name, &batch.columns, batch.records.iter().cloned())
+                    .await;
+            match result {
+                Err(e) if copy_refused(&e) => {
+                    self.insert_only.insert(table_name.clone());
+                }
+                result => return result,
+            }
+        }
+        copy::insert_records(c, table_name, &batch.columns, batch.records).await
+    }
+
+    /// Copies a batch into a distributed hypertable with its symbols split
+    /// across the connections, largest first onto the least loaded one.
+    /// Each symbol goes through one connection, so with the hypertable
+    /// space-partitioned by symbol each stream feeds few data nodes.
+    ///
+    /// The shares commit at the `commit_every` interval, each on its own,
+    /// and fall back to INSERT like `write_remote` when COPY is refused.
+    async fn write_distributed(&mut self, batch: Batch, symbol: usize) -> Result<u64> {
+        let mut by_symbol: HashMap<Vec<u8>, Vec<csv::ByteRecord>> = HashMap::new();
+        for record in batch.records {
+            let key = record.get(symbol).unwrap_or_default().to_vec();
+            by_symbol.entry(key).or_default().push(record);
+        }
+        let mut groups: Vec<(Vec<u8>, Vec<csv::ByteRecord>)> = by_symbol.into_iter().collect();
+        groups.sort_by(|a, b| b.1.len().cmp(&a.1.len()).then_with(|| a.0.cmp(&b.0)));
+
+        let clients: Vec<&Client> = std::iter::once(self.client)
+            .chain(self.workers.iter())
+            .collect();
+        let mut shares: Vec<Vec<csv::ByteRecord>> = vec![Vec::new(); clients.len()];
+        for (_, records) in groups {
+            let least = (0..shares.len()).min_by_key(|&i| shares[i].len()).unwrap();
+            shares[least].extend(records);
+        }
+
+        let table_name = &batch.table_name;
+        let resumed = std::mem::take(&mut *self.shards.lock().unwrap());
+        if !resumed.is_empty() && resumed.len() != clients.len() {
+            anyhow::bail!(
+                "{table_name} was partly loaded over {} connections; resume with as many",
+                resumed.len()
+            );
+        }
+        for (records, &skipped) in shares.iter_mut().zip(&resumed) {
+            records.drain(..(skipped as usize).min(records.len()));
+        }
+
+        let committed: Vec<AtomicU64> = resumed
+            .iter()
+            .copied()
+            .chain(std::iter::repeat(0))
+            .take(clients.len())
+            .map(AtomicU64::new)
+            .collect();
+        let insert_only = self.insert_only.contains(table_name);
+        let writes = clients
+            .into_iter()
+            .zip(shares)
+            .zip(&committed)
+            .filter(|((_, records), _)| !records.is_empty())
+            .map(|((c, records), committed)| {
+                self.write_share(
+                    c,
+                    &batch.columns,
+                    table_name,
+                    records,
+                    insert_only,
+                    committed,
+                )
+            });
+        // Every share runs to its end, so what each committed is known.
+        let results = futures::future::join_all(writes).await;
+        *self.shards.lock().unwrap() = committed
+            .iter()
+            .map(|c| c.load(Ordering::Relaxed))
+            .collect();
+
+        let mut rows = 0;
+        for result in results {
+            match result {
+                Ok((share_rows, refused)) => {
+                    rows += share_rows;
+                    if refused {
+                        self.insert_only.insert(table_name.clone());
+                    }
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(rows)
+    }
+
+    /// Writes one share of a distributed batch, adding the rows it commits
+    /// to `committed`. Returns the rows written and whether COPY was
+    /// refused, the rows going through INSERT instead.
+    async fn write_share(
+        &self,
+        c: &Client,
+        columns: &[String],
+        table_name: &str,
+        records: Vec<csv::ByteRecord>,
+        insert_only: bool,
+        committed: &AtomicU64,
+    ) -> Result<(u64, bool)> {
+        let before = committed.load(Ordering::Relaxed);
+        if !insert_only {
+            let share = AtomicU64::new(0);
+            // As in `write_remote`, a refused COPY fails before any row is
+            // sent, so the rows are only cloned once COPY is underway.
+            let result = copy::copy_records_committing(
+                c,
+                table_name,
+                columns,
+                records.iter().cloned(),
+                self.commit_every,
+                &share,
+            )
+            .await;
+            committed.store(before + share.load(Ordering::Relaxed), Ordering::Relaxed);
+            match result {
+                Err(e) if copy_refused(&e) => {}
+                result => return result.map(|rows| (rows, false)),
+            }
+        }
+        let share = AtomicU64::new(0);
+        let result = copy::insert_records_committing(c, table_name, columns, records, &share).await;
+        committed.store(before + share.load(Ordering::Relaxed), Ordering::Relaxed);
+        Ok((result?, !insert_only))
+    }
+}
+
+/// Chunks and bytes a distributed hypertable holds on one data node.
+#[derive(Debug, Clone, Serialize)]
+pub struct DataNodeUsage {
+    pub node: String,
+    pub chunks: i64,
+    pub bytes: i64,
+}
+
+/// Where a distributed hypertable's data lives, by data node.
+pub async fn data_node_usage(c: &Client, table_name: &str) -> Result<Vec<DataNodeUsage>> {
+    let rows = c
+        .query(
+            "with chunks as (
+                 select node, count(*) as chunks
+                 from timescaledb_information.chunks ch, unnest(ch.data_nodes) node
+                 where format('%I.%I', ch.hypertable_schema, ch.hypertable_name)::regclass
+                     = to_regclass($1)
+                 group by node
+             )
+             select chunks.node::text, chunks.chunks, coalesce(s.total_bytes, 0)
+             from chunks
+             left join hypertable_detailed_size(to_regclass($1)) s on s.node_name = chunks.node
+             order by 1",
+            &[&table_name],
+        )
+        .await
+        .with_context(|| format!("reading data node usage => {table_name}"))?;
+    Ok(rows
+        .iter()
+        .map(|r| DataNodeUsage {
+            node: r.get(0),
+            chunks: r.get(1),
+            bytes: r.get(2),
+        })
+        .collect())
+}
+
+fn copy_refused(e: &anyhow::Error) -> bool {
+    let code = e
+        .downcast_ref::<tokio_postgres::Error>()
+        .and_then(|e| e.code());
+    code == Some(&SqlState::FEATURE_NOT_SUPPORTED) || code == Some(&SqlState::WRONG_OBJECT_TYPE)
+}
+
+/// What a target name refers to on the server.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RelationKind {
+    Missing,
+    Table,
+    /// A foreign table, e.g. over `postgres_fdw`.
+    Foreign,
+    /// A TimescaleDB hypertable distributed over data nodes.
+    DistributedHypertable,
+}
+
+impl RelationKind {
+    /// Whether the data and DDL live on another server.
+    pub fn is_remote(self) -> bool {
+        matches!(
+            self,
+            RelationKind::Foreign | RelationKind::DistributedHypertable
+        )
+    }
+}
+
+pub async fn relation_kind(c: &Client, table_name: &str) -> Result<RelationKind> {
+    let row = c
+        .query_opt(
+            "select c.relkind::text from pg_class c where c.oid = to_regclass($1)",
+            &[&table_name],
+        )
+        .await
+        .with_context(|| format!("looking up relation => {table_name}"))?;
+    let Some(row) = row else {
+        return Ok(RelationKind::Missing);
+    };
+    if row.get::<_, String>(0) == "f" {
+        return Ok(RelationKind::Foreign);
+    }
+
+    if !multinode(c).await? {
+        return Ok(RelationKind::Table);
+    }
+    let distributed = c
+        .query_opt(
+            "select 1 from timescaledb_information.hypertables h
+             where h.is_distributed
+               and format('%I.%I', h.hypertable_schema, h.hypertable_name)::regclass
+                   = to_regclass($1)",
+            &[&table_name],
+        )
+        .await
+        .with_context(|| format!("looking up hypertable => {table_name}"))?;
+    Ok(match distributed {
+        Some(_) => RelationKind::DistributedHypertable,
+        None => RelationKind::Table,
+    })
+}
+
+/// Whether the server runs a TimescaleDB with multi-node, whose hypertables
+/// view has the `is_distributed` column. Asked once per process, as the
+/// loader works against one database.
+async fn multinode(c: &Client) -> Result<bool> {
+    static MULTINODE: OnceLock<bool> = OnceLock::new();
+    if let Some(multinode) = MULTINODE.get() {
+        return Ok(*multinode);
+    }
+    let multinode: bool = c
+        .query_one(
+            "select exists (select from pg_extension where extname = 'timescaledb')
+                and exists (
+                    select from pg_attribute
+                    where attrelid = to_regclass('timescaledb_information.hypertables')
+                      and attname = 'is_distributed' and not attisdropped
+                )",
+            &[],
+        )
+        .await
+        .context("looking up timescaledb")?
+        .get(0);
+    Ok(*MULTINODE.get_or_init(|| multinode))
+}
+
+#[async_trait]
+impl Sink for PostgresSink<'_> {
+    async fn write(&mut self, batch: Batch) -> Result<u64> {
+        let c = self.client;
+        let kind = relation_kind(c, &batch.table_name).await?;
+        if let (RelationKind::DistributedHypertable, false, Some(symbol)) =
+            (kind, self.workers.is_empty(), batch.column("symbol"))
+        {
+            return self.write_distributed(batch, symbol).await;
+        }
+        if kind.is_remote() {
+            return self.write_remote(batch).await;
+        }
+
+        prepare_table(c, &batch).await?;
+        self.committed.store(0, Ordering::Relaxed);
+        let rows = copy::copy_records_committing(
+            c,
+            &batch.table_name,
+            &batch.columns,
+            batch.records,
+            self.commit_every,
+            &self.committed,
+        )
+        .await?;
+        stamp_comment(c, &batch.table_name, &batch.source, rows)
+            .await
+            .with_context(|| format!("commenting table => {}", batch.table_name))?;
+        Ok(rows)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn verbatim_prefix_dropped_from_short_paths() {
+        assert_eq!(windows_path(r"\\?\C:\data\INFY.csv"), r"C:\data\INFY.csv");
+        assert_eq!(
+            windows_path(r"\\?\UNC\server\share\INFY.csv"),
+            r"\\server\share\INFY.csv"
+        );
+        let volume = r"\\?\Volume{26a21bda-a627-11d7-9931-806e6f6e6963}\INFY.csv";
+        assert_eq!(windows_path(volume), volume);
+    }
+
+    #[test]
+    fn long_paths_gain_verbatim_prefix() {
+        let dir = "d".repeat(MAX_PATH);
+        assert_eq!(
+            windows_path(&format!(r"C:\{dir}\INFY.csv")),
+            format!(r"\\?\C:\{dir}\INFY.csv")
+        );
+        assert_eq!(
+            windows_path(&format!(r"\\server\share\{dir}\INFY.csv")),
+            format!(r"\\?\UNC\server\share\{dir}\INFY.csv")
+        );
+        assert_eq!(
+            windows_path(&format!(r"\\?\C:\{dir}\INFY.csv")),
+            format!(r"\\?\C:\{dir}\INFY.csv")
+        );
+    }
+
+    #[test]
+    fn long_paths_lose_forward_slashes() {
+        let dir = "d".repeat(MAX_PATH);
+        assert_eq!(
+            windows_path(&format!("C:/{dir}/INFY.csv")),
+            format!(r"\\?\C:\{dir}\INFY.csv")
+        );
+        assert_eq!(windows_path("C:/data/INFY.csv"), "C:/data/INFY.csv");
+    }
+
+    #[test]
+    fn unix_paths_unchanged() {
+        let long = format!("/data/{}/INFY.csv", "d".repeat(MAX_PATH));
+        assert_eq!(windows_path(&long), long);
+        assert_eq!(server_path_literal("/data/INFY.csv"), "'/data/INFY.csv'");
+    }
+
+    #[test]
+    fn literals_escape_quotes_and_backslashes() {
+        assert_eq!(
+            server_path_literal(r"C:\data\O'Neil.csv"),
+            r"E'C:\\data\\O''Neil.csv'"
+        );
+        assert_eq!(
+            server_path_literal(r"\\?\UNC\server\share\INFY.csv"),
+            r"E'\\\\server\\share\\INFY.csv'"
+        );
+        assert_eq!(literal("it's"), "'it''s'");
+    }
+}
@@ -0,0 +1,94 @@
+use anyhow::{bail, Context, Result};
+use std::env;
+use std::ffi::OsString;
+use std::fs;
+use std::path::PathBuf;
+
+use crate::service;
+
+/// Profiles file: `$PG_NIFTY_DUMP_CONFIG`, or `profiles.conf` in the
+/// user's configuration directory.
+pub fn path() -> Result<PathBuf> {
+    if let Some(path) = env::var_os("PG_NIFTY_DUMP_CONFIG") {
+        return Ok(PathBuf::from(path));
+    }
+    let dir = match env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) => PathBuf::from(dir),
+        None => PathBuf::from(env::var_os("HOME").context("HOME is not set")?).join(".config"),
+    };
+    Ok(dir.join("pg_nifty_dump").join("profiles.conf"))
+}
+
+/// The command line with the options of the profile named by `--profile`
+/// or `$PG_NIFTY_DUMP_PROFILE` inserted after the program name, so options
+/// given on the command line override them.
+///
+/// A profile is a `[name]` section of `option = value` lines, named like
+/// the long options; `true` stands for a flag without a value.
+pub fn expand(args: Vec<OsString>) -> Result<Vec<OsString>> {
+    let mut name = env::var("PG_NIFTY_DUMP_PROFILE").ok();
+    let mut iter = args.iter().skip(1);
+    while let Some(arg) = iter.next() {
+        let arg = arg.to_string_lossy();
+        if arg == "--" {
+            break;
+        } else if arg == "--profile" {
+            name = iter.next().map(|n| n.to_string_lossy().into_owned());
+        } else if let Some(value) = arg.strip_prefix("--profile=") {
+            name = Some(value.to_string());
+        }
+    }
+    let Some(name) = name else {
+        return Ok(args);
+    };
+
+    let path = path()?;
+    let contents = fs::read_to_string(&path)
+        .with_context(|| format!("reading profiles: {}", path.display()))?;
+    let Some(options) = service::section(&contents, &name) else {
+        bail!("profile not found in {}: {name}", path.display());
+    };
+    let mut expanded = args[..1].to_vec();
+    for (option, value) in options {
+        match value {
+            "true" => expanded.push(format!("--{option}").into()),
+            "false" => {}
+            value => expanded.push(format!("--{option}={value}").into()),
+        }
+    }
+    expanded.extend_from_slice(&args[1..]);
+    Ok(expanded)
+}
+
+/// Writes the options of profile `name`, replacing its previous section.
+pub fn save(name: &str, options: &[(&str, String)]) -> Result<PathBuf> {
+    let path = path()?;
+    let existing = fs::read_to_string(&path).unwrap_or_default();
+
+    let mut contents = String::new();
+    let mut skipping = false;
+    for line in existing.lines() {
+        let trimmed = line.trim();
+        if trimmed.starts_with('[') {
+            skipping = trimmed == format!("[{name}]");
+        }
+        if !skipping {
+            contents.push_str(line);
+            contents.push('\n');
+        }
+    }
+    if !contents.is_empty() && !contents.ends_with("\n\n") {
+        contents.push('\n');
+    }
+    contents.push_str(&format!("[{name}]\n"));
+    for (option, value) in options {
+        contents.push_str(&format!("{option} = {value}\n"));
+    }
+
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)
+            .with_context(|| format!("creating directory: {}", dir.display()))?;
+    }
+    fs::write(&path, contents).with_context(|| format!("writing profiles: {}", path.display()))?;
+    Ok(path)
+}
@@ -0,0 +1,109 @@
+use clap::ValueEnum;
+use serde_json::json;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::report::Status;
+use pg_nifty_dump::pipeline::Loaded;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Format {
+    /// Human-oriented log lines.
+    Text,
+    /// One JSON event per line.
+    Jsonl,
+}
+
+/// Receives the loader's state changes. The default methods let reporters
+/// pick only the events they care about.
+pub trait Reporter {
+    /// A human-oriented log line.
+    fn log(&mut self, line: &str);
+
+    fn file_started(&mut self, _file: &str) {}
+
+    fn rows_committed(&mut self, _table: &str, _rows: u64) {}
+
+    /// A batch landed in its table.
+    fn batch_loaded(&mut self, _batch: &Loaded) {}
+
+    fn file_failed(&mut self, file: &str, error: &str) {
+        self.log(&format!("ERROR: {file}: {error}"));
+    }
+
+    /// A file was handled, whatever became of it.
+    fn file_finished(&mut self, _file: &str, _status: Status) {}
+
+    /// The run ended, with the error that stopped it, if any.
+    fn finished(&mut self, _error: Option<&str>) {}
+}
+
+/// Plain log lines on stdout.
+pub struct Plain;
+
+impl Reporter for Plain {
+    fn log(&mut self, line: &str) {
+        println!("{line}");
+    }
+}
+
+/// One JSON object per state change on stdout, for wrapper scripts. Human
+/// log lines go to stderr so stdout stays machine-readable.
+#[derive(Default)]
+pub struct Jsonl {
+    files: u64,
+    rows: u64,
+    failed: u64,
+}
+
+impl Jsonl {
+    fn emit(&self, mut event: serde_json::Value) {
+        let ts = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_millis() as u64);
+        event["ts"] = ts.into();
+        println!("{event}");
+    }
+}
+
+impl Reporter for Jsonl {
+    fn log(&mut self, line: &str) {
+        eprintln!("{line}");
+    }
+
+    fn file_started(&mut self, file: &str) {
+        self.files += 1;
+        self.emit(json!({ "event": "file_started", "file": file }));
+    }
+
+    fn rows_committed(&mut self, table: &str, rows: u64) {
+        self.rows += rows;
+        self.emit(json!({ "event": "rows_committed", "table": table, "rows": rows }));
+    }
+
+    fn batch_loaded(&mut self, batch: &Loaded) {
+        self.emit(json!({
+            "event": "batch_loaded",
+            "table": batch.table_name,
+            "symbols": batch.symbols,
+            "first_date": batch.first_date,
+            "last_date": batch.last_date,
+            "rows": batch.rows,
+        }));
+    }
+
+    fn file_failed(&mut self, file: &str, error: &str) {
+        self.failed += 1;
+        self.emit(json!({ "event": "file_failed", "file": file, "error": error }));
+    }
+
+    fn finished(&mut self, error: Option<&str>) {
+        self.emit(json!({
+            "event": "run_finished",
+            "status": if error.is_some() { "failed" } else { "ok" },
+            "error": error,
+            "files": self.files,
+            "rows": self.rows,
+            "failed": self.failed,
+        }));
+    }
+}
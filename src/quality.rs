@@ -0,0 +1,159 @@
+use serde::{Deserialize, Serialize};
+
+/// Days between consecutive rows beyond which the series counts as having
+/// a gap; a long weekend plus a market holiday stays below it.
+const GAP_DAYS: i64 = 5;
+/// Absolute close-to-close return treated as an outlier.
+const OUTLIER_RETURN: f64 = 0.2;
+
+/// Data quality of one file, from 0 (unusable) to 100 (clean).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Quality {
+    pub score: f64,
+    pub rows: u64,
+    /// Percentage of rows with a date and numeric values throughout.
+    pub valid_pct: f64,
+    /// Runs of more than `GAP_DAYS` days without rows.
+    pub gaps: u64,
+    /// Rows whose close moved by more than `OUTLIER_RETURN` in a day.
+    pub outliers: u64,
+    /// Percentage of rows whose prices and bounded oscillators agree
+    /// (low <= open/close <= high, RSI/MFI/stochastics within 0..=100).
+    pub consistency_pct: f64,
+}
+
+impl Quality {
+    pub fn compute(columns: &[String], records: &[csv::ByteRecord]) -> Quality {
+        let col = |name: &str| columns.iter().position(|c| c.eq_ignore_ascii_case(name));
+        let num = |r: &csv::ByteRecord, i: Option<usize>| -> Option<f64> {
+            let field = std::str::from_utf8(r.get(i?)?).ok()?.trim();
+            field.parse::<f64>().ok().filter(|v| v.is_finite())
+        };
+        let check = RowCheck::new(columns);
+        let (open, high, low, close) = (col("open"), col("high"), col("low"), col("close"));
+        let bounded: Vec<usize> = [
+            "RSI14", "RSI8", "MFI", "slowk", "slowd", "fastk", "fastd", "ULTOSC",
+        ]
+        .iter()
+        .filter_map(|c| col(c))
+        .collect();
+
+        let mut valid = 0;
+        let mut consistent = 0;
+        let mut gaps = 0;
+        let mut outliers = 0;
+        let mut prev_day: Option<i64> = None;
+        let mut prev_close: Option<f64> = None;
+        for r in records {
+            let day = check.date.and_then(|i| r.get(i)).and_then(days_from_date);
+            if check.invalid_column(r).is_none() {
+                valid += 1;
+            }
+
+            let prices_ok = match (num(r, open), num(r, high), num(r, low), num(r, close)) {
+                (Some(o), Some(h), Some(l), Some(c)) => l <= h && l <= o.min(c) && h >= o.max(c),
+                _ => false,
+            };
+            let bounded_ok = bounded
+                .iter()
+                .all(|&i| num(r, Some(i)).is_none_or(|v| (0.0..=100.0).contains(&v)));
+            if prices_ok && bounded_ok {
+                consistent += 1;
+            }
+
+            if let (Some(day), Some(prev)) = (day, prev_day) {
+                if (day - prev).abs() > GAP_DAYS {
+                    gaps += 1;
+                }
+            }
+            prev_day = day.or(prev_day);
+
+            if let (Some(c), Some(p)) = (num(r, close), prev_close) {
+                if p != 0.0 && ((c - p) / p).abs() > OUTLIER_RETURN {
+                    outliers += 1;
+                }
+            }
+            prev_close = num(r, close).or(prev_close);
+        }
+
+        let rows = records.len() as u64;
+        let pct = |n: u64| {
+            if rows == 0 {
+                0.0
+            } else {
+                100.0 * n as f64 / rows as f64
+            }
+        };
+        let valid_pct = pct(valid);
+        let consistency_pct = pct(consistent);
+        let gap_pct = pct(gaps).min(100.0);
+        let outlier_pct = pct(outliers).min(100.0);
+        let score = 0.4 * valid_pct
+            + 0.2 * consistency_pct
+            + 0.2 * (100.0 - gap_pct)
+            + 0.2 * (100.0 - outlier_pct);
+
+        Quality {
+            score: (score * 10.0).round() / 10.0,
+            rows,
+            valid_pct,
+            gaps,
+            outliers,
+            consistency_pct,
+        }
+    }
+}
+
+/// Row validation: a parsable date, and numbers (or nothing, or NaN) in every
+/// other column but the symbol.
+pub struct RowCheck {
+    date: Option<usize>,
+    numeric: Vec<usize>,
+}
+
+impl RowCheck {
+    pub fn new(columns: &[String]) -> Self {
+        let date = columns.iter().position(|c| c.eq_ignore_ascii_case("date"));
+        let numeric = (0..columns.len())
+            .filter(|i| Some(*i) != date && columns[*i] != "symbol")
+            .collect();
+        RowCheck { date, numeric }
+    }
+
+    /// Position of the first column failing validation.
+    pub fn invalid_column(&self, r: &csv::ByteRecord) -> Option<usize> {
+        let date = self.date?;
+        if r.get(date).and_then(days_from_date).is_none() {
+            return Some(date);
+        }
+        self.numeric.iter().copied().find(|&i| {
+            let f = r.get(i).unwrap_or_default().trim_ascii();
+            !(f.is_empty()
+                || f.eq_ignore_ascii_case(b"nan")
+                || std::str::from_utf8(f)
+                    .ok()
+                    .and_then(|s| s.parse::<f64>().ok())
+                    .is_some_and(|v| v.is_finite()))
+        })
+    }
+}
+
+/// Days since 1970-01-01 of a `YYYY-MM-DD` date or timestamp.
+pub fn days_from_date(field: &[u8]) -> Option<i64> {
+    let s = std::str::from_utf8(field).ok()?.trim();
+    let (y, m, d) = (
+        s.get(0..4)?.parse::<i64>().ok()?,
+        s.get(5..7)?.parse::<i64>().ok()?,
+        s.get(8..10)?.parse::<i64>().ok()?,
+    );
+    if !(1..=12).contains(&m) || !(1..=31).contains(&d) {
+        return None;
+    }
+    // Civil-from-days inverse, see http://howardhinnant.github.io/date_algorithms.html
+    let y = if m <= 2 { y - 1 } else { y };
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let doy = (153 * (if m > 2 { m - 3 } else { m + 9 }) + 2) / 5 + d - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    Some(era * 146097 + doe - 719468)
+}
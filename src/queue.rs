@@ -0,0 +1,173 @@
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+use tokio_postgres::Client;
+
+use crate::report::Status;
+use pg_nifty_dump::postgres;
+
+/// First key of the session advisory locks held on claimed files, the second
+/// being the hash of the file name.
+const CLAIM_LOCK: i32 = 0x6e69_6674;
+
+/// Files of a directory shared by several hosts, kept in a `load_queue`
+/// table, so the hosts drain the queue together without a coordinator.
+///
+/// Each host claims one file at a time and holds a session advisory lock on
+/// it until the file is finished. A claim whose lock is free belongs to a
+/// host whose session ended, however long its load had been running, and is
+/// taken over.
+pub struct Queue {
+    /// Identifies this process in `claimed_by`.
+    worker: String,
+}
+
+impl Queue {
+    pub async fn open(c: &Client) -> Result<Self> {
+        postgres::execute_ddl(
+            c,
+            "create table if not exists load_queue (
+                file text primary key,
+                size bigint,
+                modified timestamptz,
+                status text not null default 'pending',
+                claimed_by text,
+                claimed_at timestamptz,
+                finished_at timestamptz,
+                error text
+            )",
+        )
+        .await
+        .context("creating table => load_queue")?;
+        let host = fs::read_to_string("/etc/hostname")
+            .ok()
+            .or_else(|| std::env::var("HOSTNAME").ok())
+            .unwrap_or_else(|| "localhost".to_string());
+        Ok(Queue {
+            worker: format!("{}:{}", host.trim(), std::process::id()),
+        })
+    }
+
+    /// Adds the files not queued yet, and queues again those whose size or
+    /// modification time changed since they were queued. Files are keyed by
+    /// name, so hosts may mount the directory at different paths.
+    pub async fn enqueue(&self, c: &Client, files: &[(PathBuf, SystemTime, u64)]) -> Result<()> {
+        let mut names = Vec::new();
+        let mut sizes = Vec::new();
+        let mut modified = Vec::new();
+        for (path, mtime, size) in files {
+            let Some(name) = path.file_name().and_then(|name| name.to_str()) else {
+                continue;
+            };
+            names.push(name.to_string());
+            sizes.push(*size as i64);
+            modified.push(*mtime);
+        }
+        c.execute(
+            "insert into load_queue (file, size, modified)
+             select * from unnest($1::text[], $2::bigint[], $3::timestamptz[])
+             on conflict (file) do update
+             set size = excluded.size, modified = excluded.modified, status = 'pending',
+                 claimed_by = null, claimed_at = null, finished_at = null, error = null
+             where load_queue.size is distinct from excluded.size
+                or load_queue.modified is distinct from excluded.modified",
+            &[&names, &sizes, &modified],
+        )
+        .await
+        .context("queueing files")?;
+        Ok(())
+    }
+
+    /// Claims the next pending file of `dir`, with its modification time and
+    /// size, or `None` once the queue is drained.
+    pub async fn claim(&self, c: &Client, dir: &str) -> Result<Option<(PathBuf, SystemTime, u64)>> {
+        loop {
+            let Some(file) = self.claim_next(c).await? else {
+                return Ok(None);
+            };
+            let path = Path::new(dir).join(file);
+            match fs::metadata(&path) {
+                Ok(meta) => return Ok(Some((path, meta.modified()?, meta.len()))),
+                // Gone since it was queued.
+                Err(e) => {
+                    self.finish(c, &path, Status::Failed, Some(&e.to_string()))
+                        .await?
+                }
+            }
+        }
+    }
+
+    /// Locks and claims the first file that is pending, or claimed by a host
+    /// no longer holding its lock.
+    async fn claim_next(&self, c: &Client) -> Result<Option<String>> {
+        let candidates = c
+            .query(
+                "select file from load_queue where status in ('pending', 'claimed') order by file",
+                &[],
+            )
+            .await
+            .context("reading load_queue")?;
+        for row in candidates {
+            let file: String = row.get(0);
+            let locked: bool = c
+                .query_one(
+                    "select pg_try_advisory_lock($1, hashtext($2))",
+                    &[&CLAIM_LOCK, &file],
+                )
+                .await
+                .context("locking queued file")?
+                .get(0);
+            if !locked {
+                continue;
+            }
+            // Another host may have finished it since it was read.
+            let claimed = c
+                .execute(
+                    "update load_queue set status = 'claimed', claimed_by = $2, claimed_at = now()
+                     where file = $1 and status in ('pending', 'claimed')",
+                    &[&file, &self.worker],
+                )
+                .await
+                .context("claiming file")?;
+            if claimed == 1 {
+                return Ok(Some(file));
+            }
+            unlock(c, &file).await?;
+        }
+        Ok(None)
+    }
+
+    /// Records the outcome of a claimed file and releases its lock.
+    pub async fn finish(
+        &self,
+        c: &Client,
+        path: &Path,
+        status: Status,
+        error: Option<&str>,
+    ) -> Result<()> {
+        let file = path.file_name().unwrap().to_string_lossy();
+        let status = match status {
+            Status::Failed | Status::Invalid => "failed",
+            _ => "done",
+        };
+        c.execute(
+            "update load_queue set status = $2, finished_at = now(), error = $3
+             where file = $1 and claimed_by = $4",
+            &[&file.as_ref(), &status, &error, &self.worker],
+        )
+        .await
+        .context("updating load_queue")?;
+        unlock(c, &file).await
+    }
+}
+
+async fn unlock(c: &Client, file: &str) -> Result<()> {
+    c.execute(
+        "select pg_advisory_unlock($1, hashtext($2))",
+        &[&CLAIM_LOCK, &file],
+    )
+    .await
+    .context("unlocking queued file")?;
+    Ok(())
+}
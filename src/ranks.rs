@@ -0,0 +1,81 @@
+use anyhow::{bail, Context, Result};
+use tokio_postgres::Client;
+
+use crate::schema;
+use pg_nifty_dump::pipeline::Script;
+
+/// Rebuilds `<table>_ranks` from the shared table: per date, each
+/// indicator's rank across symbols (1 for the highest value) and its
+/// percentile among the symbols with a value (0 for the lowest, 1 for the
+/// highest, null without a value). Returns the number of rows written.
+pub async fn compute(
+    client: &Client,
+    table: &str,
+    indicators: &[String],
+    script: Option<&Script>,
+) -> Result<u64> {
+    for indicator in indicators {
+        if !schema::is_column(indicator) {
+            bail!("unknown column in --ranks: {indicator}");
+        }
+    }
+    let target = format!("{table}_ranks");
+    let columns: Vec<String> = indicators
+        .iter()
+        .map(|c| {
+            let c = c.to_lowercase();
+            format!(
+                "rank() over (partition by date order by {c} desc nulls last) as {c}_rank, \
+                 case when {c} is null then null \
+                 else percent_rank() over (partition by date, {c} is null order by {c}) end as {c}_pct"
+            )
+        })
+        .collect();
+
+    let statements = [
+        format!("drop table if exists {target}"),
+        format!(
+            "create table {target} as select symbol, date, {} from {table}",
+            columns.join(", ")
+        ),
+        format!("alter table {target} add primary key (symbol, date)"),
+    ];
+
+    // Readers see either the old or the new ranks, never none.
+    client.batch_execute("begin").await?;
+    match rebuild(client, &target, &statements).await {
+        Ok(rows) => {
+            client.batch_execute("commit").await?;
+            if let Some(script) = script {
+                script.statement("begin")?;
+                for statement in &statements {
+                    script.statement(statement)?;
+                }
+                script.statement("commit")?;
+            }
+            Ok(rows)
+        }
+        Err(e) => {
+            let _ = client.batch_execute("rollback").await;
+            Err(e)
+        }
+    }
+}
+
+/// Runs the drop, create and index statements, returning the rows created.
+async fn rebuild(client: &Client, target: &str, statements: &[String; 3]) -> Result<u64> {
+    let [drop, create, index] = statements;
+    client
+        .execute(drop, &[])
+        .await
+        .with_context(|| format!("dropping table => {target}"))?;
+    let rows = client
+        .execute(create, &[])
+        .await
+        .with_context(|| format!("creating table => {target}"))?;
+    client
+        .execute(index, &[])
+        .await
+        .with_context(|| format!("indexing table => {target}"))?;
+    Ok(rows)
+}
@@ -0,0 +1,76 @@
+use anyhow::{bail, Context, Result};
+use bytes::Bytes;
+use futures::{pin_mut, SinkExt};
+use std::fs;
+use tokio_postgres::Client;
+
+/// Lines of COPY data sent per message.
+const CHUNK_LINES: usize = 10_000;
+
+/// Runs a script recorded with --record: statements end with `;` at the end
+/// of a line outside a `$$` quoted body, and `copy ... from stdin` is
+/// followed by its rows up to `\.`.
+/// Returns the number of statements executed.
+pub async fn run(c: &Client, path: &str) -> Result<usize> {
+    let script = fs::read_to_string(path).with_context(|| format!("reading script: {path}"))?;
+    let mut lines = script.lines().enumerate();
+    let mut statement = String::new();
+    let mut executed = 0;
+
+    while let Some((n, line)) = lines.next() {
+        if statement.is_empty() && (line.trim().is_empty() || line.starts_with("--")) {
+            continue;
+        }
+        statement.push_str(line);
+        statement.push('\n');
+        if !line.trim_end().ends_with(';') || statement.matches("$$").count() % 2 == 1 {
+            continue;
+        }
+        let sql = std::mem::take(&mut statement);
+        let sql = sql.trim().trim_end_matches(';');
+
+        if !sql.to_lowercase().contains("from stdin") {
+            c.batch_execute(sql)
+                .await
+                .with_context(|| format!("{path}:{}: executing statement", n + 1))?;
+            executed += 1;
+            continue;
+        }
+
+        let sink = c
+            .copy_in(sql)
+            .await
+            .with_context(|| format!("{path}:{}: starting copy", n + 1))?;
+        pin_mut!(sink);
+        let mut chunk = String::new();
+        let mut buffered = 0;
+        let mut terminated = false;
+        for (_, row) in lines.by_ref() {
+            if row == "\\." {
+                terminated = true;
+                break;
+            }
+            chunk.push_str(row);
+            chunk.push('\n');
+            buffered += 1;
+            if buffered == CHUNK_LINES {
+                sink.send(Bytes::from(std::mem::take(&mut chunk))).await?;
+                buffered = 0;
+            }
+        }
+        if !terminated {
+            bail!("{path}:{}: copy data not terminated by \\.", n + 1);
+        }
+        if buffered > 0 {
+            sink.send(Bytes::from(chunk)).await?;
+        }
+        sink.finish()
+            .await
+            .with_context(|| format!("{path}:{}: finishing copy", n + 1))?;
+        executed += 1;
+    }
+    if !statement.trim().is_empty() {
+        bail!("{path}: script ends inside a statement");
+    }
+    Ok(executed)
+}
@@ -0,0 +1,124 @@
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+
+use crate::quality::Quality;
+use pg_nifty_dump::copy;
+use pg_nifty_dump::postgres::DataNodeUsage;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Status {
+    Loaded,
+    /// Identical to the manifest's copy, whose tables still exist.
+    Unchanged,
+    Skipped,
+    Invalid,
+    Failed,
+}
+
+/// What happened to one input file.
+#[derive(Debug, Serialize)]
+pub struct FileReport {
+    pub file: String,
+    pub status: Status,
+    pub tables: Vec<String>,
+    pub rows: u64,
+    pub quality: Option<Quality>,
+    pub error: Option<String>,
+}
+
+impl FileReport {
+    pub fn new(file: &str, status: Status) -> Self {
+        FileReport {
+            file: file.to_string(),
+            status,
+            tables: Vec::new(),
+            rows: 0,
+            quality: None,
+            error: None,
+        }
+    }
+}
+
+/// Summary of a load run, written as JSON by --report.
+#[derive(Debug, Default, Serialize)]
+pub struct RunReport {
+    pub version: &'static str,
+    pub rows: u64,
+    pub files: Vec<FileReport>,
+    /// Data node usage of the distributed hypertables loaded, by table.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub data_nodes: BTreeMap<String, Vec<DataNodeUsage>>,
+    pub resources: Option<ResourceUsage>,
+}
+
+/// The loader's own resource usage over the run.
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+pub struct ResourceUsage {
+    pub peak_memory_bytes: u64,
+    pub cpu_user_seconds: f64,
+    pub cpu_system_seconds: f64,
+    /// Row data sent to the database; files the server reads itself add
+    /// nothing.
+    pub bytes_sent: u64,
+}
+
+impl ResourceUsage {
+    /// Usage of this process so far.
+    pub fn measure() -> Self {
+        #[allow(unused_mut)]
+        let mut usage = ResourceUsage {
+            bytes_sent: copy::bytes_sent(),
+            ..Default::default()
+        };
+        #[cfg(unix)]
+        {
+            // SAFETY: getrusage only writes into the struct it is given.
+            let mut ru: libc::rusage = unsafe { std::mem::zeroed() };
+            if unsafe { libc::getrusage(libc::RUSAGE_SELF, &mut ru) } == 0 {
+                let seconds = |t: libc::timeval| t.tv_sec as f64 + t.tv_usec as f64 / 1e6;
+                usage.cpu_user_seconds = seconds(ru.ru_utime);
+                usage.cpu_system_seconds = seconds(ru.ru_stime);
+                // Kilobytes, except on macOS.
+                let rss = ru.ru_maxrss as u64;
+                usage.peak_memory_bytes = if cfg!(target_os = "macos") {
+                    rss
+                } else {
+                    rss * 1024
+                };
+            }
+        }
+        usage
+    }
+}
+
+impl fmt::Display for ResourceUsage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mib = |bytes: u64| bytes as f64 / (1024.0 * 1024.0);
+        write!(
+            f,
+            "peak memory {:.1} MiB, CPU {:.2}s user + {:.2}s system, {:.1} MiB of rows sent",
+            mib(self.peak_memory_bytes),
+            self.cpu_user_seconds,
+            self.cpu_system_seconds,
+            mib(self.bytes_sent)
+        )
+    }
+}
+
+impl RunReport {
+    pub fn add(&mut self, file: FileReport) {
+        self.rows += file.rows;
+        self.files.push(file);
+    }
+
+    pub fn write(&mut self, path: &str) -> Result<()> {
+        self.version = env!("CARGO_PKG_VERSION");
+        fs::write(path, serde_json::to_vec_pretty(self)?)
+            .with_context(|| format!("writing report: {path}"))?;
+        Ok(())
+    }
+}
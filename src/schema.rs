@@ -0,0 +1,440 @@
+use clap::ValueEnum;
+use sha2::{Digest, Sha256};
+
+pub static VERIFY_CSV_HEADER: &str = "date,close,high,low,open,volume,sma5,sma10,sma15,sma20,ema5,ema10,ema15,ema20,upperband,middleband,lowerband,HT_TRENDLINE,KAMA10,KAMA20,KAMA30,SAR,TRIMA5,TRIMA10,TRIMA20,ADX5,ADX10,ADX20,APO,CCI5,CCI10,CCI15,macd510,macd520,macd1020,macd1520,macd1226,MFI,MOM10,MOM15,MOM20,ROC5,ROC10,ROC20,PPO,RSI14,RSI8,slowk,slowd,fastk,fastd,fastksr,fastdsr,ULTOSC,WILLR,ATR,Trange,TYPPRICE,HT_DCPERIOD,BETA";
+
+/// Split- and dividend-adjusted prices a file may carry besides the
+/// canonical columns, each named after its raw column with an `adj_` prefix.
+pub static ADJUSTED_COLUMNS: &[&str] = &["adj_close", "adj_open", "adj_high", "adj_low"];
+
+/// Column names of the canonical header, in file order.
+pub fn columns() -> Vec<&'static str> {
+    VERIFY_CSV_HEADER.split(',').collect()
+}
+
+/// Canonical columns holding derived indicators, i.e. everything but the
+/// date, OHLC prices and volume.
+pub fn indicator_columns() -> Vec<&'static str> {
+    columns()
+        .into_iter()
+        .filter(|c| !matches!(*c, "date" | "close" | "high" | "low" | "open" | "volume"))
+        .collect()
+}
+
+/// Canonical columns denominated in the instrument's currency: prices and
+/// the indicators measured in price units (averages, bands, ranges,
+/// momentum and MACD differences). Ratios and oscillators are unit-free.
+pub fn price_columns() -> Vec<&'static str> {
+    columns()
+        .into_iter()
+        .filter(|c| {
+            let c = c.to_lowercase();
+            [
+                "close", "high", "low", "open", "typprice", "trange", "atr", "sar", "apo",
+            ]
+            .contains(&c.as_str())
+                || ["sma", "ema", "kama", "trima", "mom", "macd", "ht_trendline"]
+                    .iter()
+                    .any(|p| c.starts_with(p))
+                || c.ends_with("band")
+        })
+        .collect()
+}
+
+/// Columns the loader may add beyond the canonical ones, and whether each is
+/// in price units: adjusted prices, ATR bands and the VWAP are, the
+/// adjustment factor, `volatility*` returns and bookkeeping columns are not.
+pub fn extra_column_is_price(name: &str) -> Option<bool> {
+    let name = name.to_lowercase();
+    if ADJUSTED_COLUMNS.contains(&name.as_str())
+        || ["atr_upper", "atr_lower", "vwap"].contains(&name.as_str())
+    {
+        return Some(true);
+    }
+    let unit_free = [
+        "adjustment_factor",
+        "series_type",
+        "revised",
+        "source_modified",
+    ]
+    .contains(&name.as_str())
+        || name
+            .strip_prefix("volatility")
+            .is_some_and(|n| !n.is_empty() && n.bytes().all(|b| b.is_ascii_digit()));
+    unit_free.then_some(false)
+}
+
+/// Whether `name` is one of the canonical columns. Postgres folds the
+/// unquoted identifiers to lower case, so the match is case-insensitive.
+pub fn is_column(name: &str) -> bool {
+    columns().iter().any(|c| c.eq_ignore_ascii_case(name))
+}
+
+/// Longest identifier Postgres keeps; longer names are silently truncated.
+const MAX_IDENTIFIER_BYTES: usize = 63;
+
+/// Keywords Postgres does not accept as table names, lowercase.
+static RESERVED_WORDS: &[&str] = &[
+    "all",
+    "analyse",
+    "analyze",
+    "and",
+    "any",
+    "array",
+    "as",
+    "asc",
+    "asymmetric",
+    "authorization",
+    "binary",
+    "both",
+    "case",
+    "cast",
+    "check",
+    "collate",
+    "collation",
+    "column",
+    "concurrently",
+    "constraint",
+    "create",
+    "cross",
+    "current_catalog",
+    "current_date",
+    "current_role",
+    "current_schema",
+    "current_time",
+    "current_timestamp",
+    "current_user",
+    "default",
+    "deferrable",
+    "desc",
+    "distinct",
+    "do",
+    "else",
+    "end",
+    "except",
+    "false",
+    "fetch",
+    "for",
+    "foreign",
+    "freeze",
+    "from",
+    "full",
+    "grant",
+    "group",
+    "having",
+    "ilike",
+    "in",
+    "initially",
+    "inner",
+    "intersect",
+    "into",
+    "is",
+    "isnull",
+    "join",
+    "lateral",
+    "leading",
+    "left",
+    "like",
+    "limit",
+    "localtime",
+    "localtimestamp",
+    "natural",
+    "not",
+    "notnull",
+    "null",
+    "offset",
+    "on",
+    "only",
+    "or",
+    "order",
+    "outer",
+    "overlaps",
+    "placing",
+    "primary",
+    "references",
+    "returning",
+    "right",
+    "select",
+    "session_user",
+    "similar",
+    "some",
+    "symmetric",
+    "system_user",
+    "table",
+    "tablesample",
+    "then",
+    "to",
+    "trailing",
+    "true",
+    "union",
+    "unique",
+    "user",
+    "using",
+    "variadic",
+    "verbose",
+    "when",
+    "where",
+    "window",
+    "with",
+];
+
+/// What to do with names that are reserved words, e.g. `USER.csv`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum ReservedWords {
+    /// Append an underscore (`user_`).
+    #[default]
+    Suffix,
+    /// Keep the name and quote it (`"user"`).
+    Quote,
+}
+
+/// How file and symbol names become table names.
+#[derive(Debug, Clone, Default)]
+pub struct IdentifierPolicy {
+    /// Keep the original case, quoting names that are not all lowercase.
+    pub keep_case: bool,
+    pub reserved_words: ReservedWords,
+    /// Pattern of per-symbol table names, `{symbol}` standing for the file
+    /// or symbol name, e.g. `nse_{symbol}_daily`.
+    pub table_template: Option<String>,
+}
+
+/// Checks that a table template names its symbol exactly once.
+pub fn check_template(template: &str) -> anyhow::Result<()> {
+    if template.matches("{symbol}").count() != 1 {
+        anyhow::bail!("table template must contain {{symbol}} once: {template}");
+    }
+    Ok(())
+}
+
+impl IdentifierPolicy {
+    /// Table name of the file or symbol `symbol`, through the table template.
+    pub fn table(&self, symbol: &str) -> String {
+        match &self.table_template {
+            Some(template) => self.apply(&template.replacen("{symbol}", symbol, 1)),
+            None => self.apply(symbol),
+        }
+    }
+
+    /// The symbol part of `table`, a name as kept in the catalog, when the
+    /// table template makes `table` of it.
+    pub fn symbol_of(&self, table: &str) -> Option<String> {
+        let template = self.table_template.as_deref().unwrap_or("{symbol}");
+        let (prefix, suffix) = template.split_once("{symbol}")?;
+        let fold = |s: &str| -> String {
+            s.chars()
+                .map(|c| {
+                    let c = transliterate(c).unwrap_or('_');
+                    if self.keep_case {
+                        c
+                    } else {
+                        c.to_ascii_lowercase()
+                    }
+                })
+                .collect()
+        };
+        let symbol = table
+            .strip_prefix(fold(prefix).as_str())?
+            .strip_suffix(fold(suffix).as_str())?;
+        // Names cut to fit, or collapsed around the symbol, do not map back.
+        (!symbol.is_empty() && self.table(symbol).trim_matches('"') == table)
+            .then(|| symbol.to_string())
+    }
+
+    /// Table name for `name`: letters are transliterated to ASCII where
+    /// possible, anything else becomes `_`, runs of `_` collapse, and names
+    /// over 63 bytes are cut with a hash of the full name appended, so
+    /// distinct long names stay distinct.
+    pub fn apply(&self, name: &str) -> String {
+        let mut s = String::with_capacity(name.len());
+        for c in name.chars() {
+            let c = transliterate(c).unwrap_or('_');
+            let c = if self.keep_case {
+                c
+            } else {
+                c.to_ascii_lowercase()
+            };
+            if !(c == '_' && s.ends_with('_')) {
+                s.push(c);
+            }
+        }
+        let trimmed = s.trim_matches('_');
+        let mut s = if trimmed.is_empty() {
+            "_".to_string()
+        } else {
+            trimmed.to_string()
+        };
+        if s.starts_with(|c: char| c.is_ascii_digit()) {
+            s.insert(0, '_');
+        }
+
+        let reserved = RESERVED_WORDS.contains(&s.to_lowercase().as_str());
+        if reserved && self.reserved_words == ReservedWords::Suffix {
+            s.push('_');
+        }
+        if s.len() > MAX_IDENTIFIER_BYTES {
+            let hash = format!("{:x}", Sha256::digest(name.as_bytes()));
+            s.truncate(MAX_IDENTIFIER_BYTES - 9);
+            s = format!("{s}_{}", &hash[..8]);
+        }
+
+        let needs_quotes = (reserved && self.reserved_words == ReservedWords::Quote)
+            || s.chars().any(|c| c.is_ascii_uppercase());
+        if needs_quotes {
+            format!("\"{s}\"")
+        } else {
+            s
+        }
+    }
+}
+
+/// ASCII stand-in for `c`: itself for ASCII letters and digits, the base
+/// letter for common accented Latin letters, and `None` otherwise.
+fn transliterate(c: char) -> Option<char> {
+    if c.is_ascii_alphanumeric() {
+        return Some(c);
+    }
+    let base = match c {
+        'à'..='å' => 'a',
+        'À'..='Å' => 'A',
+        'ç' => 'c',
+        'Ç' => 'C',
+        'è'..='ë' => 'e',
+        'È'..='Ë' => 'E',
+        'ì'..='ï' => 'i',
+        'Ì'..='Ï' => 'I',
+        'ñ' => 'n',
+        'Ñ' => 'N',
+        'ò'..='ö' | 'ø' => 'o',
+        'Ò'..='Ö' | 'Ø' => 'O',
+        'ù'..='ü' => 'u',
+        'Ù'..='Ü' => 'U',
+        'ý' | 'ÿ' => 'y',
+        'Ý' => 'Y',
+        _ => return None,
+    };
+    Some(base)
+}
+
+/// The name of a table as the catalog keeps it, without the quotes a
+/// mixed-case or reserved name needs in SQL.
+pub fn unquoted(table: &str) -> String {
+    match table.strip_prefix('"').and_then(|t| t.strip_suffix('"')) {
+        Some(name) => name.replace("\"\"", "\""),
+        None => table.to_string(),
+    }
+}
+
+/// Table name for `s` under the default identifier policy.
+pub fn sanitise(s: String) -> String {
+    IdentifierPolicy::default().apply(&s)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy(template: &str, keep_case: bool) -> IdentifierPolicy {
+        IdentifierPolicy {
+            keep_case,
+            reserved_words: ReservedWords::Suffix,
+            table_template: Some(template.to_string()),
+        }
+    }
+
+    #[test]
+    fn table_applies_the_template() {
+        let policy = policy("nse_{symbol}_daily", false);
+        assert_eq!(policy.table("INFY"), "nse_infy_daily");
+        assert_eq!(policy.table("M&M"), "nse_m_m_daily");
+        assert_eq!(IdentifierPolicy::default().table("USER"), "user_");
+    }
+
+    #[test]
+    fn symbol_of_inverts_the_template() {
+        let policy = policy("nse_{symbol}_daily", false);
+        assert_eq!(policy.symbol_of("nse_infy_daily").as_deref(), Some("infy"));
+        assert_eq!(policy.symbol_of("infy"), None);
+        assert_eq!(policy.symbol_of("nse__daily"), None);
+        assert_eq!(
+            IdentifierPolicy::default().symbol_of("infy").as_deref(),
+            Some("infy")
+        );
+    }
+
+    #[test]
+    fn symbol_of_keeps_case() {
+        let policy = policy("nse_{symbol}", true);
+        assert_eq!(policy.table("INFY"), "\"nse_INFY\"");
+        assert_eq!(policy.symbol_of("nse_INFY").as_deref(), Some("INFY"));
+        assert_eq!(policy.symbol_of("nse_infy").as_deref(), Some("infy"));
+    }
+
+    #[test]
+    fn symbol_of_rejects_cut_names() {
+        let policy = policy("{symbol}_daily", false);
+        let table = policy.table(&"x".repeat(80));
+        assert_eq!(policy.symbol_of(&table), None);
+    }
+
+    #[test]
+    fn reserved_words_are_suffixed_or_quoted() {
+        let suffix = IdentifierPolicy::default();
+        assert_eq!(suffix.apply("select"), "select_");
+        assert_eq!(suffix.apply("Select"), "select_");
+        assert_eq!(suffix.apply("selected"), "selected");
+        let quote = IdentifierPolicy {
+            reserved_words: ReservedWords::Quote,
+            ..IdentifierPolicy::default()
+        };
+        assert_eq!(quote.apply("select"), "\"select\"");
+    }
+
+    #[test]
+    fn non_ascii_letters_are_transliterated() {
+        let policy = IdentifierPolicy::default();
+        assert_eq!(policy.apply("Nestlé"), "nestle");
+        assert_eq!(policy.apply("Société Générale"), "societe_generale");
+        assert_eq!(policy.apply("ÅÇÑØ"), "acno");
+        assert_eq!(policy.apply("日本 株"), "_");
+        assert_eq!(policy.apply("tata—steel"), "tata_steel");
+    }
+
+    #[test]
+    fn names_at_the_limit_are_kept() {
+        let policy = IdentifierPolicy::default();
+        let name = "a".repeat(MAX_IDENTIFIER_BYTES);
+        assert_eq!(policy.apply(&name), name);
+        // 126 bytes in, 63 once transliterated.
+        let name = "é".repeat(MAX_IDENTIFIER_BYTES);
+        assert_eq!(policy.apply(&name), "e".repeat(MAX_IDENTIFIER_BYTES));
+    }
+
+    #[test]
+    fn long_names_are_cut_with_a_hash() {
+        let policy = IdentifierPolicy::default();
+        let name = "a".repeat(MAX_IDENTIFIER_BYTES + 1);
+        let hash = format!("{:x}", Sha256::digest(name.as_bytes()));
+        let table = policy.apply(&name);
+        assert_eq!(table.len(), MAX_IDENTIFIER_BYTES);
+        assert_eq!(table, format!("{}_{}", "a".repeat(54), &hash[..8]));
+
+        // The cut falls where the input holds a two-byte letter.
+        let name = format!("{}é{}", "a".repeat(53), "b".repeat(20));
+        let hash = format!("{:x}", Sha256::digest(name.as_bytes()));
+        let table = policy.apply(&name);
+        assert_eq!(table.len(), MAX_IDENTIFIER_BYTES);
+        assert_eq!(table, format!("{}e_{}", "a".repeat(53), &hash[..8]));
+        assert_ne!(
+            table,
+            policy.apply(&format!("{}e{}", "a".repeat(53), "b".repeat(20)))
+        );
+    }
+
+    #[test]
+    fn templates_name_the_symbol_once() {
+        assert!(check_template("nse_{symbol}").is_ok());
+        assert!(check_template("nse").is_err());
+        assert!(check_template("{symbol}_{symbol}").is_err());
+    }
+}
@@ -0,0 +1,68 @@
+use anyhow::{bail, Context, Result};
+use tokio::process::Command;
+
+/// Fetches a secret through the backend named by `reference`.
+///
+/// References take the form `vault:<path>[#field]` or
+/// `aws:<secret-id>[#json-key]`. The backends are reached through the `vault`
+/// and `aws` CLIs so the host's existing authentication (tokens, instance
+/// roles, profiles) applies unchanged.
+pub async fn fetch(reference: &str) -> Result<String> {
+    let (backend, rest) = reference
+        .split_once(':')
+        .with_context(|| format!("secret reference has no backend: {reference}"))?;
+    let (path, key) = match rest.split_once('#') {
+        Some((path, key)) => (path, Some(key)),
+        None => (rest, None),
+    };
+
+    match backend {
+        "vault" => {
+            let field = format!("-field={}", key.unwrap_or("password"));
+            run(Command::new("vault").args(["kv", "get", &field, path])).await
+        }
+        "aws" => {
+            let secret = run(Command::new("aws").args([
+                "secretsmanager",
+                "get-secret-value",
+                "--secret-id",
+                path,
+                "--query",
+                "SecretString",
+                "--output",
+                "text",
+            ]))
+            .await?;
+            match key {
+                Some(key) => json_field(&secret, key),
+                None => Ok(secret),
+            }
+        }
+        _ => bail!("unknown secret backend: {backend}"),
+    }
+}
+
+async fn run(cmd: &mut Command) -> Result<String> {
+    let program = cmd.as_std().get_program().to_string_lossy().into_owned();
+    let output = cmd
+        .output()
+        .await
+        .with_context(|| format!("running {program}"))?;
+    if !output.status.success() {
+        bail!(
+            "{program} failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    let value = String::from_utf8(output.stdout).with_context(|| format!("{program} output"))?;
+    Ok(value.trim_end_matches(['\r', '\n']).to_string())
+}
+
+fn json_field(secret: &str, key: &str) -> Result<String> {
+    let value: serde_json::Value = serde_json::from_str(secret).context("secret is not JSON")?;
+    match value.get(key) {
+        Some(serde_json::Value::String(s)) => Ok(s.clone()),
+        Some(other) => Ok(other.to_string()),
+        None => bail!("secret has no key: {key}"),
+    }
+}
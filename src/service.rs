@@ -0,0 +1,91 @@
+use anyhow::{bail, Context, Result};
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use tokio_postgres::Config;
+
+/// Service file parameters the connection understands; others are skipped
+/// with a warning.
+static SUPPORTED: &[&str] = &[
+    "host",
+    "hostaddr",
+    "port",
+    "dbname",
+    "user",
+    "password",
+    "options",
+    "application_name",
+    "sslmode",
+    "connect_timeout",
+    "keepalives",
+    "keepalives_idle",
+    "target_session_attrs",
+    "channel_binding",
+];
+
+/// Connection parameters of `name` from the first `pg_service.conf` that
+/// defines it, searched where libpq looks: `$PGSERVICEFILE` or
+/// `~/.pg_service.conf`, then `$PGSYSCONFDIR/pg_service.conf`, then the
+/// system-wide files.
+pub fn config(name: &str) -> Result<Config> {
+    for path in service_files() {
+        let Ok(contents) = fs::read_to_string(&path) else {
+            continue;
+        };
+        let Some(params) = section(&contents, name) else {
+            continue;
+        };
+
+        let mut conninfo = Vec::new();
+        for (key, value) in params {
+            if !SUPPORTED.contains(&key) {
+                eprintln!(
+                    "ignoring unsupported parameter {key} of service {name} in {}",
+                    path.display()
+                );
+                continue;
+            }
+            let value = value.replace('\\', "\\\\").replace('\'', "\\'");
+            conninfo.push(format!("{key}='{value}'"));
+        }
+        return conninfo
+            .join(" ")
+            .parse()
+            .with_context(|| format!("invalid service {name} in {}", path.display()));
+    }
+    bail!("service not found in any pg_service.conf: {name}")
+}
+
+fn service_files() -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    match env::var_os("PGSERVICEFILE") {
+        Some(file) => files.push(PathBuf::from(file)),
+        None => {
+            if let Some(home) = env::var_os("HOME") {
+                files.push(PathBuf::from(home).join(".pg_service.conf"));
+            }
+        }
+    }
+    if let Some(dir) = env::var_os("PGSYSCONFDIR") {
+        files.push(PathBuf::from(dir).join("pg_service.conf"));
+    }
+    files.push(PathBuf::from("/etc/postgresql-common/pg_service.conf"));
+    files.push(PathBuf::from("/etc/pg_service.conf"));
+    files
+}
+
+/// `key=value` lines of the `[name]` section of an INI style file.
+pub fn section<'a>(contents: &'a str, name: &str) -> Option<Vec<(&'a str, &'a str)>> {
+    let mut lines = contents
+        .lines()
+        .map(str::trim)
+        .filter(|l| !l.is_empty() && !l.starts_with('#'));
+    lines.find(|l| *l == format!("[{name}]"))?;
+    Some(
+        lines
+            .take_while(|l| !l.starts_with('['))
+            .filter_map(|l| l.split_once('='))
+            .map(|(k, v)| (k.trim(), v.trim()))
+            .collect(),
+    )
+}
@@ -0,0 +1,27 @@
+create table if not exists pg_nifty_dump_audit (
+    id bigserial primary key,
+    table_name text not null,
+    operation text not null,
+    changed_by text not null default current_user,
+    changed_at timestamptz not null default now(),
+    old_row jsonb,
+    new_row jsonb
+);
+
+create or replace function pg_nifty_dump_audit() returns trigger
+language plpgsql as $$
+begin
+    -- Rows replaced by the loader itself are not manual edits.
+    if current_setting('pg_nifty_dump.loading', true) = 'on' then
+        return null;
+    end if;
+    insert into pg_nifty_dump_audit (table_name, operation, old_row, new_row)
+    values (
+        tg_table_name,
+        lower(tg_op),
+        to_jsonb(old),
+        case when tg_op = 'UPDATE' then to_jsonb(new) end
+    );
+    return null;
+end
+$$;
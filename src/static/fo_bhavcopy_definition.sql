@@ -0,0 +1,16 @@
+(
+    date timestamptz not null,
+    instrument text not null,
+    expiry date not null,
+    strike double precision,
+    option_type text,
+    open double precision,
+    high double precision,
+    low double precision,
+    close double precision,
+    settle double precision,
+    contracts bigint,
+    value_lakh double precision,
+    oi bigint,
+    oi_change bigint
+);
@@ -0,0 +1,333 @@
+use anyhow::{bail, Context, Result};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+
+use crate::report::{FileReport, Status};
+use crate::tls::{Tls, TlsArgs};
+
+/// Span status codes of OTLP.
+const STATUS_OK: u8 = 1;
+const STATUS_ERROR: u8 = 2;
+/// Delta aggregation temporality: each export carries only new counts.
+const DELTA: u8 = 1;
+
+/// Traces and metrics of a load run, exported as OTLP/HTTP JSON to a
+/// collector. The run is one trace; each file is a span under the run's,
+/// with a span per stage (validate, verify, compute, create, copy) below it.
+pub struct Telemetry {
+    host: String,
+    port: u16,
+    prefix: String,
+    /// Set for an https:// endpoint.
+    tls: Option<Tls>,
+    trace_id: String,
+    run_span: String,
+    run_started: u64,
+    next_id: u64,
+    /// Span id, file name and start of the file being loaded.
+    file: Option<(String, String, u64)>,
+    spans: Vec<Value>,
+    metrics: Vec<Value>,
+}
+
+impl Telemetry {
+    /// Exports to `endpoint`, the base URL of the collector's OTLP/HTTP
+    /// receiver, e.g. `http://localhost:4318`.
+    pub fn new(endpoint: &str, tls: &TlsArgs) -> Result<Self> {
+        let (tls, rest) = if let Some(rest) = endpoint.strip_prefix("https://") {
+            (Some(Tls::new(tls)?), rest)
+        } else if let Some(rest) = endpoint.strip_prefix("http://") {
+            (None, rest)
+        } else {
+            bail!("OTLP endpoint must be an http:// or https:// URL: {endpoint}");
+        };
+        let (authority, prefix) = match rest.find('/') {
+            Some(i) => (&rest[..i], rest[i..].trim_end_matches('/')),
+            None => (rest, ""),
+        };
+        let (host, port) = match authority.rsplit_once(':') {
+            Some((host, port)) => (
+                host,
+                port.parse()
+                    .with_context(|| format!("invalid port in OTLP endpoint: {endpoint}"))?,
+            ),
+            None => (authority, if tls.is_some() { 443 } else { 80 }),
+        };
+
+        let now = now_nanos();
+        let seed = format!("{now}-{}", std::process::id());
+        let mut telemetry = Telemetry {
+            host: host.to_string(),
+            port,
+            prefix: prefix.to_string(),
+            tls,
+            trace_id: hex_id(&seed, 16),
+            run_span: String::new(),
+            run_started: now,
+            next_id: 0,
+            file: None,
+            spans: Vec::new(),
+            metrics: Vec::new(),
+        };
+        telemetry.run_span = telemetry.span_id();
+        Ok(telemetry)
+    }
+
+    fn span_id(&mut self) -> String {
+        self.next_id += 1;
+        hex_id(&format!("{}-{}", self.trace_id, self.next_id), 8)
+    }
+
+    pub fn start_file(&mut self, file: &str) {
+        let id = self.span_id();
+        self.file = Some((id, file.to_string(), now_nanos()));
+    }
+
+    /// Records a stage of the current file that began at `started` and
+    /// ends now.
+    pub fn stage(&mut self, name: &str, started: SystemTime, attributes: &[(&str, Value)]) {
+        let Some((parent, file, _)) = self.file.clone() else {
+            return;
+        };
+        let mut attrs = vec![("file", json!(file))];
+        attrs.extend(attributes.iter().cloned());
+        let span = Span {
+            id: self.span_id(),
+            parent,
+            name,
+            start: nanos(started),
+            attributes: &attrs,
+            error: None,
+        };
+        self.spans.push(span.encode(&self.trace_id));
+    }
+
+    /// Ends the current file's span and adds its rows and quality to the
+    /// metrics.
+    pub fn end_file(&mut self, report: &FileReport) {
+        let Some((id, file, start)) = self.file.take() else {
+            return;
+        };
+        let status = serde_json::to_value(report.status).unwrap_or_default();
+        let attrs = [
+            ("file", json!(file)),
+            ("status", status.clone()),
+            ("rows", json!(report.rows)),
+            ("tables", json!(report.tables.join(","))),
+        ];
+        let error = match report.status {
+            Status::Failed | Status::Invalid => Some(report.error.clone().unwrap_or_default()),
+            _ => None,
+        };
+        let span = Span {
+            id,
+            parent: self.run_span.clone(),
+            name: "file",
+            start,
+            attributes: &attrs,
+            error: error.as_deref(),
+        };
+        self.spans.push(span.encode(&self.trace_id));
+
+        let now = now_nanos();
+        let point = |value: Value, attributes: &[(&str, Value)]| {
+            let mut point = json!({
+                "startTimeUnixNano": start.to_string(),
+                "timeUnixNano": now.to_string(),
+                "attributes": encode_attributes(attributes),
+            });
+            match value {
+                Value::Number(n) if n.is_u64() => point["asInt"] = json!(n.to_string()),
+                v => point["asDouble"] = v,
+            }
+            point
+        };
+        self.metrics.push(json!({
+            "name": "pg_nifty_dump.files",
+            "unit": "{file}",
+            "sum": {
+                "aggregationTemporality": DELTA,
+                "isMonotonic": true,
+                "dataPoints": [point(json!(1u64), &[("status", status)])],
+            },
+        }));
+        self.metrics.push(json!({
+            "name": "pg_nifty_dump.rows",
+            "unit": "{row}",
+            "sum": {
+                "aggregationTemporality": DELTA,
+                "isMonotonic": true,
+                "dataPoints": [point(json!(report.rows), &[("file", json!(file))])],
+            },
+        }));
+        if let Some(quality) = &report.quality {
+            for (name, value) in [
+                ("score", json!(quality.score)),
+                ("valid_pct", json!(quality.valid_pct)),
+                ("consistency_pct", json!(quality.consistency_pct)),
+                ("gaps", json!(quality.gaps)),
+                ("outliers", json!(quality.outliers)),
+            ] {
+                self.metrics.push(json!({
+                    "name": format!("pg_nifty_dump.quality.{name}"),
+                    "gauge": { "dataPoints": [point(value, &[("file", json!(file))])] },
+                }));
+            }
+        }
+    }
+
+    /// Sends the spans and metrics gathered since the last export.
+    pub async fn flush(&mut self) -> Result<()> {
+        let resource = json!({
+            "attributes": encode_attributes(&[("service.name", json!("pg_nifty_dump"))]),
+        });
+        let scope = json!({ "name": "pg_nifty_dump", "version": env!("CARGO_PKG_VERSION") });
+        if !self.spans.is_empty() {
+            let spans = std::mem::take(&mut self.spans);
+            let body = json!({
+                "resourceSpans": [{
+                    "resource": resource,
+                    "scopeSpans": [{ "scope": scope, "spans": spans }],
+                }],
+            });
+            self.post("/v1/traces", &body).await?;
+        }
+        if !self.metrics.is_empty() {
+            let metrics = std::mem::take(&mut self.metrics);
+            let body = json!({
+                "resourceMetrics": [{
+                    "resource": resource,
+                    "scopeMetrics": [{ "scope": scope, "metrics": metrics }],
+                }],
+            });
+            self.post("/v1/metrics", &body).await?;
+        }
+        Ok(())
+    }
+
+    /// Ends the run's span and exports everything left.
+    pub async fn finish(&mut self) -> Result<()> {
+        let span = Span {
+            id: self.run_span.clone(),
+            parent: String::new(),
+            name: "load",
+            start: self.run_started,
+            attributes: &[],
+            error: None,
+        };
+        self.spans.push(span.encode(&self.trace_id));
+        self.flush().await
+    }
+
+    async fn post(&self, path: &str, body: &Value) -> Result<()> {
+        let scheme = if self.tls.is_some() { "https" } else { "http" };
+        let url = format!(
+            "{scheme}://{}:{}{}{path}",
+            self.host, self.port, self.prefix
+        );
+        let body = body.to_string();
+        let tcp = TcpStream::connect((self.host.as_str(), self.port))
+            .await
+            .with_context(|| format!("connecting to OTLP endpoint: {url}"))?;
+        let request = format!(
+            "POST {}{path} HTTP/1.1\r\nHost: {}:{}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+            self.prefix,
+            self.host,
+            self.port,
+            body.len()
+        );
+        let status = match &self.tls {
+            Some(tls) => exchange(tls.connect(&self.host, tcp).await?, &request).await?,
+            None => exchange(tcp, &request).await?,
+        };
+        let status = status.trim();
+        if status
+            .split_whitespace()
+            .nth(1)
+            .is_none_or(|code| !code.starts_with('2'))
+        {
+            bail!("OTLP export to {url} failed: {status}");
+        }
+        Ok(())
+    }
+}
+
+/// Sends the request, returning the response's status line.
+async fn exchange<S: AsyncRead + AsyncWrite + Unpin>(stream: S, request: &str) -> Result<String> {
+    let mut stream = BufReader::new(stream);
+    stream.get_mut().write_all(request.as_bytes()).await?;
+    let mut status = String::new();
+    stream.read_line(&mut status).await?;
+    Ok(status)
+}
+
+struct Span<'a> {
+    id: String,
+    parent: String,
+    name: &'a str,
+    start: u64,
+    attributes: &'a [(&'a str, Value)],
+    error: Option<&'a str>,
+}
+
+impl Span<'_> {
+    fn encode(&self, trace_id: &str) -> Value {
+        let status = match self.error {
+            Some(message) => json!({ "code": STATUS_ERROR, "message": message }),
+            None => json!({ "code": STATUS_OK }),
+        };
+        json!({
+            "traceId": trace_id,
+            "spanId": self.id,
+            "parentSpanId": self.parent,
+            "name": self.name,
+            // SPAN_KIND_INTERNAL
+            "kind": 1,
+            "startTimeUnixNano": self.start.to_string(),
+            "endTimeUnixNano": now_nanos().to_string(),
+            "attributes": encode_attributes(self.attributes),
+            "status": status,
+        })
+    }
+}
+
+/// OTLP key/value list; integers are sent as strings, as OTLP JSON wants
+/// for 64-bit values.
+fn encode_attributes(attributes: &[(&str, Value)]) -> Value {
+    let encoded: Vec<Value> = attributes
+        .iter()
+        .map(|(key, value)| {
+            let value = match value {
+                Value::Number(n) if n.is_u64() || n.is_i64() => {
+                    json!({ "intValue": n.to_string() })
+                }
+                Value::Number(n) => json!({ "doubleValue": n }),
+                Value::Bool(b) => json!({ "boolValue": b }),
+                Value::String(s) => json!({ "stringValue": s }),
+                v => json!({ "stringValue": v.to_string() }),
+            };
+            json!({ "key": key, "value": value })
+        })
+        .collect();
+    Value::Array(encoded)
+}
+
+/// Hex id of `len` bytes derived from `seed`.
+fn hex_id(seed: &str, len: usize) -> String {
+    Sha256::digest(seed.as_bytes())[..len]
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect()
+}
+
+fn nanos(t: SystemTime) -> u64 {
+    t.duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_nanos() as u64)
+}
+
+fn now_nanos() -> u64 {
+    nanos(SystemTime::now())
+}
@@ -0,0 +1,182 @@
+use anyhow::{bail, Result};
+use clap::Args;
+use std::sync::OnceLock;
+use tokio::net::TcpStream;
+#[cfg(feature = "https")]
+use {
+    anyhow::Context,
+    base64::Engine as _,
+    std::fs,
+    std::path::{Path, PathBuf},
+    std::sync::Arc,
+    tokio_rustls::client::TlsStream,
+    tokio_rustls::rustls::{Certificate, ClientConfig, PrivateKey, RootCertStore, ServerName},
+    tokio_rustls::TlsConnector,
+};
+
+/// CA bundles tried, after `$SSL_CERT_FILE`, for the system's roots.
+#[cfg(feature = "https")]
+static CA_BUNDLES: &[&str] = &[
+    "/etc/ssl/certs/ca-certificates.crt",
+    "/etc/pki/tls/certs/ca-bundle.crt",
+    "/etc/ssl/cert.pem",
+];
+
+/// Certificates of the HTTPS clients: --fetch downloads and OTLP export.
+/// Without the `https` feature there are none to give.
+#[derive(Debug, Clone, Default, Args)]
+pub struct TlsArgs {
+    /// PEM bundle of CA certificates trusted besides the system's, e.g. of
+    /// a TLS-intercepting proxy or an internal CA.
+    #[cfg(feature = "https")]
+    #[clap(long = "ca-cert", env = "PG_NIFTY_DUMP_CA_CERT")]
+    ca_cert: Option<PathBuf>,
+
+    /// PEM certificate chain presented to servers requiring mutual TLS.
+    #[cfg(feature = "https")]
+    #[clap(long = "client-cert", requires = "client_key")]
+    client_cert: Option<PathBuf>,
+
+    /// PEM private key (PKCS#8, RSA or EC) of --client-cert.
+    #[cfg(feature = "https")]
+    #[clap(long = "client-key", requires = "client_cert")]
+    client_key: Option<PathBuf>,
+}
+
+/// Client side of TLS connections with the configured trust and identity.
+/// Without the `https` feature only plain connections are made.
+#[derive(Clone)]
+pub struct Tls {
+    #[cfg(feature = "https")]
+    connector: TlsConnector,
+}
+
+impl Tls {
+    #[cfg(not(feature = "https"))]
+    pub fn new(_args: &TlsArgs) -> Result<Self> {
+        Ok(Tls {})
+    }
+
+    #[cfg(not(feature = "https"))]
+    pub async fn connect(&self, host: &str, _tcp: TcpStream) -> Result<TcpStream> {
+        bail!("connecting to {host} over TLS needs a build with the https feature")
+    }
+
+    #[cfg(feature = "https")]
+    pub fn new(args: &TlsArgs) -> Result<Self> {
+        let mut roots = RootCertStore::empty();
+        let bundles = std::env::var("SSL_CERT_FILE")
+            .ok()
+            .into_iter()
+            .chain(CA_BUNDLES.iter().map(|b| b.to_string()));
+        for bundle in bundles {
+            let Ok(pem) = fs::read_to_string(&bundle) else {
+                continue;
+            };
+            roots.add_parsable_certificates(&pem_blocks(&pem, "CERTIFICATE"));
+            if !roots.is_empty() {
+                break;
+            }
+        }
+        if let Some(path) = &args.ca_cert {
+            let certificates = pem_blocks(&read(path)?, "CERTIFICATE");
+            if certificates.is_empty() {
+                bail!("no certificates in --ca-cert: {}", path.display());
+            }
+            for certificate in certificates {
+                roots
+                    .add(&Certificate(certificate))
+                    .with_context(|| format!("invalid CA certificate in {}", path.display()))?;
+            }
+        }
+        if roots.is_empty() {
+            bail!("no CA certificates found; set SSL_CERT_FILE or --ca-cert to a PEM bundle");
+        }
+
+        let builder = ClientConfig::builder()
+            .with_safe_defaults()
+            .with_root_certificates(roots);
+        let config = match (&args.client_cert, &args.client_key) {
+            (Some(cert), Some(key)) => {
+                let chain: Vec<Certificate> = pem_blocks(&read(cert)?, "CERTIFICATE")
+                    .into_iter()
+                    .map(Certificate)
+                    .collect();
+                if chain.is_empty() {
+                    bail!("no certificates in --client-cert: {}", cert.display());
+                }
+                let pem = read(key)?;
+                let Some(der) = ["PRIVATE KEY", "RSA PRIVATE KEY", "EC PRIVATE KEY"]
+                    .iter()
+                    .find_map(|label| pem_blocks(&pem, label).into_iter().next())
+                else {
+                    bail!("no private key in --client-key: {}", key.display());
+                };
+                builder
+                    .with_single_cert(chain, PrivateKey(der))
+                    .context("invalid --client-cert or --client-key")?
+            }
+            _ => builder.with_no_client_auth(),
+        };
+        Ok(Tls {
+            connector: TlsConnector::from(Arc::new(config)),
+        })
+    }
+
+    #[cfg(feature = "https")]
+    pub async fn connect(&self, host: &str, tcp: TcpStream) -> Result<TlsStream<TcpStream>> {
+        let server_name =
+            ServerName::try_from(host).with_context(|| format!("invalid host name: {host}"))?;
+        self.connector
+            .connect(server_name, tcp)
+            .await
+            .with_context(|| format!("TLS handshake with {host}"))
+    }
+}
+
+/// [`Tls`] built on the first TLS connection, so that runs making none
+/// neither read the certificates nor fail on them.
+pub struct LazyTls<'a> {
+    args: &'a TlsArgs,
+    tls: OnceLock<Tls>,
+}
+
+impl<'a> LazyTls<'a> {
+    pub fn new(args: &'a TlsArgs) -> Self {
+        LazyTls {
+            args,
+            tls: OnceLock::new(),
+        }
+    }
+
+    pub fn get(&self) -> Result<&Tls> {
+        if let Some(tls) = self.tls.get() {
+            return Ok(tls);
+        }
+        let tls = Tls::new(self.args)?;
+        Ok(self.tls.get_or_init(|| tls))
+    }
+}
+
+#[cfg(feature = "https")]
+fn read(path: &Path) -> Result<String> {
+    fs::read_to_string(path).with_context(|| format!("reading file: {}", path.display()))
+}
+
+/// DER contents of the PEM blocks with this label.
+#[cfg(feature = "https")]
+fn pem_blocks(pem: &str, label: &str) -> Vec<Vec<u8>> {
+    let begin = format!("-----BEGIN {label}-----");
+    let end = format!("-----END {label}-----");
+    pem.split(begin.as_str())
+        .skip(1)
+        .filter_map(|block| {
+            let body: String = block
+                .split(end.as_str())
+                .next()?
+                .split_whitespace()
+                .collect();
+            base64::engine::general_purpose::STANDARD.decode(body).ok()
+        })
+        .collect()
+}
@@ -0,0 +1,84 @@
+use anyhow::{bail, Context, Result};
+use std::path::Path;
+
+/// Largest difference accepted between two values of a column: either
+/// bound suffices. The default accepts only equal values.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Tolerance {
+    pub absolute: f64,
+    /// Fraction of the larger magnitude of the two values.
+    pub relative: f64,
+}
+
+impl Tolerance {
+    pub fn accepts(self, a: f64, b: f64) -> bool {
+        if a == b || (a.is_nan() && b.is_nan()) {
+            return true;
+        }
+        let difference = (a - b).abs();
+        difference <= self.absolute || difference <= self.relative * a.abs().max(b.abs())
+    }
+}
+
+/// Tolerances by column, from a CSV file of `column,absolute,relative`
+/// rows. Columns match ignoring case; a pattern ending in `*`, e.g. `ema*`
+/// or `*`, matches every column it prefixes, the longest pattern winning
+/// and an exact name beating every pattern. Empty bounds are zero, so
+/// `volume,,` compares volume exactly.
+#[derive(Debug, Default)]
+pub struct Tolerances {
+    entries: Vec<(String, Tolerance)>,
+}
+
+impl Tolerances {
+    pub fn load(path: Option<&Path>) -> Result<Self> {
+        let mut tolerances = Tolerances::default();
+        let Some(path) = path else {
+            return Ok(tolerances);
+        };
+
+        let mut rdr = csv::Reader::from_path(path)
+            .with_context(|| format!("opening tolerances: {}", path.display()))?;
+        for record in rdr.records() {
+            let record =
+                record.with_context(|| format!("reading tolerances: {}", path.display()))?;
+            let Some(column) = record.get(0).map(str::trim).filter(|c| !c.is_empty()) else {
+                bail!("malformed tolerance entry: {record:?}");
+            };
+            let bound = |i: usize| -> Result<f64> {
+                let field = record.get(i).unwrap_or_default().trim();
+                if field.is_empty() {
+                    return Ok(0.0);
+                }
+                match field.parse::<f64>() {
+                    Ok(bound) if bound >= 0.0 => Ok(bound),
+                    _ => bail!("invalid tolerance of {column}: {field}"),
+                }
+            };
+            let tolerance = Tolerance {
+                absolute: bound(1)?,
+                relative: bound(2)?,
+            };
+            tolerances.entries.push((column.to_lowercase(), tolerance));
+        }
+        Ok(tolerances)
+    }
+
+    pub fn for_column(&self, column: &str) -> Tolerance {
+        let column = column.to_lowercase();
+        if let Some((_, tolerance)) = self.entries.iter().find(|(c, _)| *c == column) {
+            return *tolerance;
+        }
+        self.entries
+            .iter()
+            .filter_map(|(pattern, tolerance)| {
+                let prefix = pattern.strip_suffix('*')?;
+                column
+                    .starts_with(prefix)
+                    .then_some((prefix.len(), *tolerance))
+            })
+            .max_by_key(|(len, _)| *len)
+            .map(|(_, tolerance)| tolerance)
+            .unwrap_or_default()
+    }
+}
@@ -0,0 +1,659 @@
+#[cfg(feature = "parallel")]
+use anyhow::Context;
+use anyhow::Result;
+use clap::ValueEnum;
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+use std::collections::{HashMap, HashSet};
+
+use crate::schema;
+use pg_nifty_dump::pipeline::Batch;
+
+/// Indicators with the longest look-back windows, the last to become
+/// populated at the start of a series.
+static LONG_WINDOW_COLUMNS: &[&str] = &[
+    "sma20", "ema20", "trima20", "kama30", "adx20", "mom20", "roc20", "BETA",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum TrimWarmup {
+    /// Drop leading rows until the long-window indicators are populated.
+    LongWindow,
+    /// Start the series at the first row with every column populated.
+    Complete,
+}
+
+/// How the raw and adjusted series of files with `adj_*` columns are stored.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum AdjustedSeries {
+    /// Raw prices into `<table>_raw`, adjusted ones into `<table>_adj`.
+    Tables,
+    /// Both series in the one table, told apart by a `series_type` column
+    /// of `raw` or `adj`.
+    Column,
+}
+
+/// How a column mixing `12.5%` and bare numbers is brought to one scale.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum PercentRule {
+    /// Store percentages: `12.5%` becomes 12.5, bare numbers are kept.
+    Percent,
+    /// Store fractions: `12.5%` becomes 0.125, bare numbers are kept.
+    Fraction,
+    /// Store percentages, taking bare numbers within -1..=1 as fractions
+    /// (0.125 becomes 12.5). Suits columns like WILLR or ROC whose
+    /// percentages rarely fall within a point of zero.
+    DetectFraction,
+}
+
+/// Brings the listed columns to one scale, rewriting cells in place.
+/// Returns the number of cells changed.
+pub fn normalize_percent(
+    columns: &[String],
+    records: &mut [csv::ByteRecord],
+    rules: &[(String, PercentRule)],
+) -> usize {
+    let rules: Vec<(usize, PercentRule)> = rules
+        .iter()
+        .filter_map(|(column, rule)| {
+            let i = columns
+                .iter()
+                .position(|c| c.eq_ignore_ascii_case(column))?;
+            Some((i, *rule))
+        })
+        .collect();
+    if rules.is_empty() {
+        return 0;
+    }
+
+    let mut changed = 0;
+    for record in records.iter_mut() {
+        let mut fields: Vec<Vec<u8>> = record.iter().map(<[u8]>::to_vec).collect();
+        let mut dirty = false;
+        for &(i, rule) in &rules {
+            let Some(field) = fields.get(i) else {
+                continue;
+            };
+            let Some(text) = std::str::from_utf8(field).ok().map(str::trim) else {
+                continue;
+            };
+            let (number, percent) = match text.strip_suffix('%') {
+                Some(n) => (n.trim(), true),
+                None => (text, false),
+            };
+            let Ok(value) = number.parse::<f64>() else {
+                continue;
+            };
+            let value = match (rule, percent) {
+                (PercentRule::Percent, true) => value,
+                (PercentRule::Fraction, true) => value / 100.0,
+                (PercentRule::DetectFraction, true) => value,
+                (PercentRule::DetectFraction, false) if value.abs() <= 1.0 => value * 100.0,
+                _ => continue,
+            };
+            fields[i] = value.to_string().into_bytes();
+            dirty = true;
+            changed += 1;
+        }
+        if dirty {
+            let position = record.position().cloned();
+            *record = csv::ByteRecord::from(fields);
+            record.set_position(position);
+        }
+    }
+    changed
+}
+
+/// Separates the raw and adjusted series of a batch with `adj_*` columns.
+///
+/// The adjusted series takes each `adj_*` column for its raw column and
+/// scales the other price columns (see [`schema::price_columns`]) by the
+/// row's `adj_close / close`, kept in an `adjustment_factor` column; rows
+/// without both closes get empty prices. Without a mode the `adj_*`
+/// columns are stored as they are.
+pub fn split_adjusted(mut batch: Batch, mode: Option<AdjustedSeries>) -> Vec<Batch> {
+    let adjusted: Vec<usize> = (0..batch.columns.len())
+        .filter(|&i| schema::ADJUSTED_COLUMNS.contains(&batch.columns[i].as_str()))
+        .collect();
+    if adjusted.is_empty() {
+        return vec![batch];
+    }
+    let Some(mode) = mode else {
+        for &i in &adjusted {
+            let column = batch.columns[i].clone();
+            batch
+                .extra_columns
+                .push((column, "double precision".to_string()));
+        }
+        return vec![batch];
+    };
+
+    let num = |r: &csv::ByteRecord, i: Option<usize>| -> Option<f64> {
+        let field = std::str::from_utf8(r.get(i?)?).ok()?.trim();
+        field.parse::<f64>().ok().filter(|v| v.is_finite())
+    };
+    let close = batch.column("close");
+    let adj_close = batch.column("adj_close");
+    let prices: Vec<usize> = schema::price_columns()
+        .iter()
+        .filter_map(|c| batch.column(c))
+        .collect();
+    // The `adj_*` column given for each raw column.
+    let given: HashMap<usize, usize> = adjusted
+        .iter()
+        .filter_map(|&i| Some((batch.column(&batch.columns[i]["adj_".len()..])?, i)))
+        .collect();
+    let keep: Vec<usize> = (0..batch.columns.len())
+        .filter(|i| !adjusted.contains(i))
+        .collect();
+
+    let mut raw = Vec::with_capacity(batch.records.len());
+    let mut adj = Vec::with_capacity(batch.records.len());
+    for record in &batch.records {
+        let factor = match (num(record, close), num(record, adj_close)) {
+            (Some(c), Some(a)) if c != 0.0 => Some(a / c),
+            _ => None,
+        };
+        let mut r = csv::ByteRecord::with_capacity(record.as_slice().len(), keep.len() + 2);
+        let mut a = csv::ByteRecord::with_capacity(record.as_slice().len(), keep.len() + 2);
+        for &i in &keep {
+            r.push_field(&record[i]);
+            match (given.get(&i), prices.contains(&i)) {
+                (Some(&j), _) => a.push_field(&record[j]),
+                (None, true) => match num(record, Some(i)).zip(factor) {
+                    Some((v, f)) => a.push_field((v * f).to_string().as_bytes()),
+                    None => a.push_field(b""),
+                },
+                (None, false) => a.push_field(&record[i]),
+            }
+        }
+        let factor = factor.map(|f| f.to_string()).unwrap_or_default();
+        raw.push((r, factor.clone()));
+        adj.push((a, factor));
+    }
+
+    let Batch {
+        table_name,
+        columns,
+        mut extra_columns,
+        shared,
+        source,
+        layout,
+        ..
+    } = batch;
+    let columns: Vec<String> = keep.iter().map(|&i| columns[i].clone()).collect();
+    let factor = "adjustment_factor".to_string();
+    let double = "double precision".to_string();
+    match mode {
+        AdjustedSeries::Tables => {
+            let raw = Batch {
+                table_name: suffixed(&table_name, "_raw"),
+                columns: columns.clone(),
+                records: raw.into_iter().map(|(r, _)| r).collect(),
+                extra_columns: extra_columns.clone(),
+                shared,
+                source: source.clone(),
+                layout,
+            };
+            extra_columns.push((factor.clone(), double));
+            let adj = Batch {
+                table_name: suffixed(&table_name, "_adj"),
+                columns: columns.into_iter().chain([factor]).collect(),
+                records: adj
+                    .into_iter()
+                    .map(|(mut a, factor)| {
+                        a.push_field(factor.as_bytes());
+                        a
+                    })
+                    .collect(),
+                extra_columns,
+                shared,
+                source,
+                layout,
+            };
+            vec![raw, adj]
+        }
+        AdjustedSeries::Column => {
+            let records = raw
+                .into_iter()
+                .map(|r| ("raw", r))
+                .chain(adj.into_iter().map(|a| ("adj", a)))
+                .map(|(series, (mut record, factor))| {
+                    record.push_field(series.as_bytes());
+                    record.push_field(factor.as_bytes());
+                    record
+                })
+                .collect();
+            extra_columns.push(("series_type".to_string(), "text".to_string()));
+            extra_columns.push((factor.clone(), double));
+            vec![Batch {
+                table_name,
+                columns: columns
+                    .into_iter()
+                    .chain(["series_type".to_string(), factor])
+                    .collect(),
+                records,
+                extra_columns,
+                shared,
+                source,
+                layout,
+            }]
+        }
+    }
+}
+
+/// `name` with `suffix` appended, inside its closing quote when quoted.
+fn suffixed(name: &str, suffix: &str) -> String {
+    match name.strip_suffix('"') {
+        Some(stem) => format!("{stem}{suffix}\""),
+        None => format!("{name}{suffix}"),
+    }
+}
+
+/// Windows, in trading days, of the rolling volatility columns.
+const VOLATILITY_WINDOWS: [usize; 3] = [10, 20, 30];
+/// Multiple of ATR between the close and the volatility bands.
+const ATR_BAND_WIDTH: f64 = 2.0;
+
+fn is_missing(field: &[u8]) -> bool {
+    let field = field.trim_ascii();
+    field.is_empty() || field.eq_ignore_ascii_case(b"nan") || field.eq_ignore_ascii_case(b"null")
+}
+
+/// Drops the rows before the first one whose watched columns are all
+/// populated, per symbol when the batch mixes several. Returns the number of
+/// rows dropped.
+pub fn trim_warmup(batch: &mut Batch, mode: TrimWarmup) -> usize {
+    let watched: Vec<usize> = match mode {
+        TrimWarmup::LongWindow => LONG_WINDOW_COLUMNS
+            .iter()
+            .filter_map(|c| batch.column(c))
+            .collect(),
+        TrimWarmup::Complete => (0..batch.columns.len()).collect(),
+    };
+
+    let symbol = batch.column("symbol");
+    let mut started: HashSet<Vec<u8>> = HashSet::new();
+    let before = batch.records.len();
+    batch.records.retain(|r| {
+        let key = symbol.and_then(|i| r.get(i)).unwrap_or_default();
+        if started.contains(key) {
+            return true;
+        }
+        let populated = watched.iter().all(|&i| !r.get(i).is_none_or(is_missing));
+        if populated {
+            started.insert(key.to_vec());
+        }
+        populated
+    });
+    before - batch.records.len()
+}
+
+/// Appends rolling volatility columns to every batch: `volatility10/20/30`,
+/// the sample standard deviation of close-to-close returns over that many
+/// days, and `atr_upper`/`atr_lower`, the close plus or minus
+/// `ATR_BAND_WIDTH` times ATR. Rows are taken in file order, per symbol when
+/// a batch mixes several and per series of a `series_type` column; values
+/// are left empty until a window fills.
+///
+/// Each symbol of each batch is one task on `pool`.
+pub fn add_volatility(batches: &mut [Batch], pool: &ComputePool) {
+    let mut series = Vec::new();
+    for (b, batch) in batches.iter().enumerate() {
+        let close = batch.column("close");
+        let atr = batch.column("ATR");
+        let symbol = batch.column("symbol");
+        let series_type = batch.column("series_type");
+        let num = |r: &csv::ByteRecord, i: Option<usize>| -> Option<f64> {
+            let field = std::str::from_utf8(r.get(i?)?).ok()?.trim();
+            field.parse::<f64>().ok().filter(|v| v.is_finite())
+        };
+        let mut by_symbol: HashMap<(&[u8], &[u8]), usize> = HashMap::new();
+        for (row, record) in batch.records.iter().enumerate() {
+            let field = |i: Option<usize>| i.and_then(|i| record.get(i)).unwrap_or_default();
+            let key = (field(symbol), field(series_type));
+            let s = *by_symbol.entry(key).or_insert_with(|| {
+                series.push(Series {
+                    batch: b,
+                    ..Series::default()
+                });
+                series.len() - 1
+            });
+            let s = &mut series[s];
+            s.rows.push(row);
+            s.close.push(num(record, close));
+            s.atr.push(num(record, atr));
+        }
+    }
+
+    #[cfg(feature = "parallel")]
+    pool.install(|| series.par_iter_mut().for_each(Series::compute));
+    #[cfg(not(feature = "parallel"))]
+    {
+        let _ = pool;
+        series.iter_mut().for_each(Series::compute);
+    }
+
+    let width = VOLATILITY_WINDOWS.len() + 2;
+    for s in &series {
+        let records = &mut batches[s.batch].records;
+        for (row, fields) in s.rows.iter().zip(s.output.chunks_exact(width)) {
+            for field in fields {
+                match field {
+                    Some(v) => records[*row].push_field(v.to_string().as_bytes()),
+                    None => records[*row].push_field(b""),
+                }
+            }
+        }
+    }
+    for batch in batches {
+        let names = VOLATILITY_WINDOWS
+            .iter()
+            .map(|n| format!("volatility{n}"))
+            .chain(["atr_upper".to_string(), "atr_lower".to_string()]);
+        for name in names {
+            batch.columns.push(name.clone());
+            batch
+                .extra_columns
+                .push((name, "double precision".to_string()));
+        }
+    }
+}
+
+/// Rows of one symbol in one batch, in file order.
+#[derive(Default)]
+struct Series {
+    batch: usize,
+    rows: Vec<usize>,
+    close: Vec<Option<f64>>,
+    atr: Vec<Option<f64>>,
+    /// The volatility and band columns of each row, row after row.
+    output: Vec<Option<f64>>,
+}
+
+impl Series {
+    fn compute(&mut self) {
+        let mut returns = Vec::with_capacity(self.close.len());
+        self.output = Vec::with_capacity(self.close.len() * (VOLATILITY_WINDOWS.len() + 2));
+        let mut prev_close = None;
+        for (c, atr) in self.close.iter().zip(&self.atr) {
+            if let (Some(c), Some(p)) = (*c, prev_close) {
+                if p != 0.0 {
+                    returns.push(c / p - 1.0);
+                }
+            }
+            prev_close = c.or(prev_close);
+
+            // Each window is a contiguous slice ending at the row.
+            for n in VOLATILITY_WINDOWS {
+                let window = returns.len().checked_sub(n).map(|start| &returns[start..]);
+                self.output.push(window.map(stddev));
+            }
+            let band = atr.map(|a| ATR_BAND_WIDTH * a);
+            self.output.push(c.zip(band).map(|(c, b)| c + b));
+            self.output.push(c.zip(band).map(|(c, b)| c - b));
+        }
+    }
+}
+
+/// Threads of the indicator computation, built once per run. Without the
+/// `parallel` feature the loading thread computes.
+#[cfg(feature = "parallel")]
+pub type ComputePool = rayon::ThreadPool;
+
+#[cfg(not(feature = "parallel"))]
+pub struct ComputePool;
+
+/// As many compute threads as asked, or one per CPU. Idle threads steal
+/// tasks from busy ones, so long and short series balance.
+#[cfg(feature = "parallel")]
+pub fn compute_pool(threads: Option<usize>) -> Result<ComputePool> {
+    rayon::ThreadPoolBuilder::new()
+        .num_threads(threads.unwrap_or(0))
+        .thread_name(|i| format!("compute-{i}"))
+        .build()
+        .context("starting compute threads")
+}
+
+#[cfg(not(feature = "parallel"))]
+pub fn compute_pool(threads: Option<usize>) -> Result<ComputePool> {
+    if threads.is_some_and(|n| n > 1) {
+        anyhow::bail!("--compute-threads needs a build with the parallel feature");
+    }
+    Ok(ComputePool)
+}
+
+/// Sample standard deviation.
+fn stddev(values: &[f64]) -> f64 {
+    let n = values.len() as f64;
+    let mean = lane_sum(values, |v| v) / n;
+    let var = lane_sum(values, |v| (v - mean) * (v - mean)) / (n - 1.0);
+    var.sqrt()
+}
+
+/// Sum of `f` over the values, accumulated in four independent lanes the
+/// compiler can vectorize.
+fn lane_sum(values: &[f64], f: impl Fn(f64) -> f64) -> f64 {
+    let mut lanes = [0.0; 4];
+    let chunks = values.chunks_exact(4);
+    let rest: f64 = chunks.remainder().iter().map(|v| f(*v)).sum();
+    for chunk in chunks {
+        for (lane, v) in lanes.iter_mut().zip(chunk) {
+            *lane += f(*v);
+        }
+    }
+    lanes.iter().sum::<f64>() + rest
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn batch(rows: &[&[&str]]) -> Batch {
+        Batch {
+            table_name: "infy".to_string(),
+            columns: ["date", "open", "close", "adj_close"]
+                .map(String::from)
+                .to_vec(),
+            records: rows
+                .iter()
+                .map(|r| csv::ByteRecord::from(r.to_vec()))
+                .collect(),
+            extra_columns: Vec::new(),
+            shared: false,
+            source: "INFY.csv".to_string(),
+            layout: Default::default(),
+        }
+    }
+
+    fn fields(record: &csv::ByteRecord) -> Vec<&str> {
+        record
+            .iter()
+            .map(|f| std::str::from_utf8(f).unwrap())
+            .collect()
+    }
+
+    fn normalized(rule: PercentRule, cells: &[&str]) -> (usize, Vec<String>) {
+        let columns = ["date".to_string(), "WILLR".to_string()];
+        let mut records: Vec<csv::ByteRecord> = cells
+            .iter()
+            .map(|c| csv::ByteRecord::from(vec!["2024-01-01", c]))
+            .collect();
+        let changed = normalize_percent(&columns, &mut records, &[("willr".to_string(), rule)]);
+        let values = records.iter().map(|r| fields(r)[1].to_string()).collect();
+        (changed, values)
+    }
+
+    #[test]
+    fn percent_rule_strips_the_sign() {
+        let (changed, values) = normalized(PercentRule::Percent, &["12.5%", " 40 % ", "0.3", ""]);
+        assert_eq!(changed, 2);
+        assert_eq!(values, ["12.5", "40", "0.3", ""]);
+    }
+
+    #[test]
+    fn fraction_rule_divides_percentages() {
+        let (changed, values) = normalized(PercentRule::Fraction, &["12.5%", "0.3", "-50%", ""]);
+        assert_eq!(changed, 2);
+        assert_eq!(values, ["0.125", "0.3", "-0.5", ""]);
+    }
+
+    #[test]
+    fn detected_fractions_become_percentages() {
+        let (changed, values) = normalized(
+            PercentRule::DetectFraction,
+            &["0.125", "-1", "12.5", "7%", "", "n/a"],
+        );
+        assert_eq!(changed, 3);
+        assert_eq!(values, ["12.5", "-100", "12.5", "7", "", "n/a"]);
+    }
+
+    #[test]
+    fn adjusted_tables_scale_prices_by_the_close_ratio() {
+        let rows: &[&[&str]] = &[
+            &["2024-01-01", "100", "200", "100"],
+            &["2024-01-02", "90", "", "95"],
+        ];
+        let batches = split_adjusted(batch(rows), Some(AdjustedSeries::Tables));
+        let [raw, adj] = &batches[..] else {
+            panic!("expected two batches");
+        };
+        assert_eq!(raw.table_name, "infy_raw");
+        assert_eq!(raw.columns, ["date", "open", "close"]);
+        assert_eq!(fields(&raw.records[0]), ["2024-01-01", "100", "200"]);
+        assert_eq!(adj.table_name, "infy_adj");
+        assert_eq!(adj.columns, ["date", "open", "close", "adjustment_factor"]);
+        assert_eq!(fields(&adj.records[0]), ["2024-01-01", "50", "100", "0.5"]);
+        // Without both closes there is no factor to scale by.
+        assert_eq!(fields(&adj.records[1]), ["2024-01-02", "", "95", ""]);
+    }
+
+    #[test]
+    fn adjusted_column_tells_the_series_apart() {
+        let rows: &[&[&str]] = &[&["2024-01-01", "100", "200", "100"]];
+        let batches = split_adjusted(batch(rows), Some(AdjustedSeries::Column));
+        let [both] = &batches[..] else {
+            panic!("expected one batch");
+        };
+        assert_eq!(
+            both.columns,
+            ["date", "open", "close", "series_type", "adjustment_factor"]
+        );
+        assert_eq!(
+            fields(&both.records[0]),
+            ["2024-01-01", "100", "200", "raw", "0.5"]
+        );
+        assert_eq!(
+            fields(&both.records[1]),
+            ["2024-01-01", "50", "100", "adj", "0.5"]
+        );
+    }
+
+    #[test]
+    fn adjusted_columns_kept_without_a_mode() {
+        let rows: &[&[&str]] = &[&["2024-01-01", "100", "200", "100"]];
+        let batches = split_adjusted(batch(rows), None);
+        assert_eq!(batches.len(), 1);
+        assert_eq!(batches[0].columns.len(), 4);
+        assert_eq!(
+            batches[0].extra_columns,
+            [("adj_close".to_string(), "double precision".to_string())]
+        );
+    }
+
+    #[test]
+    fn stddev_is_the_sample_deviation() {
+        let values = [2.0, 4.0, 4.0, 4.0, 5.0, 5.0, 7.0, 9.0];
+        assert!((stddev(&values) - (32.0f64 / 7.0).sqrt()).abs() < 1e-12);
+        assert_eq!(stddev(&[1.5, 1.5, 1.5]), 0.0);
+    }
+
+    /// A batch of `closes`, with an ATR of 2 on every row but the first.
+    fn priced(closes: &[f64]) -> Batch {
+        let records = closes
+            .iter()
+            .enumerate()
+            .map(|(i, c)| {
+                let atr = if i == 0 {
+                    String::new()
+                } else {
+                    "2".to_string()
+                };
+                csv::ByteRecord::from(vec![format!("2024-01-{:02}", i + 1), c.to_string(), atr])
+            })
+            .collect();
+        Batch {
+            columns: ["date", "close", "ATR"].map(String::from).to_vec(),
+            records,
+            ..batch(&[])
+        }
+    }
+
+    fn value(record: &csv::ByteRecord, i: usize) -> Option<f64> {
+        let field = std::str::from_utf8(&record[i]).unwrap();
+        (!field.is_empty()).then(|| field.parse().unwrap())
+    }
+
+    #[test]
+    fn volatility_fills_once_each_window_does() {
+        let closes: Vec<f64> = (0..32).map(|i| 100.0 + f64::from(i * i % 7)).collect();
+        let returns: Vec<f64> = closes.windows(2).map(|w| w[1] / w[0] - 1.0).collect();
+        let mut batches = [priced(&closes)];
+        add_volatility(&mut batches, &compute_pool(Some(1)).unwrap());
+        let [batch] = &batches;
+
+        assert_eq!(
+            batch.columns[3..],
+            [
+                "volatility10",
+                "volatility20",
+                "volatility30",
+                "atr_upper",
+                "atr_lower"
+            ]
+        );
+        assert_eq!(batch.extra_columns.len(), 5);
+        for (row, record) in batch.records.iter().enumerate() {
+            for (column, n) in VOLATILITY_WINDOWS.into_iter().enumerate() {
+                // Row `n` is the first with `n` returns behind it.
+                let expected = row.checked_sub(n).map(|start| stddev(&returns[start..row]));
+                let got = value(record, 3 + column);
+                match (got, expected) {
+                    (Some(got), Some(expected)) => assert!((got - expected).abs() < 1e-12),
+                    (None, None) => {}
+                    _ => panic!("row {row} volatility{n}: {got:?} != {expected:?}"),
+                }
+            }
+        }
+        assert_eq!(value(&batch.records[9], 3), None);
+        assert!(value(&batch.records[10], 3).is_some());
+        assert_eq!(value(&batch.records[29], 5), None);
+        assert!(value(&batch.records[30], 5).is_some());
+    }
+
+    #[test]
+    fn atr_bands_surround_the_close() {
+        let mut batches = [priced(&[100.0, 103.0])];
+        add_volatility(&mut batches, &compute_pool(Some(1)).unwrap());
+        let [batch] = &batches;
+        // No ATR on the first row, so no bands.
+        assert_eq!(value(&batch.records[0], 6), None);
+        assert_eq!(value(&batch.records[0], 7), None);
+        assert_eq!(
+            value(&batch.records[1], 6),
+            Some(103.0 + ATR_BAND_WIDTH * 2.0)
+        );
+        assert_eq!(
+            value(&batch.records[1], 7),
+            Some(103.0 - ATR_BAND_WIDTH * 2.0)
+        );
+    }
+
+    #[test]
+    fn lane_sum_covers_the_remainder() {
+        for n in 0..10 {
+            let values: Vec<f64> = (1..=n).map(f64::from).collect();
+            assert_eq!(lane_sum(&values, |v| v), f64::from(n * (n + 1) / 2));
+        }
+    }
+}
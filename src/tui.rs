@@ -0,0 +1,243 @@
+use anyhow::Result;
+use crossterm::event::{self, Event, KeyCode, KeyModifiers};
+use crossterm::execute;
+use crossterm::terminal::{
+    disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen,
+};
+use ratatui::backend::CrosstermBackend;
+use ratatui::layout::{Constraint, Direction, Layout};
+use ratatui::widgets::{Block, Borders, List, ListItem, Paragraph};
+use ratatui::{Frame, Terminal};
+use std::collections::VecDeque;
+use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use crate::progress::Reporter;
+use crate::report::Status;
+
+/// Lines kept in the scrolling log pane.
+const LOG_LINES: usize = 500;
+const REFRESH: Duration = Duration::from_millis(200);
+
+/// The file the load is on, or last handled.
+#[derive(Default)]
+struct Current {
+    file: Option<String>,
+    status: &'static str,
+    rows: u64,
+}
+
+struct State {
+    started: Instant,
+    current: Current,
+    files_done: u64,
+    rows: u64,
+    errors: u64,
+    log: VecDeque<String>,
+    done: bool,
+}
+
+/// Terminal dashboard showing the current file, throughput, error counts
+/// and a scrolling log. Rendering happens on its own thread; dropping the
+/// dashboard restores the terminal.
+pub struct Dashboard {
+    state: Arc<Mutex<State>>,
+    stop: Arc<AtomicBool>,
+    render: Option<JoinHandle<()>>,
+}
+
+impl Dashboard {
+    pub fn start() -> Result<Self> {
+        let state = Arc::new(Mutex::new(State {
+            started: Instant::now(),
+            current: Current::default(),
+            files_done: 0,
+            rows: 0,
+            errors: 0,
+            log: VecDeque::new(),
+            done: false,
+        }));
+        let stop = Arc::new(AtomicBool::new(false));
+
+        enable_raw_mode()?;
+        execute!(io::stdout(), EnterAlternateScreen)?;
+        let mut terminal = Terminal::new(CrosstermBackend::new(io::stdout()))?;
+
+        let render = {
+            let state = state.clone();
+            let stop = stop.clone();
+            thread::spawn(move || {
+                while !stop.load(Ordering::Relaxed) {
+                    let _ = terminal.draw(|f| draw(f, &state.lock().unwrap()));
+                    // Raw mode swallows Ctrl-C, so quitting is handled here.
+                    if let Ok(true) = event::poll(REFRESH) {
+                        if let Ok(Event::Key(key)) = event::read() {
+                            let ctrl_c = key.code == KeyCode::Char('c')
+                                && key.modifiers.contains(KeyModifiers::CONTROL);
+                            let quit = ctrl_c || key.code == KeyCode::Char('q');
+                            if quit && state.lock().unwrap().done && !ctrl_c {
+                                break;
+                            }
+                            if quit {
+                                restore_terminal();
+                                std::process::exit(130);
+                            }
+                        }
+                    }
+                }
+            })
+        };
+
+        Ok(Dashboard {
+            state,
+            stop,
+            render: Some(render),
+        })
+    }
+
+    fn update(&self, f: impl FnOnce(&mut State)) {
+        f(&mut self.state.lock().unwrap());
+    }
+}
+
+impl Drop for Dashboard {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(render) = self.render.take() {
+            let _ = render.join();
+        }
+        restore_terminal();
+    }
+}
+
+impl Reporter for Dashboard {
+    fn log(&mut self, line: &str) {
+        self.update(|s| {
+            s.log.push_back(line.to_string());
+            if s.log.len() > LOG_LINES {
+                s.log.pop_front();
+            }
+        });
+    }
+
+    fn file_started(&mut self, file: &str) {
+        self.update(|s| {
+            s.current = Current {
+                file: Some(file.to_string()),
+                status: "loading",
+                rows: 0,
+            }
+        });
+    }
+
+    fn rows_committed(&mut self, _table: &str, rows: u64) {
+        self.update(|s| {
+            s.rows += rows;
+            s.current.rows += rows;
+        });
+    }
+
+    fn file_failed(&mut self, file: &str, error: &str) {
+        self.update(|s| s.errors += 1);
+        self.log(&format!("ERROR: {file}: {error}"));
+    }
+
+    fn file_finished(&mut self, file: &str, status: Status) {
+        self.update(|s| {
+            s.files_done += 1;
+            // Unchanged files are never started.
+            if s.current.file.as_deref() != Some(file) {
+                s.current = Current {
+                    file: Some(file.to_string()),
+                    ..Current::default()
+                };
+            }
+            s.current.status = match status {
+                Status::Loaded => "loaded",
+                Status::Unchanged => "unchanged",
+                Status::Skipped => "skipped",
+                Status::Invalid => "invalid",
+                Status::Failed => "failed",
+            };
+        });
+    }
+
+    fn finished(&mut self, error: Option<&str>) {
+        self.update(|s| {
+            if error.is_some() {
+                s.current.status = "failed";
+            }
+            s.done = true;
+        });
+        match error {
+            Some(error) => self.log(&format!("ERROR: {error}, press q to exit")),
+            None => self.log("Finished, press q to exit"),
+        }
+        // Keep the final state on screen until the user leaves.
+        while let Some(render) = &self.render {
+            if render.is_finished() {
+                break;
+            }
+            thread::sleep(REFRESH);
+        }
+    }
+}
+
+fn draw(f: &mut Frame, state: &State) {
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Length(3),
+            Constraint::Length(3),
+            Constraint::Min(3),
+        ])
+        .split(f.size());
+
+    let elapsed = state.started.elapsed().as_secs_f64();
+    let summary = format!(
+        "files: {}   rows: {}   rows/sec: {:.0}   errors: {}   elapsed: {:.0}s",
+        state.files_done,
+        state.rows,
+        state.rows as f64 / elapsed.max(1e-3),
+        state.errors,
+        elapsed,
+    );
+    f.render_widget(
+        Paragraph::new(summary).block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title("pg_nifty_dump"),
+        ),
+        chunks[0],
+    );
+
+    let current = &state.current;
+    let line = match &current.file {
+        Some(file) => format!("{file}   {}   rows: {}", current.status, current.rows),
+        None => "waiting for files".to_string(),
+    };
+    f.render_widget(
+        Paragraph::new(line).block(Block::default().borders(Borders::ALL).title("file")),
+        chunks[1],
+    );
+
+    let height = chunks[2].height.saturating_sub(2) as usize;
+    let lines: Vec<ListItem> = state
+        .log
+        .iter()
+        .skip(state.log.len().saturating_sub(height))
+        .map(|l| ListItem::new(l.as_str()))
+        .collect();
+    f.render_widget(
+        List::new(lines).block(Block::default().borders(Borders::ALL).title("log")),
+        chunks[2],
+    );
+}
+
+fn restore_terminal() {
+    let _ = disable_raw_mode();
+    let _ = execute!(io::stdout(), LeaveAlternateScreen);
+}
@@ -0,0 +1,115 @@
+use anyhow::{bail, Context, Result};
+use async_trait::async_trait;
+use russh::client::{self, Handle};
+use russh_keys::key::PublicKey;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncWrite};
+
+const STARTUP_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// Keys tried in `~/.ssh` when no --ssh-identity is given.
+const DEFAULT_IDENTITIES: [&str; 3] = ["id_ed25519", "id_ecdsa", "id_rsa"];
+
+/// SSH session with a jump host, through which connections to a host behind
+/// it are forwarded. The session closes when the value is dropped.
+///
+/// The jump host's key must be in `~/.ssh/known_hosts`.
+pub struct Tunnel {
+    session: Handle<KnownHosts>,
+    host: String,
+    port: u16,
+}
+
+impl Tunnel {
+    pub async fn open(
+        bastion: &str,
+        ssh_port: Option<u16>,
+        identity: Option<&str>,
+        host: &str,
+        port: u16,
+    ) -> Result<Tunnel> {
+        let (user, bastion) = match bastion.split_once('@') {
+            Some((user, bastion)) => (user.to_string(), bastion),
+            None => (
+                std::env::var("USER").context("--ssh-tunnel needs a user, e.g. user@bastion")?,
+                bastion,
+            ),
+        };
+        let ssh_port = ssh_port.unwrap_or(22);
+        let key = load_identity(identity)?;
+
+        let handler = KnownHosts {
+            host: bastion.to_string(),
+            port: ssh_port,
+        };
+        let connect = client::connect(
+            Arc::new(client::Config::default()),
+            (bastion, ssh_port),
+            handler,
+        );
+        let mut session = tokio::time::timeout(STARTUP_TIMEOUT, connect)
+            .await
+            .with_context(|| format!("timed out opening ssh tunnel to {bastion}"))?
+            .with_context(|| format!("connecting to ssh jump host {bastion}"))?;
+        if !session
+            .authenticate_publickey(user.as_str(), Arc::new(key))
+            .await
+            .with_context(|| format!("authenticating with {bastion}"))?
+        {
+            bail!("ssh jump host {bastion} rejected the key of {user}");
+        }
+
+        Ok(Tunnel {
+            session,
+            host: host.to_string(),
+            port,
+        })
+    }
+
+    /// Opens a forwarded connection to the host behind the jump host.
+    pub async fn connect(&self) -> Result<impl AsyncRead + AsyncWrite + Unpin + Send + 'static> {
+        let channel = self
+            .session
+            .channel_open_direct_tcpip(self.host.as_str(), self.port.into(), "127.0.0.1", 0)
+            .await
+            .with_context(|| format!("forwarding to {}:{}", self.host, self.port))?;
+        Ok(channel.into_stream())
+    }
+}
+
+/// Accepts the jump host only under the key `~/.ssh/known_hosts` has for it.
+struct KnownHosts {
+    host: String,
+    port: u16,
+}
+
+#[async_trait]
+impl client::Handler for KnownHosts {
+    type Error = russh::Error;
+
+    async fn check_server_key(&mut self, key: &PublicKey) -> Result<bool, Self::Error> {
+        Ok(russh_keys::check_known_hosts(&self.host, self.port, key)?)
+    }
+}
+
+fn load_identity(identity: Option<&str>) -> Result<russh_keys::key::KeyPair> {
+    let path = match identity {
+        Some(path) => PathBuf::from(path),
+        None => {
+            let home = std::env::var("HOME").context("HOME is not set")?;
+            let dir = PathBuf::from(home).join(".ssh");
+            match DEFAULT_IDENTITIES
+                .iter()
+                .map(|name| dir.join(name))
+                .find(|path| path.exists())
+            {
+                Some(path) => path,
+                None => bail!("no ssh key in {}; pass --ssh-identity", dir.display()),
+            }
+        }
+    };
+    russh_keys::load_secret_key(&path, None)
+        .with_context(|| format!("loading ssh key: {}", path.display()))
+}
@@ -0,0 +1,70 @@
+use anyhow::{bail, Context, Result};
+use std::collections::HashSet;
+use std::fs;
+
+use crate::schema;
+
+static NIFTY50: &str = include_str!("static/nifty50.txt");
+
+/// Symbols a load is restricted to. Symbols are compared as table names, so
+/// `M&M` matches a file named `M_M.csv`.
+pub struct Universe {
+    symbols: HashSet<String>,
+}
+
+impl Universe {
+    /// `nifty50` for the bundled list, or a file with one symbol per line
+    /// (`#` starts a comment). A CSV with a `Symbol` column, such as the
+    /// constituents lists NSE publishes for each index, works too.
+    ///
+    /// Broader indices such as the Nifty 500 are not bundled: their
+    /// constituents change at every semi-annual rebalance, so a list shipped
+    /// with a release goes stale. Their NSE CSVs are read instead.
+    pub fn load(spec: &str) -> Result<Self> {
+        let contents = match spec {
+            "nifty50" => NIFTY50.to_string(),
+            "nifty500" => bail!(
+                "nifty500 is not bundled, as its constituents change at every rebalance; \
+                 download ind_nifty500list.csv from NSE and pass its path"
+            ),
+            _ if spec.starts_with("nifty") && !spec.contains('.') => {
+                bail!(
+                    "no bundled list for {spec}; pass the index constituents CSV from NSE instead"
+                )
+            }
+            path => {
+                fs::read_to_string(path).with_context(|| format!("reading universe: {path}"))?
+            }
+        };
+
+        let mut lines = contents
+            .lines()
+            .map(str::trim)
+            .filter(|l| !l.is_empty() && !l.starts_with('#'))
+            .peekable();
+        let column = lines.peek().and_then(|header| {
+            header
+                .split(',')
+                .position(|h| h.trim().eq_ignore_ascii_case("symbol"))
+        });
+        if column.is_some() {
+            lines.next();
+        }
+        let symbols: HashSet<String> = lines
+            .filter_map(|l| l.split(',').nth(column.unwrap_or(0)))
+            .map(|s| schema::sanitise(s.trim().to_string()))
+            .collect();
+        if symbols.is_empty() {
+            bail!("universe {spec} lists no symbols");
+        }
+        Ok(Universe { symbols })
+    }
+
+    pub fn contains(&self, symbol: &str) -> bool {
+        self.symbols.contains(&schema::sanitise(symbol.to_string()))
+    }
+
+    pub fn len(&self) -> usize {
+        self.symbols.len()
+    }
+}